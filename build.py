@@ -273,6 +273,16 @@ def run_tests():
     command(args)
 
 
+def regenerate_golden_audio():
+    set_env('AUDIOBENCH_REGENERATE_GOLDEN_AUDIO', '1')
+    args = ['cargo', 'test', '-p', 'audiobench', '--test', 'golden_audio', '--test', 'native_executor']
+    if DO_RELEASE:
+        args.append('--release')
+    args.append('--')
+    args.append('--test-threads=1')
+    command(args)
+
+
 def run_benchmark():
     args = ['cargo', 'run', '-p', 'benchmark']
     if DO_RELEASE:
@@ -458,6 +468,7 @@ JOBS = {
     'installer': Job('Build a publishable installer', ['juce_frontend'], build_installer),
     'run': Job('Run the standalone version of Audiobench', ['juce_frontend'], run_standalone),
     'test': Job('Test all Rust components in the project', ['deps'], run_tests),
+    'regenerate_golden_audio': Job('Rerecord the reference audio used by the golden audio and native executor tests', ['deps'], regenerate_golden_audio),
     'benchmark': Job('Run a benchmarking suite', ['deps'], run_benchmark),
     'check_version': Job('Ensures version numbers have been incremented', [], check_version),
 }
@@ -0,0 +1,247 @@
+//! Recorded audio used by Sample controls. Files are decoded once when they are loaded and turned
+//! into mono audio, which is resampled to whatever rate the note graph runs at when it is needed.
+
+use std::{
+    fmt::{self, Debug, Formatter},
+    io::Cursor,
+};
+
+/// Every file extension that can be decoded, without the dot.
+pub const SUPPORTED_EXTENSIONS: [&str; 4] = ["wav", "flac", "aif", "aiff"];
+/// How many input samples on either side of the output sample the resampler looks at.
+const RESAMPLER_RADIUS: usize = 16;
+
+#[derive(Clone, PartialEq)]
+pub struct AudioSample {
+    pub sample_rate: usize,
+    /// Mono audio between -1 and 1. Files with multiple channels are mixed down when decoded.
+    pub data: Vec<f32>,
+}
+
+// Printing every sample would make anything containing a sample unreadable.
+impl Debug for AudioSample {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AudioSample")
+            .field("sample_rate", &self.sample_rate)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+pub fn is_supported_file(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SUPPORTED_EXTENSIONS
+        .iter()
+        .any(|extension| name.ends_with(&format!(".{}", extension)))
+}
+
+/// Averages the channels of interleaved audio together.
+fn mix_down(interleaved: Vec<f32>, channels: usize) -> Vec<f32> {
+    if channels == 1 {
+        return interleaved;
+    }
+    interleaved
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+fn decode_wav(buffer: &[u8]) -> Result<AudioSample, String> {
+    let mut reader = hound::WavReader::new(Cursor::new(buffer)).map_err(|e| e.to_string())?;
+    let spec = reader.spec();
+    let interleaved = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .samples::<f32>()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / scale))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| e.to_string())?
+        }
+    };
+    Ok(AudioSample {
+        sample_rate: spec.sample_rate as usize,
+        data: mix_down(interleaved, spec.channels as usize),
+    })
+}
+
+fn decode_flac(buffer: &[u8]) -> Result<AudioSample, String> {
+    let mut reader = claxon::FlacReader::new(Cursor::new(buffer)).map_err(|e| e.to_string())?;
+    let info = reader.streaminfo();
+    let scale = (1i64 << (info.bits_per_sample - 1)) as f32;
+    let interleaved = reader
+        .samples()
+        .map(|sample| sample.map(|sample| sample as f32 / scale))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(AudioSample {
+        sample_rate: info.sample_rate as usize,
+        data: mix_down(interleaved, info.channels as usize),
+    })
+}
+
+/// Converts the 80 bit extended precision float AIFF files use to store their sample rate.
+fn aiff_extended_to_f64(bytes: &[u8]) -> f64 {
+    let exponent = (((bytes[0] as i32) & 0x7F) << 8) | bytes[1] as i32;
+    let mut mantissa = 0u64;
+    for byte in &bytes[2..10] {
+        mantissa = (mantissa << 8) | *byte as u64;
+    }
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    let value = mantissa as f64 * 2f64.powi(exponent - 16383 - 63);
+    if bytes[0] & 0x80 != 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// AIFF is simple enough that it is not worth another dependency. Only uncompressed PCM is
+/// supported, which is what AIFF (as opposed to AIFF-C) always contains.
+fn decode_aiff(buffer: &[u8]) -> Result<AudioSample, String> {
+    let corrupt = || "The file is not a valid AIFF file.".to_owned();
+    if buffer.len() < 12 || &buffer[0..4] != b"FORM" || &buffer[8..12] != b"AIFF" {
+        return Err(corrupt());
+    }
+    let mut format = None;
+    let mut sound_data = None;
+    let mut position = 12;
+    while position + 8 <= buffer.len() {
+        let id = &buffer[position..position + 4];
+        let mut size_bytes = [0; 4];
+        size_bytes.copy_from_slice(&buffer[position + 4..position + 8]);
+        let size = u32::from_be_bytes(size_bytes) as usize;
+        let start = position + 8;
+        let end = start.checked_add(size).ok_or_else(corrupt)?;
+        if end > buffer.len() {
+            return Err(corrupt());
+        }
+        let chunk = &buffer[start..end];
+        if id == b"COMM" {
+            if chunk.len() < 18 {
+                return Err(corrupt());
+            }
+            let channels = u16::from_be_bytes([chunk[0], chunk[1]]) as usize;
+            let bits = u16::from_be_bytes([chunk[6], chunk[7]]) as usize;
+            let sample_rate = aiff_extended_to_f64(&chunk[8..18]).round() as usize;
+            format = Some((channels, bits, sample_rate));
+        } else if id == b"SSND" {
+            if chunk.len() < 8 {
+                return Err(corrupt());
+            }
+            let offset = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize;
+            sound_data = Some(chunk.get(8 + offset..).ok_or_else(corrupt)?);
+        }
+        // Chunks are padded to an even number of bytes.
+        position = end + size % 2;
+    }
+    let (channels, bits, sample_rate) = format.ok_or_else(corrupt)?;
+    let sound_data = sound_data.ok_or_else(corrupt)?;
+    if channels == 0 || sample_rate == 0 || bits == 0 || bits > 32 {
+        return Err(corrupt());
+    }
+    // Samples are padded to a whole number of bytes.
+    let bytes_per_sample = match bits {
+        1..=8 => 1,
+        9..=16 => 2,
+        17..=24 => 3,
+        _ => 4,
+    };
+    let scale = (1i64 << (bytes_per_sample * 8 - 1)) as f32;
+    let interleaved = sound_data
+        .chunks_exact(bytes_per_sample)
+        .map(|bytes| {
+            // Shift the sample into the top of an i32 so that the sign is extended.
+            let mut value = 0i32;
+            for (index, byte) in bytes.iter().enumerate() {
+                value |= (*byte as i32) << (24 - index * 8);
+            }
+            (value >> (32 - bytes_per_sample * 8)) as f32 / scale
+        })
+        .collect();
+    Ok(AudioSample {
+        sample_rate,
+        data: mix_down(interleaved, channels),
+    })
+}
+
+impl AudioSample {
+    /// Decodes a WAV, FLAC, or AIFF file, the format is determined from the name of the file.
+    pub fn decode(file_name: &str, buffer: &[u8]) -> Result<Self, String> {
+        let lower_name = file_name.to_lowercase();
+        let result = if lower_name.ends_with(".wav") {
+            decode_wav(buffer)
+        } else if lower_name.ends_with(".flac") {
+            decode_flac(buffer)
+        } else if lower_name.ends_with(".aif") || lower_name.ends_with(".aiff") {
+            decode_aiff(buffer)
+        } else {
+            Err(format!(
+                "Only {} files are supported.",
+                SUPPORTED_EXTENSIONS.join(", ")
+            ))
+        };
+        let sample = result.map_err(|err| {
+            format!(
+                "ERROR: Failed to decode the audio file {}, caused by:\nERROR: {}",
+                file_name, err
+            )
+        })?;
+        if sample.sample_rate == 0 {
+            return Err(format!(
+                "ERROR: The audio file {} has a sample rate of zero.",
+                file_name
+            ));
+        }
+        Ok(sample)
+    }
+
+    /// How long the sample is in seconds.
+    pub fn get_duration(&self) -> f32 {
+        self.data.len() as f32 / self.sample_rate as f32
+    }
+
+    /// Returns the audio converted to the specified sample rate with a windowed sinc filter, which
+    /// also removes anything above the new Nyquist frequency when the rate goes down.
+    pub fn resample(&self, sample_rate: usize) -> Vec<f32> {
+        use std::f32::consts::PI;
+        if sample_rate == self.sample_rate || self.data.is_empty() {
+            return self.data.clone();
+        }
+        let ratio = self.sample_rate as f64 / sample_rate as f64;
+        // In cycles per input sample.
+        let cutoff = (0.5 / ratio).min(0.5) as f32;
+        let radius = (RESAMPLER_RADIUS as f32 * 0.5 / cutoff).ceil() as isize;
+        let len = (self.data.len() as f64 / ratio).round() as usize;
+        (0..len)
+            .map(|index| {
+                let position = index as f64 * ratio;
+                let center = position.floor() as isize;
+                let fraction = (position - center as f64) as f32;
+                let mut sum = 0.0;
+                for offset in -radius + 1..=radius {
+                    let input_index = center + offset;
+                    if input_index < 0 || input_index as usize >= self.data.len() {
+                        continue;
+                    }
+                    let x = offset as f32 - fraction;
+                    let sinc = if x == 0.0 {
+                        2.0 * cutoff
+                    } else {
+                        (2.0 * PI * cutoff * x).sin() / (PI * x)
+                    };
+                    // Hann window spanning the whole radius.
+                    let window = 0.5 + 0.5 * (PI * x / radius as f32).cos();
+                    sum += self.data[input_index as usize] * sinc * window;
+                }
+                sum
+            })
+            .collect()
+    }
+}
@@ -1,22 +1,28 @@
 use crate::{
     engine::{
-        codegen::{self, CodeGenResult},
+        codegen::{self, CodeGenOptions, CodeGenResult, NoteGraphCode},
         data_transfer::IOData,
         data_transfer::{
             DynDataCollector, FeedbackData, FeedbackDisplayer, GlobalData, GlobalParameters,
         },
+        image_cache::ImageCache,
         julia_thread,
+        master_safety::MasterSafetySettings,
+        note_processor::NoteProcessorSettings,
+        oversampling,
         parts::{Module, ModuleGraph},
+        program_wrapper::{FeedbackVoice, NonFiniteVoice},
     },
     registry::{save_data::Patch, Registry},
 };
-use crossbeam_channel::{Receiver, Sender, TrySendError};
-use crossbeam_utils::atomic::AtomicCell;
-use julia_helper::GeneratedCode;
+use crossbeam_channel::{Receiver, Sender};
+use crossbeam_queue::ArrayQueue;
+use crossbeam_utils::{atomic::AtomicCell, Backoff};
 use observatory::{observable, ObservablePtr};
-use shared_util::prelude::*;
+use shared_util::{alloc_tracker, prelude::*};
 use std::{
-    sync::Mutex,
+    cmp::Ordering,
+    thread::Thread,
     time::{Duration, Instant},
 };
 
@@ -24,6 +30,33 @@ const DEFAULT_CHANNELS: usize = 2;
 const DEFAULT_BUFFER_LENGTH: usize = 512;
 const DEFAULT_SAMPLE_RATE: usize = 44100;
 const FEEDBACK_UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+const DEFAULT_CROSSFADE_TIME: f32 = 0.25;
+/// Both options slow down every module a little, so they are only turned on when asked for.
+const DEFAULT_CODEGEN_OPTIONS: CodeGenOptions = CodeGenOptions {
+    profile: false,
+    check_non_finite: false,
+};
+/// Voices which produce NaN or infinite samples are reported at most this often, since a broken
+/// patch can easily produce one every buffer.
+const NON_FINITE_WARNING_INTERVAL: Duration = Duration::from_secs(2);
+/// How many note events can pile up while the Julia thread is busy. Events past this point are
+/// dropped, since the audio thread is not allowed to allocate more space for them.
+pub(super) const NOTE_EVENT_QUEUE_CAPACITY: usize = 1024;
+/// The audio thread never waits for the Julia thread, so the audio it plays was requested during
+/// an earlier buffer. This is how many buffers ahead of the host it can be with lookahead enabled,
+/// without lookahead it is one buffer ahead.
+const MAX_RENDER_AHEAD: usize = 2;
+/// Rendered audio waiting to be collected by the audio thread. It collects everything that is
+/// waiting every buffer, so this only has to hold the requests that can be in flight at once.
+const RENDERED_AUDIO_CAPACITY: usize = MAX_RENDER_AHEAD + 1;
+/// Every buffer that is not in the free queue is either waiting to be collected, held by the audio
+/// thread because it arrived early, or being rendered into. When the Julia thread takes a buffer it
+/// is not holding one, so there is always at least one left. Since the free queue can hold every
+/// buffer in existence, the audio thread never has to free one because the queue is full.
+const AUDIO_BUFFER_POOL_SIZE: usize = RENDERED_AUDIO_CAPACITY + 2;
+/// How many frames of the master output can wait for the GUI to analyze them. Frames are dropped
+/// while it is full, e.g. when the GUI is closed.
+const MASTER_TAP_CAPACITY: usize = 8192;
 
 type PreferredPerfCounter = shared_util::perf_counter::SimplePerfCounter;
 
@@ -31,33 +64,102 @@ struct UiThreadData {
     registry: Rcrc<Registry>,
     module_graph: Rcrc<ModuleGraph>,
     dyn_data_collector: DynDataCollector,
+    /// The sample rate the last dyn data was collected for, samples have to be resampled again
+    /// when the host or the oversampling factor changes it.
+    dyn_data_sample_rate: usize,
     feedback_displayer: FeedbackDisplayer,
     current_patch_save_data: ObservablePtr<Rcrc<Patch>>,
+    note_processor_settings: NoteProcessorSettings,
+    master_safety_settings: MasterSafetySettings,
     posted_errors: Vec<String>,
     julia_errors: Receiver<String>,
+    compile_log: Receiver<String>,
+    non_finite_reports: Receiver<NonFiniteVoice>,
+    /// Reports which have not been turned into a warning yet.
+    pending_non_finite_voices: Vec<NonFiniteVoice>,
+    last_non_finite_warning: Option<Instant>,
+    julia_thread: Thread,
+    /// Incremented every time code is generated, see codegen::generated_module_name.
+    code_generation: usize,
+    codegen_options: CodeGenOptions,
+    /// The most recent result of profiling, see FeedbackData::module_cpu_usage.
+    module_cpu_usage: Vec<f32>,
+    /// The module most recently hovered in the module graph, its outputs are always watched.
+    hovered_module: Option<Rcrc<Module>>,
+    feedback_voice: FeedbackVoice,
+    /// When true, the next note played on the virtual keyboard becomes the feedback voice.
+    picking_feedback_voice: bool,
+    /// The MIDI note the most recent feedback data was collected from, None for the dummy note.
+    displayed_feedback_voice: Option<usize>,
 }
 
 pub(super) struct Communication {
     pub julia_thread_status: AtomicCell<julia_thread::Status>,
 
     pub new_global_params: AtomicCell<Option<()>>,
-    pub new_note_graph_code: AtomicCell<Option<(GeneratedCode, Vec<IOData>)>>,
+    /// Code, its dyn data, and which generation of code it is.
+    pub new_note_graph_code: AtomicCell<Option<(NoteGraphCode, Vec<IOData>, usize)>>,
     pub new_dyn_data: AtomicCell<Option<Vec<IOData>>>,
     pub new_feedback: AtomicCell<Option<FeedbackData>>,
+    pub new_note_processor_settings: AtomicCell<Option<NoteProcessorSettings>>,
+    pub new_master_safety_settings: AtomicCell<Option<MasterSafetySettings>>,
+    /// The settings last sent through new_master_safety_settings, so that the audio thread can
+    /// report the latency they add.
+    pub master_safety_settings: AtomicCell<MasterSafetySettings>,
+    /// How many dB the master safety stage's limiter turned the last buffer down by.
+    pub master_gain_reduction: AtomicCell<f32>,
     pub do_dummy_note: AtomicCell<bool>,
     pub do_dummy_note_once: AtomicCell<bool>,
-    pub module_view_index: AtomicCell<usize>,
+    /// Indexes of the modules whose outputs should be sent back to be shown in output views.
+    pub new_watched_modules: AtomicCell<Option<Vec<usize>>>,
+    pub new_feedback_voice: AtomicCell<Option<FeedbackVoice>>,
+    /// When enabled, audio is rendered one buffer ahead of when it is played.
+    pub lookahead: AtomicCell<bool>,
+    /// How many times the Julia thread didn't finish a buffer in time.
+    pub underrun_count: AtomicCell<usize>,
+    /// How many seconds notes playing on old code take to fade out after the code changes.
+    pub crossfade_time: AtomicCell<f32>,
+    /// None when the Julia thread is not compiling anything.
+    pub compile_progress: AtomicCell<Option<julia_thread::CompileProgress>>,
+    /// The generation of the code new notes are currently being started with.
+    pub loaded_generation: AtomicCell<usize>,
 
     pub global_params: AtomicCell<GlobalParameters>,
-    pub note_events: Mutex<Vec<julia_thread::NoteEvent>>,
-    pub julia_render_pipe: Sender<julia_thread::RenderRequest>,
+    /// How many times faster than the host's sample rate the note graph runs. Changing it is
+    /// signalled through new_global_params.
+    pub oversampling: AtomicCell<usize>,
     pub julia_poll_pipe: Sender<()>,
+
+    // Everything the audio thread touches while rendering is a preallocated lock-free queue so
+    // that it never has to wait on the Julia thread or the UI thread.
+    pub note_events: ArrayQueue<julia_thread::NoteEvent>,
+    pub render_requests: ArrayQueue<julia_thread::RenderRequest>,
+    pub rendered_audio: ArrayQueue<julia_thread::AudioResponse>,
+    pub free_audio_buffers: ArrayQueue<Vec<f32>>,
+    /// Left and right channels of every frame the audio thread outputs, for the analyzers in the
+    /// GUI. Only filled while gui_open is true, since nothing else drains it.
+    pub master_tap: ArrayQueue<(f32, f32)>,
+    pub gui_open: AtomicCell<bool>,
 }
 
 struct AudioThreadData {
     global_data: GlobalData,
+    // Copy of comms.global_params, the audio thread is the only one that changes them.
+    global_params: GlobalParameters,
     last_feedback_data_update: Instant,
-    audio_response_output: Receiver<julia_thread::AudioResponse>,
+    next_request_id: u64,
+    /// The oldest request whose audio has not been played or given up on yet. Every request from
+    /// here up to next_request_id is still in flight.
+    next_request_to_play: u64,
+    /// Audio that was rendered before it was needed, which happens when more than one request is
+    /// in flight.
+    early_audio: Option<julia_thread::AudioResponse>,
+    /// The last sample of each channel from the previous buffer, used to fade out smoothly when the
+    /// Julia thread can't keep up.
+    last_frame: Vec<f32>,
+    /// True if the last buffer had to be faded out, so the next one should be faded in.
+    faded_out: bool,
+    julia_thread: Thread,
 }
 
 pub struct UiThreadEngine {
@@ -100,35 +202,30 @@ pub fn new_engine(
         dyn_data_collector,
         feedback_displayer,
         ..
-    } = codegen::generate_code(&module_graph, &global_params).map_err(|_| {
-        format!(concat!(
-            "Default patch contains feedback loops!\n",
-            "This is a critical error, please submit a bug report containing this ",
-            "error.",
-        ),)
-    })?;
-    let dyn_data = dyn_data_collector.collect();
-
-    let (renderi, rendero) = crossbeam_channel::bounded(0);
+    } = codegen::generate_code(&module_graph, &global_params, 0, &DEFAULT_CODEGEN_OPTIONS)
+        .map_err(|_| {
+            format!(concat!(
+                "Default patch contains feedback loops!\n",
+                "This is a critical error, please submit a bug report containing this ",
+                "error.",
+            ),)
+        })?;
+    let note_processor_settings = default_patch.borrow().get_note_processor_settings();
+    let master_safety_settings = default_patch.borrow().get_master_safety_settings();
+    let oversampling = default_patch.borrow().get_oversampling();
+    let dyn_data_sample_rate = global_params.sample_rate * oversampling;
+    let dyn_data = dyn_data_collector.collect(dyn_data_sample_rate);
+
     let (polli, pollo) = crossbeam_channel::bounded(0xFF);
-    let (audio_resi, audio_reso) = crossbeam_channel::bounded(0);
     let (jerrori, jerroro) = crossbeam_channel::unbounded();
+    let (clogi, clogo) = crossbeam_channel::unbounded();
+    let (nfi, nfo) = crossbeam_channel::unbounded();
 
-    let utd = UiThreadData {
-        registry: Rc::clone(&registry_ptr),
-        module_graph: rcrc(module_graph),
-        dyn_data_collector,
-        feedback_displayer,
-        current_patch_save_data: observable(default_patch),
-        posted_errors: Vec::new(),
-        julia_errors: jerroro,
-    };
-
-    let atd = AudioThreadData {
-        global_data: GlobalData::new(),
-        last_feedback_data_update: Instant::now(),
-        audio_response_output: audio_reso,
-    };
+    let free_audio_buffers = ArrayQueue::new(AUDIO_BUFFER_POOL_SIZE);
+    for _ in 0..AUDIO_BUFFER_POOL_SIZE {
+        let buffer = vec![0.0; global_params.channels * global_params.buffer_length];
+        free_audio_buffers.push(buffer).unwrap();
+    }
 
     let global_params_2 = global_params.clone();
     let comms = Communication {
@@ -138,36 +235,85 @@ pub fn new_engine(
         new_note_graph_code: Default::default(),
         new_dyn_data: Default::default(),
         new_feedback: Default::default(),
+        new_note_processor_settings: AtomicCell::new(Some(note_processor_settings)),
+        new_master_safety_settings: AtomicCell::new(Some(master_safety_settings)),
+        master_safety_settings: AtomicCell::new(master_safety_settings),
+        master_gain_reduction: AtomicCell::new(0.0),
         do_dummy_note: AtomicCell::new(false),
         do_dummy_note_once: AtomicCell::new(false),
-        module_view_index: AtomicCell::new(0),
+        new_watched_modules: Default::default(),
+        new_feedback_voice: Default::default(),
+        lookahead: AtomicCell::new(false),
+        underrun_count: AtomicCell::new(0),
+        crossfade_time: AtomicCell::new(DEFAULT_CROSSFADE_TIME),
+        compile_progress: AtomicCell::new(None),
+        loaded_generation: AtomicCell::new(0),
 
         global_params: AtomicCell::new(global_params),
-        note_events: Default::default(),
-        julia_render_pipe: renderi,
+        oversampling: AtomicCell::new(oversampling),
         julia_poll_pipe: polli,
+
+        note_events: ArrayQueue::new(NOTE_EVENT_QUEUE_CAPACITY),
+        render_requests: ArrayQueue::new(MAX_RENDER_AHEAD),
+        rendered_audio: ArrayQueue::new(RENDERED_AUDIO_CAPACITY),
+        free_audio_buffers,
+        master_tap: ArrayQueue::new(MASTER_TAP_CAPACITY),
+        gui_open: AtomicCell::new(false),
     };
     let comms = Arc::new(comms);
 
     let registry_source = codegen::generate_registry_code(&*registry)?;
-    let comms2 = Arc::clone(&comms);
-    let julia_executor = move || {
-        julia_thread::entry(
-            comms2,
-            global_params_2,
-            registry_source,
-            code,
-            dyn_data,
-            rendero,
-            pollo,
-            audio_resi,
-            jerrori,
-        );
+    let image_cache = ImageCache::new(&*registry, &registry_source, &global_params);
+    let connection = julia_thread::InstanceConnection {
+        comms: Arc::clone(&comms),
+        global_params: global_params_2,
+        registry_source,
+        image_cache,
+        default_patch_code: code,
+        dyn_data,
+        poll_pipe: pollo,
+        error_report_pipe: jerrori,
+        compile_log_pipe: clogi,
+        non_finite_report_pipe: nfi,
+    };
+    let julia_thread = julia_thread::connect(connection)?;
+
+    let utd = UiThreadData {
+        registry: Rc::clone(&registry_ptr),
+        module_graph: rcrc(module_graph),
+        dyn_data_collector,
+        dyn_data_sample_rate,
+        feedback_displayer,
+        current_patch_save_data: observable(default_patch),
+        note_processor_settings,
+        master_safety_settings,
+        posted_errors: Vec::new(),
+        julia_errors: jerroro,
+        compile_log: clogo,
+        non_finite_reports: nfo,
+        pending_non_finite_voices: Vec::new(),
+        last_non_finite_warning: None,
+        julia_thread: julia_thread.clone(),
+        code_generation: 0,
+        codegen_options: DEFAULT_CODEGEN_OPTIONS,
+        module_cpu_usage: Vec::new(),
+        hovered_module: None,
+        feedback_voice: FeedbackVoice::Youngest,
+        picking_feedback_voice: false,
+        displayed_feedback_voice: None,
+    };
+
+    let atd = AudioThreadData {
+        global_data: GlobalData::new(),
+        global_params,
+        last_feedback_data_update: Instant::now(),
+        next_request_id: 0,
+        next_request_to_play: 0,
+        early_audio: None,
+        last_frame: vec![0.0; global_params.channels],
+        faded_out: false,
+        julia_thread,
     };
-    std::thread::Builder::new()
-        .name("julia_executor".to_owned())
-        .spawn(julia_executor)
-        .unwrap();
 
     Ok((
         rcrc(UiThreadEngine {
@@ -210,6 +356,67 @@ impl UiThreadEngine {
         errors
     }
 
+    /// Returns warnings about voices which were muted and reset because they produced NaN or
+    /// infinite samples. Repeated problems are combined into a single warning every so often.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        let data = &mut self.data;
+        data.pending_non_finite_voices
+            .extend(data.non_finite_reports.try_iter());
+        let recently_warned = data
+            .last_non_finite_warning
+            .map(|time| time.elapsed() < NON_FINITE_WARNING_INTERVAL)
+            .unwrap_or(false);
+        if data.pending_non_finite_voices.is_empty() || recently_warned {
+            return Vec::new();
+        }
+        data.last_non_finite_warning = Some(Instant::now());
+        let voices = std::mem::take(&mut data.pending_non_finite_voices);
+        let graph = data.module_graph.borrow();
+        let culprit = voices
+            .iter()
+            .filter(|voice| voice.generation == data.code_generation)
+            .filter_map(|voice| voice.module_index)
+            .filter_map(|index| graph.borrow_modules().get(index).map(|m| (index, m)))
+            .next();
+        let mut warning = if voices.len() == 1 {
+            "WARNING: A voice produced NaN or infinite samples, it was muted and reset.".to_owned()
+        } else {
+            format!(
+                concat!(
+                    "WARNING: Voices produced NaN or infinite samples {} times, they were muted ",
+                    "and reset."
+                ),
+                voices.len()
+            )
+        };
+        if let Some((index, module)) = culprit {
+            let module = module.borrow();
+            warning.push_str(&format!(
+                "\nThe first module to produce one was {} (module #{}).",
+                module.template.borrow().label,
+                index + 1
+            ));
+        } else if !data.codegen_options.check_non_finite {
+            warning.push_str("\nTurn on NaN Check in the Note Processor tab to find the module.");
+        }
+        vec![warning]
+    }
+
+    /// Returns messages describing how long each compile took or why it was stopped.
+    pub fn take_compile_log(&mut self) -> Vec<String> {
+        self.data.compile_log.try_iter().collect()
+    }
+
+    pub fn get_compile_progress(&self) -> Option<julia_thread::CompileProgress> {
+        self.comms.compile_progress.load()
+    }
+
+    /// Returns true once new notes are played with the code from the last call to
+    /// regenerate_code(), I.E. there is no compile queued or in progress.
+    pub fn is_note_graph_up_to_date(&self) -> bool {
+        self.comms.loaded_generation.load() == self.data.code_generation
+    }
+
     pub fn borrow_registry(&self) -> &Rcrc<Registry> {
         &self.data.registry
     }
@@ -225,6 +432,9 @@ impl UiThreadEngine {
         let mut patch_ref = patch_ref_ref.borrow_mut();
         let reg = self.data.registry.borrow();
         patch_ref.save_note_graph(&*self.data.module_graph.borrow(), &*reg);
+        patch_ref.set_note_processor_settings(self.data.note_processor_settings);
+        patch_ref.set_master_safety_settings(self.data.master_safety_settings);
+        patch_ref.set_oversampling(self.comms.oversampling.load());
         patch_ref.write().unwrap();
     }
 
@@ -240,6 +450,9 @@ impl UiThreadEngine {
         // the user explicitly clicking 'save'.
         let mut dummy_patch = Patch::new_dummy(patch_ref.borrow_name().to_owned());
         dummy_patch.save_note_graph(&*self.data.module_graph.borrow(), &*reg);
+        dummy_patch.set_note_processor_settings(self.data.note_processor_settings);
+        dummy_patch.set_master_safety_settings(self.data.master_safety_settings);
+        dummy_patch.set_oversampling(self.comms.oversampling.load());
         dummy_patch.serialize()
     }
 
@@ -267,6 +480,9 @@ impl UiThreadEngine {
         let mut new_patch_ref = new_patch.borrow_mut();
         new_patch_ref.set_name(name);
         new_patch_ref.save_note_graph(&*self.data.module_graph.borrow(), &*reg);
+        new_patch_ref.set_note_processor_settings(self.data.note_processor_settings);
+        new_patch_ref.set_master_safety_settings(self.data.master_safety_settings);
+        new_patch_ref.set_oversampling(self.comms.oversampling.load());
         new_patch_ref.write().unwrap();
         drop(new_patch_ref);
         drop(patch_ref);
@@ -315,9 +531,78 @@ impl UiThreadEngine {
         }
         self.data.module_graph.borrow().rebuild_widget();
         self.regenerate_code();
+        let patch_ref_ref = self.data.current_patch_save_data.borrow_untracked();
+        let patch_ref = patch_ref_ref.borrow();
+        let settings = patch_ref.get_note_processor_settings();
+        let safety_settings = patch_ref.get_master_safety_settings();
+        let oversampling = patch_ref.get_oversampling();
+        drop(patch_ref);
+        drop(patch_ref_ref);
+        self.set_note_processor_settings(settings);
+        self.set_master_safety_settings(safety_settings);
+        self.set_oversampling(oversampling);
         Ok(())
     }
 
+    pub fn get_note_processor_settings(&self) -> NoteProcessorSettings {
+        self.data.note_processor_settings
+    }
+
+    pub fn set_note_processor_settings(&mut self, settings: NoteProcessorSettings) {
+        self.data.note_processor_settings = settings;
+        self.comms.new_note_processor_settings.store(Some(settings));
+    }
+
+    pub fn get_master_safety_settings(&self) -> MasterSafetySettings {
+        self.data.master_safety_settings
+    }
+
+    pub fn set_master_safety_settings(&mut self, settings: MasterSafetySettings) {
+        self.data.master_safety_settings = settings;
+        self.comms.master_safety_settings.store(settings);
+        self.comms.new_master_safety_settings.store(Some(settings));
+    }
+
+    /// How many times faster than the host's sample rate the note graph runs.
+    pub fn get_oversampling(&self) -> usize {
+        self.comms.oversampling.load()
+    }
+
+    /// Changing the oversampling factor recompiles the registry for the new internal sample rate
+    /// and silences every note that is playing, so it should not be done casually.
+    pub fn set_oversampling(&mut self, factor: usize) {
+        if factor == self.comms.oversampling.load() {
+            return;
+        }
+        self.comms.oversampling.store(factor);
+        self.comms.new_global_params.store(Some(()));
+        self.poll_julia_thread();
+        self.update_dyn_data_sample_rate();
+    }
+
+    /// The sample rate the note graph runs at, including oversampling.
+    fn get_graph_sample_rate(&self) -> usize {
+        self.comms.global_params.load().sample_rate * self.comms.oversampling.load()
+    }
+
+    /// Recollects dyn data if the sample rate changed since it was last collected.
+    fn update_dyn_data_sample_rate(&mut self) {
+        if self.get_graph_sample_rate() != self.data.dyn_data_sample_rate {
+            self.send_dyn_data();
+        }
+    }
+
+    /// Returns how many dB the master safety stage is currently turning the output down by, or
+    /// None if its limiter is turned off.
+    pub fn get_master_gain_reduction(&self) -> Option<f32> {
+        let settings = &self.data.master_safety_settings;
+        if settings.enabled && settings.limiter {
+            Some(self.comms.master_gain_reduction.load())
+        } else {
+            None
+        }
+    }
+
     pub fn borrow_module_graph_ref(&self) -> &Rcrc<ModuleGraph> {
         &self.data.module_graph
     }
@@ -325,7 +610,13 @@ impl UiThreadEngine {
     pub fn regenerate_code(&mut self) {
         let module_graph_ref = self.data.module_graph.borrow();
         let params = self.comms.global_params.load();
-        let new_gen = codegen::generate_code(&*module_graph_ref, &params);
+        let generation = self.data.code_generation + 1;
+        let new_gen = codegen::generate_code(
+            &*module_graph_ref,
+            &params,
+            generation,
+            &self.data.codegen_options,
+        );
         let new_gen = if let Ok(value) = new_gen {
             value
         } else {
@@ -335,19 +626,38 @@ impl UiThreadEngine {
         };
         drop(module_graph_ref);
         self.comms.new_dyn_data.store(None);
-        let dyn_data = new_gen.dyn_data_collector.collect();
+        self.data.dyn_data_sample_rate = self.get_graph_sample_rate();
+        let dyn_data = new_gen
+            .dyn_data_collector
+            .collect(self.data.dyn_data_sample_rate);
+        self.data.code_generation = generation;
         self.comms
             .new_note_graph_code
-            .store(Some((new_gen.code, dyn_data)));
-        self.comms.julia_poll_pipe.send(()).unwrap();
+            .store(Some((new_gen.code, dyn_data, generation)));
+        self.poll_julia_thread();
         self.data.dyn_data_collector = new_gen.dyn_data_collector;
         self.data.feedback_displayer = new_gen.feedback_displayer;
+        // The indexes of watched modules change whenever the graph does.
+        self.send_watched_modules();
     }
 
-    pub fn reload_dyn_data(&mut self) {
-        let data = self.data.dyn_data_collector.collect();
-        self.comms.new_dyn_data.store(Some(data));
+    fn poll_julia_thread(&self) {
         self.comms.julia_poll_pipe.send(()).unwrap();
+        self.data.julia_thread.unpark();
+    }
+
+    fn send_dyn_data(&mut self) {
+        self.data.dyn_data_sample_rate = self.get_graph_sample_rate();
+        let data = self
+            .data
+            .dyn_data_collector
+            .collect(self.data.dyn_data_sample_rate);
+        self.comms.new_dyn_data.store(Some(data));
+        self.poll_julia_thread();
+    }
+
+    pub fn reload_dyn_data(&mut self) {
+        self.send_dyn_data();
         self.set_dummy_note_active(true);
     }
 
@@ -356,14 +666,92 @@ impl UiThreadEngine {
     /// new data so this is okay to call relatively often. It also does not block on waiting for
     /// the mutex.
     pub fn display_new_feedback_data(&mut self) {
+        // The host can change the sample rate at any time, this is a convenient place to notice.
+        self.update_dyn_data_sample_rate();
         if let Some(data) = self.comms.new_feedback.take() {
+            self.data.module_cpu_usage = data.module_cpu_usage.clone();
+            self.data.displayed_feedback_voice = data.voice;
             if let Some(widget) = &self.data.module_graph.borrow().current_widget {
                 let widget = Rc::clone(widget);
-                self.data.feedback_displayer.display(data, widget);
+                let sample_rate = self.get_graph_sample_rate();
+                self.data
+                    .feedback_displayer
+                    .display(data, widget, sample_rate);
             }
         }
     }
 
+    pub fn is_profiling_enabled(&self) -> bool {
+        self.data.codegen_options.profile
+    }
+
+    /// Profiling measures how much CPU time each module uses, at a small cost to performance. The
+    /// code has to be recompiled to turn it on or off.
+    pub fn set_profiling_enabled(&mut self, enabled: bool) {
+        if enabled != self.data.codegen_options.profile {
+            self.data.codegen_options.profile = enabled;
+            self.data.module_cpu_usage.clear();
+            self.regenerate_code();
+        }
+    }
+
+    pub fn is_non_finite_check_enabled(&self) -> bool {
+        self.data.codegen_options.check_non_finite
+    }
+
+    /// When enabled, warnings about voices producing NaN or infinite samples say which module
+    /// produced them first. The code has to be recompiled to turn it on or off.
+    pub fn set_non_finite_check_enabled(&mut self, enabled: bool) {
+        if enabled != self.data.codegen_options.check_non_finite {
+            self.data.codegen_options.check_non_finite = enabled;
+            self.regenerate_code();
+        }
+    }
+
+    /// Returns the fraction of real time the whole note graph used, if profiling is enabled and a
+    /// note has been played since.
+    pub fn get_total_cpu_usage(&self) -> Option<f32> {
+        if self.data.module_cpu_usage.is_empty() {
+            None
+        } else {
+            Some(self.data.module_cpu_usage.iter().sum())
+        }
+    }
+
+    /// Describes how much CPU time each module used, from most to least, for the message log.
+    pub fn make_cpu_usage_report(&self) -> Result<String, String> {
+        let usage = &self.data.module_cpu_usage;
+        if usage.is_empty() {
+            return Err(
+                "ERROR: No profiling data is available, enable profiling and play a note first."
+                    .to_owned(),
+            );
+        }
+        let graph = self.data.module_graph.borrow();
+        let modules = graph.borrow_modules();
+        if usage.len() != modules.len() {
+            return Err("ERROR: The profiling data is out of date, play another note.".to_owned());
+        }
+        let mut order: Vec<usize> = (0..usage.len()).collect();
+        // A module which produced NaN timings should not take down the whole report.
+        order.sort_by(|a, b| usage[*b].partial_cmp(&usage[*a]).unwrap_or(Ordering::Equal));
+        let mut report = format!(
+            "Modules used {:.1}% of the available CPU time:",
+            usage.iter().sum::<f32>() * 100.0
+        );
+        for index in order {
+            let module = modules[index].borrow();
+            let template = module.template.borrow();
+            report.push_str(&format!(
+                "\n{:>6.2}% {} (module #{})",
+                usage[index] * 100.0,
+                template.label,
+                index + 1
+            ));
+        }
+        Ok(report)
+    }
+
     pub fn set_dummy_note_active(&self, should_be_active: bool) {
         self.comms.do_dummy_note.store(should_be_active);
     }
@@ -373,21 +761,116 @@ impl UiThreadEngine {
         self.comms.do_dummy_note_once.store(true);
     }
 
-    pub fn set_module_view(&self, module: &Rcrc<Module>) {
-        let index = self
-            .data
-            .module_graph
-            .borrow()
-            .borrow_modules()
-            .iter()
-            .position(|other| Rc::ptr_eq(module, other));
-        if let Some(index) = index {
-            self.comms.module_view_index.store(index);
+    pub fn is_lookahead_enabled(&self) -> bool {
+        self.comms.lookahead.load()
+    }
+
+    /// Lookahead adds a buffer of latency but gives the Julia thread an extra buffer's worth of
+    /// time to render audio, which avoids most underruns on heavy patches.
+    pub fn set_lookahead_enabled(&self, enabled: bool) {
+        self.comms.lookahead.store(enabled);
+    }
+
+    pub fn get_underrun_count(&self) -> usize {
+        self.comms.underrun_count.load()
+    }
+
+    /// Returns the left and right channels of the oldest frame of the master output which has
+    /// not been taken yet.
+    pub fn take_master_frame(&self) -> Option<(f32, f32)> {
+        self.comms.master_tap.pop()
+    }
+
+    /// Should be called whenever the GUI is created or destroyed, the master output is only sent
+    /// to the UI thread while it is open.
+    pub fn set_gui_open(&self, open: bool) {
+        if open {
+            // Throw away whatever was left over from the last time the GUI was open, so that the
+            // analyzers don't start out showing stale audio.
+            while self.comms.master_tap.pop().is_some() {}
+        }
+        self.comms.gui_open.store(open);
+    }
+
+    /// The sample rate of the master output, which does not include oversampling.
+    pub fn get_host_sample_rate(&self) -> usize {
+        self.comms.global_params.load().sample_rate
+    }
+
+    pub fn get_crossfade_time(&self) -> f32 {
+        self.comms.crossfade_time.load()
+    }
+
+    /// Sets how long notes take to fade out when the old code they were started with has to be
+    /// unloaded, for example after several edits while they are held. Zero cuts them off
+    /// immediately.
+    pub fn set_crossfade_time(&self, seconds: f32) {
+        assert!(seconds >= 0.0);
+        self.comms.crossfade_time.store(seconds);
+    }
+
+    fn send_watched_modules(&self) {
+        let graph = self.data.module_graph.borrow();
+        let mut watched = Vec::new();
+        for (index, module) in graph.borrow_modules().iter().enumerate() {
+            let hovered = self
+                .data
+                .hovered_module
+                .as_ref()
+                .map(|hovered| Rc::ptr_eq(hovered, module))
+                .unwrap_or(false);
+            if hovered || module.borrow().pinned {
+                watched.push(index);
+            }
         }
+        self.comms.new_watched_modules.store(Some(watched));
     }
 
-    pub fn virtual_keyboard_note(&self, index: usize, down: bool) {
-        let mut events = self.comms.note_events.lock().unwrap();
+    /// The outputs of the module are sent back to the GUI until another module is hovered.
+    pub fn set_module_view(&mut self, module: &Rcrc<Module>) {
+        if let Some(hovered) = &self.data.hovered_module {
+            if Rc::ptr_eq(hovered, module) {
+                return;
+            }
+        }
+        self.data.hovered_module = Some(Rc::clone(module));
+        self.send_watched_modules();
+    }
+
+    /// Pinned modules keep sending their outputs back to the GUI while they are not hovered.
+    pub fn set_module_pinned(&mut self, module: &Rcrc<Module>, pinned: bool) {
+        module.borrow_mut().pinned = pinned;
+        self.send_watched_modules();
+    }
+
+    pub fn get_feedback_voice(&self) -> FeedbackVoice {
+        self.data.feedback_voice
+    }
+
+    /// Sets which note feedback and output view data is collected from when several are playing.
+    pub fn set_feedback_voice(&mut self, voice: FeedbackVoice) {
+        self.data.feedback_voice = voice;
+        self.data.picking_feedback_voice = false;
+        self.comms.new_feedback_voice.store(Some(voice));
+    }
+
+    /// Makes the next note played on the virtual keyboard the feedback voice.
+    pub fn start_picking_feedback_voice(&mut self) {
+        self.data.picking_feedback_voice = true;
+    }
+
+    pub fn is_picking_feedback_voice(&self) -> bool {
+        self.data.picking_feedback_voice
+    }
+
+    pub fn get_displayed_feedback_voice(&self) -> Option<usize> {
+        self.data.displayed_feedback_voice
+    }
+
+    pub fn virtual_keyboard_note(&mut self, index: usize, down: bool) {
+        if down && self.data.picking_feedback_voice {
+            self.set_feedback_voice(FeedbackVoice::Note(index));
+        }
         let event = if down {
             julia_thread::NoteEvent::StartNote {
                 index,
@@ -396,33 +879,66 @@ impl UiThreadEngine {
         } else {
             julia_thread::NoteEvent::ReleaseNote { index }
         };
-        events.push(event);
+        // If the queue is full the note is lost, but that takes a lot of clicking.
+        let _ = self.comms.note_events.push(event);
     }
 }
 
 impl AudioThreadEngine {
     // AUDIO THREAD METHODS ========================================================================
+    /// Hosts only change these while they are not playing, but this still doesn't allocate, lock,
+    /// or block, since it is called from the audio thread.
     pub fn set_global_params(&mut self, buffer_length: usize, sample_rate: usize) {
-        let mut params = self.comms.global_params.load();
+        let params = &mut self.data.global_params;
 
         // Avoid recompiling if there was no change.
         if buffer_length != params.buffer_length || sample_rate != params.sample_rate {
             params.buffer_length = buffer_length;
             params.sample_rate = sample_rate;
+            self.comms.global_params.store(*params);
+            // The Julia thread checks this flag whenever it wakes up.
             self.comms.new_global_params.store(Some(()));
-            self.comms.global_params.store(params);
-            self.comms.julia_poll_pipe.send(()).unwrap();
+            self.data.julia_thread.unpark();
         }
     }
 
+    /// How many samples of latency render_audio adds, which should be reported to the host. This
+    /// includes the lookahead of the master safety stage's limiter and the delay of the filter used
+    /// to undo oversampling.
+    pub fn get_latency(&self) -> usize {
+        let params = &self.data.global_params;
+        let safety_settings = self.comms.master_safety_settings.load();
+        params.buffer_length * self.render_ahead()
+            + safety_settings.latency(params.sample_rate)
+            + oversampling::decimator_latency(self.comms.oversampling.load())
+    }
+
+    /// How many buffers ahead of the host audio is requested, see MAX_RENDER_AHEAD.
+    fn render_ahead(&self) -> usize {
+        if self.comms.lookahead.load() {
+            MAX_RENDER_AHEAD
+        } else {
+            1
+        }
+    }
+
+    /// How many samples render_audio will produce, with channels interleaved.
+    pub fn get_output_length(&self) -> usize {
+        self.data.global_params.channels * self.data.global_params.buffer_length
+    }
+
+    fn queue_note_event(&mut self, event: julia_thread::NoteEvent) {
+        // The queue can only fill up if the Julia thread is stuck compiling while the host sends
+        // more than a thousand events, there is not much we can do without allocating.
+        let _ = self.comms.note_events.push(event);
+    }
+
     pub fn start_note(&mut self, index: usize, velocity: f32) {
-        let mut queue = self.comms.note_events.lock().unwrap();
-        queue.push(julia_thread::NoteEvent::StartNote { index, velocity });
+        self.queue_note_event(julia_thread::NoteEvent::StartNote { index, velocity });
     }
 
     pub fn release_note(&mut self, index: usize) {
-        let mut queue = self.comms.note_events.lock().unwrap();
-        queue.push(julia_thread::NoteEvent::ReleaseNote { index });
+        self.queue_note_event(julia_thread::NoteEvent::ReleaseNote { index });
     }
 
     pub fn set_pitch_wheel(&mut self, new_pitch_wheel: f32) {
@@ -434,6 +950,15 @@ impl AudioThreadEngine {
         self.data.global_data.pitch_wheel = new_pitch_wheel;
     }
 
+    pub fn set_channel_pressure(&mut self, new_pressure: f32) {
+        assert!(
+            new_pressure >= 0.0 && new_pressure <= 1.0,
+            "{} is not a valid channel pressure value.",
+            new_pressure
+        );
+        self.data.global_data.channel_pressure = new_pressure;
+    }
+
     pub fn set_control(&mut self, index: usize, value: f32) {
         assert!(
             value >= -1.0 && value <= 1.0,
@@ -456,40 +981,214 @@ impl AudioThreadEngine {
         self.data.global_data.elapsed_beats = beats;
     }
 
-    pub fn render_audio(&mut self) -> Vec<f32> {
+    pub fn set_playing(&mut self, playing: bool) {
+        let data = &mut self.data.global_data;
+        if playing && !data.playing {
+            data.transport_started = true;
+        }
+        data.playing = playing;
+    }
+
+    pub fn set_time_signature(&mut self, numerator: u8, denominator: u8) {
+        assert!(
+            numerator > 0 && denominator > 0,
+            "{}/{} is not a valid time signature.",
+            numerator,
+            denominator
+        );
+        self.data.global_data.time_signature = (numerator, denominator);
+    }
+
+    pub fn set_bar_start_beats(&mut self, beats: f32) {
+        self.data.global_data.bar_start_beats = beats;
+    }
+
+    pub fn set_loop_beats(&mut self, loop_beats: Option<(f32, f32)>) {
+        self.data.global_data.loop_beats = loop_beats;
+    }
+
+    /// Fills `output` with the next buffer of audio. `output` must be as long as
+    /// get_output_length(). This does not allocate, lock, or block. The audio played is whatever
+    /// the Julia thread rendered for an earlier request, if it did not finish in time then the
+    /// previous buffer is faded out instead. In debug builds this will panic if the heap is
+    /// touched.
+    pub fn render_audio(&mut self, output: &mut [f32]) {
+        alloc_tracker::assert_no_alloc(|| self.render_audio_impl(output));
+    }
+
+    /// Like render_audio(), but waits for the Julia thread for as long as it takes instead of
+    /// fading out, so the output does not depend on how fast the machine is. This is meant for
+    /// offline rendering like regression tests, not for use on the audio thread. Returns an error
+    /// if the Julia thread has stopped.
+    pub fn render_audio_offline(&mut self, output: &mut [f32]) -> Result<(), String> {
+        assert_eq!(output.len(), self.get_output_length());
+        let params = self.data.global_params;
+        let buf_time = params.buffer_length as f32 / params.sample_rate as f32;
+        let backoff = Backoff::new();
+        let id = loop {
+            if self.comms.julia_thread_status.load() == julia_thread::Status::Error {
+                return Err("ERROR: The Julia thread stopped while rendering audio.".to_owned());
+            }
+            if let Some(id) = self.request_render(false) {
+                break id;
+            }
+            backoff.snooze();
+        };
+        self.data.global_data.advance_transport(buf_time);
+        self.data.next_request_to_play = id;
+        let backoff = Backoff::new();
+        loop {
+            // The length only changes when set_global_params() is called, which needs &mut self.
+            match self.take_audio(output) {
+                Some(true) => return Ok(()),
+                Some(false) => {
+                    return Err(
+                        "ERROR: The Julia thread rendered a buffer of the wrong size.".to_owned(),
+                    )
+                }
+                None => backoff.snooze(),
+            }
+        }
+    }
+
+    fn render_audio_impl(&mut self, output: &mut [f32]) {
+        assert_eq!(output.len(), self.get_output_length());
         let update_feedback_data =
             self.data.last_feedback_data_update.elapsed() > FEEDBACK_UPDATE_INTERVAL;
         if update_feedback_data {
             self.data.last_feedback_data_update = Instant::now();
         }
 
+        let params = self.data.global_params;
+        let buf_time = params.buffer_length as f32 / params.sample_rate as f32;
         // The thread will only be marked as busy if it is doing something that takes a long time,
-        // e.g. compiling code.
-        let mut ready = self.comms.julia_thread_status.load().is_ready();
-        if ready {
-            let data = self.data.global_data.clone();
-            let request = julia_thread::RenderRequest {
-                data,
-                do_feedback: update_feedback_data,
-            };
-            let res = self.comms.julia_render_pipe.try_send(request);
-            match res {
-                Ok(()) => (),
-                Err(TrySendError::Full(..)) => ready = false,
-                Err(TrySendError::Disconnected(..)) => panic!("Julia thread has shut down."),
+        // e.g. compiling code. Silence during that time is expected, so it is not an underrun.
+        let status = self.comms.julia_thread_status.load();
+        let julia_working =
+            status == julia_thread::Status::Ready || status == julia_thread::Status::Rendering;
+
+        // Until enough requests are in flight, e.g. right after starting or after turning on
+        // lookahead, there is nothing to play yet.
+        let render_ahead = self.render_ahead() as u64;
+        let in_flight = self.data.next_request_id - self.data.next_request_to_play;
+        let (expected, delivered) = if in_flight >= render_ahead {
+            match self.take_audio(output) {
+                Some(delivered) => (true, delivered),
+                None => {
+                    // Give up on the request, its audio will be thrown away when it arrives.
+                    self.data.next_request_to_play += 1;
+                    (julia_working, false)
+                }
             }
+        } else {
+            (false, false)
+        };
+        if self.data.next_request_id - self.data.next_request_to_play < render_ahead {
+            self.request_render(update_feedback_data);
+            self.data.global_data.advance_transport(buf_time);
         }
 
-        let params = self.comms.global_params.load();
-        let buf_time = params.buffer_length as f32 / params.sample_rate as f32;
-        self.data.global_data.elapsed_time += buf_time;
-        self.data.global_data.elapsed_beats += buf_time * self.data.global_data.bpm / 60.0;
+        if delivered {
+            if self.data.faded_out {
+                self.fade(output, false);
+                self.data.faded_out = false;
+            }
+        } else {
+            if expected {
+                self.comms.underrun_count.fetch_add(1);
+            }
+            self.fade(output, true);
+            self.data.faded_out = true;
+        }
+        let channels = params.channels;
+        self.data
+            .last_frame
+            .copy_from_slice(&output[output.len() - channels..]);
+        if self.comms.gui_open.load() {
+            for frame in output.chunks(channels) {
+                let left = frame[0];
+                let right = frame.get(1).cloned().unwrap_or(left);
+                if self.comms.master_tap.push((left, right)).is_err() {
+                    break;
+                }
+            }
+        }
+    }
 
-        if ready {
-            self.data.audio_response_output.recv().unwrap().audio
+    /// Asks the Julia thread to render the next buffer, returning the ID of the request if it was
+    /// accepted.
+    fn request_render(&mut self, do_feedback: bool) -> Option<u64> {
+        if !self.comms.julia_thread_status.load().is_ready() {
+            return None;
+        }
+        let id = self.data.next_request_id;
+        let request = julia_thread::RenderRequest {
+            id,
+            data: self.data.global_data,
+            do_feedback,
+        };
+        if self.comms.render_requests.push(request).is_ok() {
+            self.data.next_request_id += 1;
+            self.data.global_data.transport_started = false;
+            self.data.julia_thread.unpark();
+            Some(id)
         } else {
-            let size = params.channels * params.buffer_length;
-            vec![0.0; size]
+            None
         }
     }
+
+    /// When fading out, `output` is replaced with a ramp from the last sample of the previous
+    /// buffer down to silence. When fading in, `output` is multiplied by a ramp from zero to one.
+    /// Either way this avoids the clicks that would be caused by abruptly switching to silence.
+    fn fade(&self, output: &mut [f32], out: bool) {
+        let channels = self.data.global_params.channels;
+        let frames = output.len() / channels;
+        for (index, frame) in output.chunks_exact_mut(channels).enumerate() {
+            let progress = (index + 1) as f32 / frames as f32;
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = if out {
+                    self.data.last_frame[channel] * (1.0 - progress)
+                } else {
+                    *sample * progress
+                };
+            }
+        }
+    }
+
+    /// Copies the audio for next_request_to_play into `output` if the Julia thread has finished
+    /// it, without waiting. Returns None if it has not, otherwise whether the audio was usable.
+    /// Audio from earlier requests which took too long to render is thrown away.
+    fn take_audio(&mut self, output: &mut [f32]) -> Option<bool> {
+        let id = self.data.next_request_to_play;
+        loop {
+            let response = match self.data.early_audio.take() {
+                Some(response) => response,
+                None => self.comms.rendered_audio.pop()?,
+            };
+            if response.request_id > id {
+                self.data.early_audio = Some(response);
+                return None;
+            }
+            let done = response.request_id == id;
+            // The length can be wrong if the buffer was rendered before a parameter change.
+            let usable = done && response.audio.len() == output.len();
+            if usable {
+                output.copy_from_slice(&response.audio[..]);
+            }
+            self.recycle_audio_buffer(response.audio);
+            if done {
+                self.data.next_request_to_play += 1;
+                return Some(usable);
+            }
+        }
+    }
+
+    fn recycle_audio_buffer(&self, buffer: Vec<f32>) {
+        // The free queue has room for every buffer in existence, see AUDIO_BUFFER_POOL_SIZE.
+        let result = self.comms.free_audio_buffers.push(buffer);
+        debug_assert!(
+            result.is_ok(),
+            "An audio buffer was freed on the audio thread."
+        );
+    }
 }
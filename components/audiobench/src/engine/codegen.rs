@@ -2,6 +2,7 @@ use crate::{
     engine::{
         controls::AutomationSource,
         data_transfer::{DataFormat, DynDataCollector, FeedbackDisplayer, GlobalParameters},
+        native_executor::{self, NativeGraph},
         parts::*,
     },
     gui::module_widgets::FeedbackMode,
@@ -10,8 +11,25 @@ use crate::{
 use julia_helper::GeneratedCode;
 use shared_util::prelude::*;
 
+/// Optional instrumentation which can be added to the generated code.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct CodeGenOptions {
+    /// Measure how long each module takes to run.
+    pub profile: bool,
+    /// Remember which module first produced a NaN or infinite value for each note.
+    pub check_non_finite: bool,
+}
+
+/// The note graph in every form an executor might want to run it in.
+pub(super) struct NoteGraphCode {
+    pub julia: GeneratedCode,
+    /// Err explains why the graph can't be run without Julia.
+    pub native: Result<NativeGraph, String>,
+    pub options: CodeGenOptions,
+}
+
 pub(super) struct CodeGenResult {
-    pub code: GeneratedCode,
+    pub code: NoteGraphCode,
     pub dyn_data_collector: DynDataCollector,
     pub feedback_displayer: FeedbackDisplayer,
     pub data_format: DataFormat,
@@ -38,11 +56,28 @@ struct CodeGenerator<'a> {
     dyn_data_types: Vec<()>, // Previously IOType
     dyn_data_parameter_defs: Vec<String>,
     feedback_data_len: usize,
+    options: CodeGenOptions,
+}
+
+/// Every version of the note graph gets its own module so that notes can keep playing on the old
+/// version while the new one is loaded. The module is loaded inside the namespace of the instance
+/// it belongs to, which provides the `Registry` it uses.
+///
+/// If `options.profile` is set, the module also has a function `take_module_times()` which
+/// returns how many nanoseconds each module has spent running since it was last called, indexed by
+/// the position of the module in the graph. If `options.check_non_finite` is set, it has a
+/// function `first_non_finite_module(static_index)` which returns the position plus one of the
+/// first module which produced a NaN or infinite value during the last buffer of that note, or
+/// zero if there was none.
+pub(super) fn generated_module_name(generation: usize) -> String {
+    format!("Generated{}", generation)
 }
 
 pub(super) fn generate_code(
     for_graph: &ModuleGraph,
     global_params: &GlobalParameters,
+    generation: usize,
+    options: &CodeGenOptions,
 ) -> Result<CodeGenResult, ()> {
     let execution_order = for_graph.compute_execution_order()?;
     let generator = CodeGenerator {
@@ -51,27 +86,38 @@ pub(super) fn generate_code(
         dyn_data_types: Vec::new(),
         dyn_data_parameter_defs: Vec::new(),
         feedback_data_len: 0,
+        options: *options,
     };
-    Ok(generator.generate_code(global_params))
+    Ok(generator.generate_code(global_params, generation))
 }
 
 impl<'a> CodeGenerator<'a> {
-    fn generate_code(mut self, global_params: &GlobalParameters) -> CodeGenResult {
+    fn generate_code(
+        mut self,
+        global_params: &GlobalParameters,
+        generation: usize,
+    ) -> CodeGenResult {
         let mut code = "".to_owned();
         let mut ordered_modules = Vec::new();
         let mut ordered_controls = Vec::new();
         let mut feedback_widget_selectors = Vec::new();
+        let mut voice_widget_selectors = Vec::new();
+        let mut voice_feedback_fields = String::new();
         for module_ptr in self.graph.borrow_modules() {
             ordered_modules.push(Rc::clone(module_ptr));
         }
 
-        code.push_str("module Generated\n\n  using Main.Registry.Factory.Lib\n\n");
+        let module_name = generated_module_name(generation);
+        code.push_str(&format!(
+            "module {}\n\n  using ..Registry.Factory.Lib\n  import ..Registry\n\n",
+            module_name
+        ));
         code.push_str("  mutable struct StaticData");
         for (index, module) in self.graph.borrow_modules().iter().enumerate() {
             let module_ref = module.borrow();
             let template_ref = module_ref.template.borrow();
             code.push_str(&format!(
-                "\n    m{}::Main.Registry.{}.{}Module.StaticData",
+                "\n    m{}::Registry.{}.{}Module.StaticData",
                 index, template_ref.lib_name, template_ref.module_name
             ));
         }
@@ -84,7 +130,7 @@ impl<'a> CodeGenerator<'a> {
             let module_ref = module.borrow();
             let template_ref = module_ref.template.borrow();
             code.push_str(&format!(
-                "      Main.Registry.{}.{}Module.static_init()",
+                "      Registry.{}.{}Module.static_init()",
                 template_ref.lib_name, template_ref.module_name
             ));
             if index < self.graph.borrow_modules().len() - 1 {
@@ -110,7 +156,13 @@ impl<'a> CodeGenerator<'a> {
             let module = module_ptr.borrow();
             let template = module.template.borrow();
             for (widget_index, outline) in template.widget_outlines.iter().enumerate() {
-                if outline.get_feedback_mode() != FeedbackMode::None {
+                let mode = outline.get_feedback_mode();
+                if let FeedbackMode::ManualValuePerVoice { .. } = mode {
+                    voice_widget_selectors.push((Rc::clone(module_ptr), widget_index));
+                    voice_feedback_fields
+                        .push_str(&format!("feedback.m{}w{}, ", module_index, widget_index));
+                }
+                if mode != FeedbackMode::None {
                     feedback_widget_selectors.push((Rc::clone(module_ptr), widget_index));
                     code.push_str(&format!(
                         "    m{}w{}::Vector{{Float32}}\n",
@@ -121,29 +173,68 @@ impl<'a> CodeGenerator<'a> {
         }
         code.push_str("  end # struct FeedbackData\n\n");
 
+        if self.options.check_non_finite {
+            code.push_str(concat!(
+                "  const first_non_finite = Vector{Int}()\n\n",
+                "  function first_non_finite_module(static_index::Integer)\n",
+                "    first_non_finite[static_index + 1]\n",
+                "  end # function first_non_finite_module\n\n",
+            ));
+        }
+        if self.options.profile {
+            code.push_str(&format!(
+                "  const module_times = zeros(Float64, {})\n\n",
+                self.graph.borrow_modules().len()
+            ));
+            code.push_str(concat!(
+                "  function take_module_times()\n",
+                "    times = Vector{Float32}(module_times)\n",
+                "    module_times .= 0.0\n",
+                "    times\n",
+                "  end # function take_module_times\n\n",
+            ));
+        }
+
         let mut exec_body = String::new();
         code.push_str(concat!(
             "  function exec(midi_controls::Vector{Float32}, pitch_wheel::Float32,\n",
-            "    bpm::Float32, elapsed_time::Float32, elapsed_beats::Float32,\n",
-            "    do_feedback::Bool, note_input::NoteInput, static_index::Integer, \n",
-            "    view_index::Integer, "
+            "    channel_pressure::Float32, bpm::Float32, elapsed_time::Float32,\n",
+            "    elapsed_beats::Float32, playing::Bool, transport_started::Bool,\n",
+            "    time_signature_numerator::Int32, time_signature_denominator::Int32,\n",
+            "    bar_start_beats::Float32, looping::Bool, loop_start_beats::Float32,\n",
+            "    loop_end_beats::Float32, do_feedback::Bool, note_input::NoteInput,\n",
+            "    static_index::Integer, watched_modules::Vector{Int32}, "
         ));
         exec_body.push_str(concat!(
             "    set_zero_subnormals(true)\n",
             "    static_index += 1\n", // grumble grumble
-            "    global_input = GlobalInput(midi_controls, pitch_wheel, bpm, elapsed_time, ",
-            "elapsed_beats)\n",
+            "    global_input = GlobalInput(midi_controls, pitch_wheel, channel_pressure, bpm, ",
+            "elapsed_time, elapsed_beats, playing, transport_started, time_signature_numerator, ",
+            "time_signature_denominator, bar_start_beats, looping, loop_start_beats, ",
+            "loop_end_beats)\n",
             "    start_trigger = Trigger(reshape([note_input.start_trigger, repeat([false], buffer_length - 1)...], (1, buffer_length)))\n",
             "    release_trigger = Trigger(reshape([note_input.release_trigger, repeat([false], buffer_length - 1)...], (1, buffer_length)))\n",
             "    note_output = NoteOutput()\n",
             "    context = NoteContext(global_input, note_input, note_output)\n",
-            "    view = ()\n",
-            "    feedback = FeedbackData(",
         ));
+        // Modules which are not watched leave their entry empty.
+        exec_body.push_str(&format!(
+            "    views = Any[() for _ in 1:{}]\n",
+            self.graph.borrow_modules().len()
+        ));
+        exec_body.push_str("    feedback = FeedbackData(");
         for _ in 0..feedback_widget_selectors.len() {
             exec_body.push_str("Vector{Float32}(), ");
         }
         exec_body.push_str(")\n\n    context.note_out.audio .= 0f0\n");
+        if self.options.check_non_finite {
+            exec_body.push_str(concat!(
+                "    while length(first_non_finite) < static_index\n",
+                "      push!(first_non_finite, 0)\n",
+                "    end\n",
+                "    first_non_finite[static_index] = 0\n",
+            ));
+        }
         let automation_code = AutomationCode {
             ordered_modules: ordered_modules.clone(),
         };
@@ -190,13 +281,16 @@ impl<'a> CodeGenerator<'a> {
                 exec_body.push_str("    end\n");
             }
 
+            if self.options.profile {
+                exec_body.push_str("    profile_start = time_ns()\n");
+            }
             exec_body.push_str("    ");
             for output_index in 0..template_ref.outputs.len() {
                 exec_body.push_str(&format!("m{}o{}, ", index, output_index,));
             }
             exec_body.push_str(&format!("static_container[static_index].m{}, = \n", index));
             exec_body.push_str(&format!(
-                "    Main.Registry.{}.{}Module.exec(\n      context, do_feedback,\n",
+                "    Registry.{}.{}Module.exec(\n      context, do_feedback,\n",
                 template_ref.lib_name, template_ref.module_name
             ));
 
@@ -206,7 +300,9 @@ impl<'a> CodeGenerator<'a> {
             }
             let mut first = true;
             for (widget_index, widget) in template.widget_outlines.iter().enumerate() {
-                if let FeedbackMode::ManualValue { .. } = widget.get_feedback_mode() {
+                if let FeedbackMode::ManualValue { .. } | FeedbackMode::ManualValuePerVoice { .. } =
+                    widget.get_feedback_mode()
+                {
                     if first {
                         first = false;
                         exec_body.push_str("\n      ");
@@ -218,11 +314,38 @@ impl<'a> CodeGenerator<'a> {
                 "\n      static_container[static_index].m{},\n    )\n",
                 index
             ));
-            exec_body.push_str(&format!("    if do_feedback && view_index == {}\n", index));
-            exec_body.push_str("      view = (\n");
+            if self.options.profile {
+                exec_body.push_str(&format!(
+                    "    module_times[{}] += time_ns() - profile_start\n",
+                    index + 1
+                ));
+            }
+            if self.options.check_non_finite {
+                exec_body.push_str("    if first_non_finite[static_index] == 0 && !(");
+                for (out_index, output) in template.outputs.iter().enumerate() {
+                    let numeric = match output.get_type() {
+                        JackType::Audio | JackType::Control | JackType::Pitch => true,
+                        JackType::Trigger | JackType::Waveform => false,
+                    };
+                    if numeric {
+                        exec_body.push_str(&format!("all(isfinite, m{}o{}) && ", index, out_index));
+                    }
+                }
+                exec_body.push_str("all(isfinite, context.note_out.audio))\n");
+                exec_body.push_str(&format!(
+                    "      first_non_finite[static_index] = {}\n    end\n",
+                    index + 1
+                ));
+            }
+            exec_body.push_str(&format!(
+                "    if do_feedback && {} in watched_modules\n",
+                index
+            ));
+            exec_body.push_str(&format!("      views[{}] = (\n", index + 1));
             for (out_index, output) in template.outputs.iter().enumerate() {
                 let fn_name = match output.get_type() {
                     JackType::Audio => "make_audio_view_data",
+                    JackType::Control => "make_control_view_data",
                     JackType::Pitch => "make_pitch_view_data",
                     JackType::Trigger => "make_trigger_view_data",
                     JackType::Waveform => "make_waveform_view_data",
@@ -234,10 +357,19 @@ impl<'a> CodeGenerator<'a> {
         }
         code.push_str("\n  )\n");
         code.push_str(&exec_body);
-        code.push_str("\n\n    (Array(context.note_out.audio), feedback, view)\n");
+        // The data for widgets which show every voice is also returned on its own, so that it
+        // can be retrieved from voices which are not providing the rest of the feedback data.
+        code.push_str(&format!(
+            "\n\n    (Array(context.note_out.audio), feedback, Tuple(views), ({}))\n",
+            voice_feedback_fields
+        ));
         code.push_str("  end # function exec\n\n");
-        code.push_str("end # module Generated\n");
-        let code = GeneratedCode::from_unique_source("Generated/note_graph.jl", &code);
+        code.push_str(&format!("end # module {}\n", module_name));
+        let code = NoteGraphCode {
+            julia: GeneratedCode::from_unique_source("Generated/note_graph.jl", &code),
+            native: native_executor::describe_graph(self.graph, &self.execution_order[..]),
+            options: self.options,
+        };
 
         let Self {
             graph,
@@ -252,7 +384,8 @@ impl<'a> CodeGenerator<'a> {
             feedback_data_len,
         };
         let dyn_data_collector = DynDataCollector::new(ordered_controls);
-        let feedback_displayer = FeedbackDisplayer::new(feedback_widget_selectors);
+        let feedback_displayer =
+            FeedbackDisplayer::new(feedback_widget_selectors, voice_widget_selectors);
 
         CodeGenResult {
             code,
@@ -292,10 +425,7 @@ pub fn generate_registry_code(registry: &Registry) -> Result<GeneratedCode, Stri
             let template = template_ptr.borrow();
 
             registry_code.append(
-                &format!(
-                    "\nmodule {}Module\nusing Main.Registry.Factory.Lib\n",
-                    mod_name
-                ),
+                &format!("\nmodule {}Module\nusing ...Factory.Lib\n", mod_name),
                 "generated",
             );
             if !file_content.contains("mutable struct StaticData") {
@@ -324,7 +454,9 @@ pub fn generate_registry_code(registry: &Registry) -> Result<GeneratedCode, Stri
                 func_header.push_str(", ");
             }
             for widget in &template.widget_outlines {
-                if let FeedbackMode::ManualValue { name } = widget.get_feedback_mode() {
+                if let FeedbackMode::ManualValue { name }
+                | FeedbackMode::ManualValuePerVoice { name } = widget.get_feedback_mode()
+                {
                     func_header.push_str(&name);
                     func_header.push_str("::Vector{Float32}, ");
                 }
@@ -2,8 +2,9 @@ use crate::{
     engine::{
         codegen::AutomationCode,
         controls::{
-            DurationControl, FloatInRangeControl, FrequencyControl, InputControl, IntControl,
-            OptionChoiceControl, TimingModeControl, TriggerSequenceControl, ValueSequenceControl,
+            CurveControl, DrawnWaveformControl, DurationControl, FloatInRangeControl,
+            FrequencyControl, InputControl, IntControl, OptionChoiceControl, SampleControl,
+            TimingModeControl, TriggerSequenceControl, ValueSequenceControl, WavetableControl,
         },
         data_transfer::{IOData, IOType},
         parts::{JackType, Module},
@@ -77,6 +78,12 @@ pub trait Control: Debug {
     /// Returns the value for each parameter as defined by get_parameter_types.
     fn get_parameter_values(&self) -> Vec<IOData>;
 
+    /// Like get_parameter_values, for controls whose values depend on the sample rate the note
+    /// graph runs at. Default implementation ignores the sample rate.
+    fn get_parameter_values_at_rate(&self, _sample_rate: usize) -> Vec<IOData> {
+        self.get_parameter_values()
+    }
+
     /// Returns code that provides the current value of this control. The provided string array
     /// contains code which provides the value of each parameter as defined in get_parameter_types.
     /// automation_code.of(automation_source) can be used to get the value of a particular
@@ -140,4 +147,8 @@ any_control_enum! {
     ValueSequence,
     OptionChoice,
     Frequency,
+    Sample,
+    Wavetable,
+    Curve,
+    DrawnWaveform,
 }
@@ -0,0 +1,213 @@
+use crate::{
+    engine::{
+        codegen::AutomationCode,
+        controls::{Control, UpdateRequest},
+        data_transfer::{IOData, IOType},
+    },
+    registry::yaml::YamlNode,
+};
+use shared_util::{
+    mini_serde::{MiniDes, MiniSer},
+    prelude::*,
+};
+
+pub const MAX_CURVE_POINTS: usize = 64;
+/// How extreme the most curved segments are, see shape_curve_segment.
+const CURVE_STEEPNESS: f32 = 3.0;
+
+/// Bends how far along a segment of a curve something is. Positive curvature makes the segment
+/// change quickly at its start and slowly at its end, negative curvature does the opposite. Same
+/// as shape_curve_segment in the Factory library.
+pub fn shape_curve_segment(amount: f32, curvature: f32) -> f32 {
+    amount.powf((-curvature * CURVE_STEEPNESS).exp())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CurvePoint {
+    /// Between 0 and 1.
+    pub x: f32,
+    /// Between 0 and 1, where 0 is the minimum value of the control and 1 is the maximum.
+    pub y: f32,
+    /// Between -1 and 1, how curved the segment starting at this point is. Unused for the last
+    /// point.
+    pub curvature: f32,
+}
+
+impl CurvePoint {
+    fn new(x: f32, y: f32) -> Self {
+        Self {
+            x,
+            y,
+            curvature: 0.0,
+        }
+    }
+}
+
+/// A list of points connected by curved segments. The first point is always at the start of the
+/// curve and the last one is always at the end, the others can be moved freely between their
+/// neighbors.
+#[derive(Clone, Debug)]
+pub struct CurveControl {
+    range: (f32, f32),
+    points: Vec<CurvePoint>,
+}
+
+impl CurveControl {
+    pub fn from_yaml(mut yaml: YamlNode) -> Result<Self, String> {
+        let min = if let Ok(child) = yaml.map_entry("min") {
+            child.parse()?
+        } else {
+            0.0
+        };
+        let max = if let Ok(child) = yaml.map_entry("max") {
+            child.parse_ranged(Some(min), None)?
+        } else {
+            1.0f32.max(min)
+        };
+        Ok(Self {
+            range: (min, max),
+            points: vec![CurvePoint::new(0.0, 0.0), CurvePoint::new(1.0, 1.0)],
+        })
+    }
+
+    pub fn get_range(&self) -> (f32, f32) {
+        self.range
+    }
+
+    pub fn get_points(&self) -> &[CurvePoint] {
+        &self.points[..]
+    }
+
+    /// Returns the y coordinate of the curve at the specified x coordinate, both between 0 and 1.
+    pub fn value_at(&self, x: f32) -> f32 {
+        let next = self.points.iter().position(|point| point.x > x);
+        let next = if let Some(next) = next {
+            next.max(1)
+        } else {
+            return self.points.last().unwrap().y;
+        };
+        let (start, end) = (&self.points[next - 1], &self.points[next]);
+        let width = end.x - start.x;
+        let amount = if width > 0.0 {
+            ((x - start.x) / width).clam(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let amount = shape_curve_segment(amount, start.curvature);
+        start.y * (1.0 - amount) + end.y * amount
+    }
+
+    /// The point is kept between its neighbors, the first and last points cannot move
+    /// horizontally.
+    pub fn move_point(&mut self, index: usize, x: f32, y: f32) -> UpdateRequest {
+        let last = self.points.len() - 1;
+        let x = if index == 0 {
+            0.0
+        } else if index == last {
+            1.0
+        } else {
+            x.clam(self.points[index - 1].x, self.points[index + 1].x)
+        };
+        let y = y.clam(0.0, 1.0);
+        let point = &mut self.points[index];
+        if point.x == x && point.y == y {
+            return UpdateRequest::Nothing;
+        }
+        point.x = x;
+        point.y = y;
+        UpdateRequest::UpdateDynData
+    }
+
+    /// Sets the curvature of the segment starting at the specified point.
+    pub fn set_curvature(&mut self, index: usize, curvature: f32) -> UpdateRequest {
+        assert!(index < self.points.len() - 1);
+        let curvature = curvature.clam(-1.0, 1.0);
+        if self.points[index].curvature == curvature {
+            return UpdateRequest::Nothing;
+        }
+        self.points[index].curvature = curvature;
+        UpdateRequest::UpdateDynData
+    }
+
+    /// Splits the segment at the specified x coordinate. Nothing happens if the curve already
+    /// has as many points as it can.
+    pub fn add_point(&mut self, x: f32, y: f32) -> UpdateRequest {
+        if self.points.len() >= MAX_CURVE_POINTS {
+            return UpdateRequest::Nothing;
+        }
+        let x = x.clam(0.0, 1.0);
+        let index = self
+            .points
+            .iter()
+            .position(|point| point.x > x)
+            .unwrap_or(self.points.len() - 1)
+            .max(1);
+        // Both halves of the split segment keep the curvature it had.
+        let curvature = self.points[index - 1].curvature;
+        self.points.insert(
+            index,
+            CurvePoint {
+                x,
+                y: y.clam(0.0, 1.0),
+                curvature,
+            },
+        );
+        UpdateRequest::UpdateDynData
+    }
+
+    /// The first and last points cannot be removed.
+    pub fn remove_point(&mut self, index: usize) -> UpdateRequest {
+        if index == 0 || index >= self.points.len() - 1 {
+            return UpdateRequest::Nothing;
+        }
+        self.points.remove(index);
+        UpdateRequest::UpdateDynData
+    }
+}
+
+#[rustfmt::skip]
+impl Control for CurveControl {
+    fn get_parameter_types(&self) -> Vec<IOType> {
+        vec![IOType::FloatArray, IOType::FloatArray, IOType::FloatArray]
+    }
+    fn get_parameter_values(&self) -> Vec<IOData> {
+        let (min, max) = self.range;
+        let num_segments = self.points.len() - 1;
+        vec![
+            IOData::FloatArray(self.points.iter().map(|p| p.x).collect()),
+            IOData::FloatArray(self.points.iter().map(|p| p.y * (max - min) + min).collect()),
+            IOData::FloatArray(self.points[..num_segments].iter().map(|p| p.curvature).collect()),
+        ]
+    }
+    fn generate_code(&self, params: &[&str], _automation_code: &AutomationCode) -> String {
+        format!("(x={}, y={}, curvature={})", params[0], params[1], params[2])
+    }
+    fn serialize(&self, ser: &mut MiniSer) {
+        assert!(self.points.len() <= MAX_CURVE_POINTS);
+        ser.u8(self.points.len() as u8);
+        for point in &self.points {
+            ser.f32_in_range(point.x, 0.0, 1.0);
+            ser.f32_in_range(point.y, 0.0, 1.0);
+            ser.f32_in_range(point.curvature, -1.0, 1.0);
+        }
+    }
+    fn deserialize(&mut self, des: &mut MiniDes) -> Result<(), ()> {
+        let len = des.u8()? as usize;
+        if !(2..=MAX_CURVE_POINTS).contains(&len) {
+            return Err(());
+        }
+        let mut points = Vec::with_capacity(len);
+        let mut previous_x = 0.0;
+        for _ in 0..len {
+            let x = des.f32_in_range(0.0, 1.0)?.max(previous_x);
+            let y = des.f32_in_range(0.0, 1.0)?;
+            let curvature = des.f32_in_range(-1.0, 1.0)?;
+            points.push(CurvePoint { x, y, curvature });
+            previous_x = x;
+        }
+        points[0].x = 0.0;
+        points[len - 1].x = 1.0;
+        self.points = points;
+        Ok(())
+    }
+}
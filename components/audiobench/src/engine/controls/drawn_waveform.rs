@@ -0,0 +1,177 @@
+use crate::{
+    engine::{
+        codegen::AutomationCode,
+        controls::{Control, UpdateRequest},
+        data_transfer::{IOData, IOType},
+    },
+    registry::yaml::YamlNode,
+};
+use shared_util::{
+    mini_serde::{MiniDes, MiniSer},
+    prelude::*,
+};
+use std::f32::consts::PI;
+
+/// How many samples are stored for a single cycle of the waveform.
+pub const DRAWN_WAVEFORM_LENGTH: usize = 128;
+pub const NUM_HARMONICS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawMode {
+    /// The samples are drawn directly.
+    Pencil,
+    /// The samples are computed from the strength of each harmonic.
+    Harmonics,
+}
+
+#[derive(Clone, Debug)]
+pub struct DrawnWaveformControl {
+    mode: DrawMode,
+    table: Vec<f32>,
+    /// The amplitude of each sine wave that is added together in harmonics mode, starting with the
+    /// fundamental.
+    harmonics: [f32; NUM_HARMONICS],
+}
+
+impl DrawnWaveformControl {
+    pub fn from_yaml(mut _yaml: YamlNode) -> Result<Self, String> {
+        let mut harmonics = [0.0; NUM_HARMONICS];
+        harmonics[0] = 1.0;
+        let mut result = Self {
+            mode: DrawMode::Pencil,
+            table: Vec::new(),
+            harmonics,
+        };
+        result.table = result.compute_harmonics_table();
+        Ok(result)
+    }
+
+    pub fn get_mode(&self) -> DrawMode {
+        self.mode
+    }
+
+    /// Switching to harmonics mode replaces whatever was drawn with the sum of the harmonics.
+    pub fn set_mode(&mut self, mode: DrawMode) -> UpdateRequest {
+        if self.mode == mode {
+            return UpdateRequest::Nothing;
+        }
+        self.mode = mode;
+        if mode == DrawMode::Harmonics {
+            self.table = self.compute_harmonics_table();
+        }
+        UpdateRequest::UpdateDynData
+    }
+
+    pub fn get_table(&self) -> &[f32] {
+        &self.table[..]
+    }
+
+    /// Sets every sample from start to end (inclusive) to values interpolated between the
+    /// specified ones, so that fast mouse movements do not leave gaps.
+    pub fn draw_line(
+        &mut self,
+        start: usize,
+        start_value: f32,
+        end: usize,
+        end_value: f32,
+    ) -> UpdateRequest {
+        assert_eq!(self.mode, DrawMode::Pencil);
+        let (start, start_value, end, end_value) = if start <= end {
+            (start, start_value, end, end_value)
+        } else {
+            (end, end_value, start, start_value)
+        };
+        let end = end.min(DRAWN_WAVEFORM_LENGTH - 1);
+        let mut changed = false;
+        for index in start..=end {
+            let amount = if end > start {
+                (index - start) as f32 / (end - start) as f32
+            } else {
+                1.0
+            };
+            let value = (start_value * (1.0 - amount) + end_value * amount).clam(-1.0, 1.0);
+            if self.table[index] != value {
+                self.table[index] = value;
+                changed = true;
+            }
+        }
+        if changed {
+            UpdateRequest::UpdateDynData
+        } else {
+            UpdateRequest::Nothing
+        }
+    }
+
+    pub fn get_harmonic(&self, index: usize) -> f32 {
+        self.harmonics[index]
+    }
+
+    pub fn set_harmonic(&mut self, index: usize, amplitude: f32) -> UpdateRequest {
+        assert_eq!(self.mode, DrawMode::Harmonics);
+        let amplitude = amplitude.clam(-1.0, 1.0);
+        if self.harmonics[index] == amplitude {
+            return UpdateRequest::Nothing;
+        }
+        self.harmonics[index] = amplitude;
+        self.table = self.compute_harmonics_table();
+        UpdateRequest::UpdateDynData
+    }
+
+    /// The waveform is scaled down if the harmonics would make it go outside of -1 and 1.
+    fn compute_harmonics_table(&self) -> Vec<f32> {
+        let mut table: Vec<f32> = (0..DRAWN_WAVEFORM_LENGTH)
+            .map(|index| {
+                let phase = index as f32 / DRAWN_WAVEFORM_LENGTH as f32;
+                self.harmonics
+                    .iter()
+                    .enumerate()
+                    .map(|(harmonic, amplitude)| {
+                        amplitude * (phase * (harmonic + 1) as f32 * PI * 2.0).sin()
+                    })
+                    .sum()
+            })
+            .collect();
+        let peak = table
+            .iter()
+            .fold(0.0f32, |peak, value| peak.max(value.abs()));
+        if peak > 1.0 {
+            for value in &mut table {
+                *value /= peak;
+            }
+        }
+        table
+    }
+}
+
+#[rustfmt::skip]
+impl Control for DrawnWaveformControl {
+    fn get_parameter_types(&self) -> Vec<IOType> { vec![IOType::FloatArray] }
+    fn get_parameter_values(&self) -> Vec<IOData> { vec![IOData::FloatArray(self.table.clone())] }
+    fn generate_code(&self, params: &[&str], _automation_code: &AutomationCode) -> String {
+        params[0].to_owned()
+    }
+    fn serialize(&self, ser: &mut MiniSer) {
+        ser.u1(if self.mode == DrawMode::Pencil { 0 } else { 1 });
+        for value in &self.table {
+            ser.f32_in_range(*value, -1.0, 1.0);
+        }
+        // Stored so that they can still be edited after the patch is loaded.
+        if self.mode == DrawMode::Harmonics {
+            for amplitude in &self.harmonics {
+                ser.f32_in_range(*amplitude, -1.0, 1.0);
+            }
+        }
+    }
+    fn deserialize(&mut self, des: &mut MiniDes) -> Result<(), ()> {
+        self.mode = if des.u1()? == 0 { DrawMode::Pencil } else { DrawMode::Harmonics };
+        for value in &mut self.table {
+            *value = des.f32_in_range(-1.0, 1.0)?;
+        }
+        if self.mode == DrawMode::Harmonics {
+            for amplitude in &mut self.harmonics {
+                *amplitude = des.f32_in_range(-1.0, 1.0)?;
+            }
+        }
+        Ok(())
+    }
+}
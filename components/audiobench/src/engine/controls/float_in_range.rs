@@ -51,7 +51,7 @@ impl FloatInRangeControl {
 
 impl Control for FloatInRangeControl {
     fn acceptable_automation(&self) -> Vec<JackType> {
-        vec![JackType::Audio]
+        vec![JackType::Control, JackType::Audio]
     }
 
     fn connect_automation(&mut self, from: AutomationSource) {
@@ -8,6 +8,7 @@ use crate::{
     registry::yaml::YamlNode,
 };
 use shared_util::mini_serde::{MiniDes, MiniSer};
+use std::f32::consts::PI;
 
 pub struct DefaultInputDescription {
     pub name: &'static str,
@@ -15,7 +16,7 @@ pub struct DefaultInputDescription {
     pub icon: &'static str,
 }
 
-fn default_option_descriptions_for(typ: JackType) -> &'static [DefaultInputDescription] {
+pub fn default_option_descriptions_for(typ: JackType) -> &'static [DefaultInputDescription] {
     match typ {
         JackType::Pitch => &[DefaultInputDescription {
             name: "Note Pitch",
@@ -49,6 +50,11 @@ fn default_option_descriptions_for(typ: JackType) -> &'static [DefaultInputDescr
             code: "StaticMonoAudio(0f0)",
             icon: "Factory:nothing",
         }],
+        JackType::Control => &[DefaultInputDescription {
+            name: "Zero",
+            code: "StaticControlSignal(0f0)",
+            icon: "Factory:nothing",
+        }],
         JackType::Trigger => &[
             DefaultInputDescription {
                 name: "Note Start",
@@ -69,6 +75,38 @@ fn default_option_descriptions_for(typ: JackType) -> &'static [DefaultInputDescr
     }
 }
 
+/// The waveforms that waveform inputs use when nothing is connected to them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuiltinWaveform {
+    Flat,
+    RampUp,
+    RampDown,
+    Sine,
+}
+
+impl BuiltinWaveform {
+    /// Interprets the code of one of the default options for waveform inputs.
+    pub fn from_code(code: &str) -> Option<Self> {
+        Some(match code {
+            "flat_waveform" => Self::Flat,
+            "ramp_up_waveform" => Self::RampUp,
+            "ramp_down_waveform" => Self::RampDown,
+            "sine_waveform" => Self::Sine,
+            _ => return None,
+        })
+    }
+
+    /// Same as the waveforms defined in the Julia code, phase is between 0 and 1.
+    pub fn at(self, phase: f32) -> f32 {
+        match self {
+            Self::Flat => 0.0,
+            Self::RampUp => phase * 2.0 - 1.0,
+            Self::RampDown => 1.0 - phase * 2.0,
+            Self::Sine => (phase * PI * 2.0).sin(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct InputControl {
     typ: JackType,
@@ -2,22 +2,30 @@ mod base;
 
 pub use base::*;
 
+mod curve;
+mod drawn_waveform;
 mod duration;
 mod float_in_range;
 mod frequency;
 mod input;
 mod int;
 mod option_choice;
+mod sample;
 mod timing_mode;
 mod trigger_sequence;
 mod value_sequence;
+mod wavetable;
 
+pub use curve::*;
+pub use drawn_waveform::*;
 pub use duration::*;
 pub use float_in_range::*;
 pub use frequency::*;
 pub use input::*;
 pub use int::*;
 pub use option_choice::*;
+pub use sample::*;
 pub use timing_mode::*;
 pub use trigger_sequence::*;
 pub use value_sequence::*;
+pub use wavetable::*;
@@ -0,0 +1,234 @@
+use crate::{
+    engine::{
+        audio_sample::AudioSample,
+        codegen::AutomationCode,
+        controls::{Control, UpdateRequest},
+        data_transfer::{IOData, IOType},
+    },
+    registry::{yaml::YamlNode, Registry},
+};
+use shared_util::{
+    mini_serde::{MiniDes, MiniSer},
+    prelude::*,
+};
+use std::cell::RefCell;
+
+/// Embedded samples are stored as 16 bit integers, this keeps patches from getting absurdly large.
+pub const MAX_EMBEDDED_SAMPLE_LENGTH: usize = 0x80_0000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SampleSource {
+    None,
+    /// A sample file which is part of a library, E.G. `Factory:samples/Piano.wav`.
+    Library(String),
+    /// The audio is stored in the patch itself.
+    Embedded,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SampleMarker {
+    Start,
+    LoopStart,
+    LoopEnd,
+    End,
+}
+
+impl SampleMarker {
+    /// In the order they always appear in the sample.
+    pub const ALL: [SampleMarker; 4] = [
+        SampleMarker::Start,
+        SampleMarker::LoopStart,
+        SampleMarker::LoopEnd,
+        SampleMarker::End,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Start => "Start",
+            Self::LoopStart => "Loop Start",
+            Self::LoopEnd => "Loop End",
+            Self::End => "End",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SampleControl {
+    source: SampleSource,
+    sample: Option<Rc<AudioSample>>,
+    /// Positions of the markers in the order of SampleMarker::ALL, as fractions of the length of
+    /// the sample.
+    markers: [f32; 4],
+    /// The sample converted to the sample rate it was last requested at.
+    resampled: RefCell<Option<(usize, Vec<f32>)>>,
+}
+
+impl SampleControl {
+    pub fn from_yaml(mut _yaml: YamlNode) -> Result<Self, String> {
+        Ok(Self {
+            source: SampleSource::None,
+            sample: None,
+            markers: [0.0, 0.0, 1.0, 1.0],
+            resampled: RefCell::new(None),
+        })
+    }
+
+    pub fn get_source(&self) -> &SampleSource {
+        &self.source
+    }
+
+    /// None if no sample was chosen or the library it came from is not installed.
+    pub fn get_sample(&self) -> Option<&Rc<AudioSample>> {
+        self.sample.as_ref()
+    }
+
+    fn set_sample(&mut self, source: SampleSource, sample: Rc<AudioSample>) -> UpdateRequest {
+        self.source = source;
+        self.sample = Some(sample);
+        self.markers = [0.0, 0.0, 1.0, 1.0];
+        self.resampled.replace(None);
+        UpdateRequest::UpdateDynData
+    }
+
+    pub fn set_library_sample(&mut self, name: String, sample: Rc<AudioSample>) -> UpdateRequest {
+        self.set_sample(SampleSource::Library(name), sample)
+    }
+
+    /// Returns an error if the sample is too long to be stored in a patch.
+    pub fn set_embedded_sample(&mut self, sample: AudioSample) -> Result<UpdateRequest, String> {
+        if sample.data.len() > MAX_EMBEDDED_SAMPLE_LENGTH {
+            return Err(format!(
+                concat!(
+                    "ERROR: The sample is {:.1} seconds long, samples can be at most {:.1} ",
+                    "seconds long."
+                ),
+                sample.get_duration(),
+                MAX_EMBEDDED_SAMPLE_LENGTH as f32 / sample.sample_rate as f32
+            ));
+        }
+        Ok(self.set_sample(SampleSource::Embedded, Rc::new(sample)))
+    }
+
+    /// Looks up the audio of a sample which is referenced from a library. Nothing happens if the
+    /// library is missing, the reference is kept so that the patch can still be saved.
+    pub fn resolve_library_sample(&mut self, registry: &Registry) {
+        if let SampleSource::Library(name) = &self.source {
+            self.sample = registry.get_sample_by_name(name).map(Rc::clone);
+            self.resampled.replace(None);
+        }
+    }
+
+    pub fn get_marker(&self, marker: SampleMarker) -> f32 {
+        self.markers[marker as usize]
+    }
+
+    /// The marker is kept between the markers that come before and after it.
+    pub fn set_marker(&mut self, marker: SampleMarker, value: f32) -> UpdateRequest {
+        let index = marker as usize;
+        let min = index.checked_sub(1).map(|i| self.markers[i]).unwrap_or(0.0);
+        let max = self.markers.get(index + 1).cloned().unwrap_or(1.0);
+        let value = value.clam(min, max);
+        if self.markers[index] == value {
+            return UpdateRequest::Nothing;
+        }
+        self.markers[index] = value;
+        UpdateRequest::UpdateDynData
+    }
+
+    fn marker_indexes(&self, len: usize) -> Vec<IOData> {
+        self.markers
+            .iter()
+            .map(|marker| IOData::Int((marker * len as f32).round() as i32))
+            .collect()
+    }
+}
+
+#[rustfmt::skip]
+impl Control for SampleControl {
+    fn get_parameter_types(&self) -> Vec<IOType> {
+        vec![IOType::FloatArray, IOType::Int, IOType::Int, IOType::Int, IOType::Int]
+    }
+    fn get_parameter_values(&self) -> Vec<IOData> {
+        let sample_rate = self.sample.as_ref().map(|s| s.sample_rate).unwrap_or(1);
+        self.get_parameter_values_at_rate(sample_rate)
+    }
+    fn get_parameter_values_at_rate(&self, sample_rate: usize) -> Vec<IOData> {
+        let sample = if let Some(sample) = &self.sample {
+            sample
+        } else {
+            let mut values = vec![IOData::FloatArray(Vec::new())];
+            values.append(&mut self.marker_indexes(0));
+            return values;
+        };
+        let mut resampled = self.resampled.borrow_mut();
+        if resampled.as_ref().map(|(rate, _)| *rate) != Some(sample_rate) {
+            *resampled = Some((sample_rate, sample.resample(sample_rate)));
+        }
+        let data = resampled.as_ref().unwrap().1.clone();
+        let len = data.len();
+        let mut values = vec![IOData::FloatArray(data)];
+        values.append(&mut self.marker_indexes(len));
+        values
+    }
+    fn generate_code(&self, params: &[&str], _automation_code: &AutomationCode) -> String {
+        format!(
+            "(audio={}, start={}, loop_start={}, loop_end={}, stop={})",
+            params[0], params[1], params[2], params[3], params[4]
+        )
+    }
+    fn serialize(&self, ser: &mut MiniSer) {
+        match &self.source {
+            SampleSource::None => ser.u2(0),
+            SampleSource::Library(name) => {
+                ser.u2(1);
+                ser.str(name);
+            }
+            SampleSource::Embedded => {
+                ser.u2(2);
+                let sample = self.sample.as_ref().unwrap();
+                ser.u32(sample.sample_rate as u32);
+                ser.u32(sample.data.len() as u32);
+                for value in &sample.data {
+                    ser.i16((value.clam(-1.0, 1.0) * std::i16::MAX as f32) as i16);
+                }
+            }
+        }
+        for marker in &self.markers {
+            ser.f32_in_range(*marker, 0.0, 1.0);
+        }
+    }
+    fn deserialize(&mut self, des: &mut MiniDes) -> Result<(), ()> {
+        match des.u2()? {
+            0 => {
+                self.source = SampleSource::None;
+                self.sample = None;
+            }
+            // Resolved later by resolve_library_sample, since the registry is needed to do that.
+            1 => {
+                self.source = SampleSource::Library(des.str()?);
+                self.sample = None;
+            }
+            2 => {
+                let sample_rate = des.u32()? as usize;
+                let len = des.u32()? as usize;
+                if sample_rate == 0 || len > MAX_EMBEDDED_SAMPLE_LENGTH {
+                    return Err(());
+                }
+                let mut data = Vec::with_capacity(len);
+                for _ in 0..len {
+                    data.push(des.i16()? as f32 / std::i16::MAX as f32);
+                }
+                self.source = SampleSource::Embedded;
+                self.sample = Some(Rc::new(AudioSample { sample_rate, data }));
+            }
+            _ => return Err(()),
+        }
+        self.resampled.replace(None);
+        let mut previous = 0.0;
+        for marker in &mut self.markers {
+            *marker = des.f32_in_range(0.0, 1.0)?.max(previous);
+            previous = *marker;
+        }
+        Ok(())
+    }
+}
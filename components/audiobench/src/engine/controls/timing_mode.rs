@@ -13,16 +13,19 @@ pub struct TimingModeControl {
     /// True if time should be measured against how long the song has been running, false if time
     /// should be measured against how long the note has been running.
     use_elapsed_time: bool,
+    /// True if song time should restart at the beginning of every bar. Only meaningful when
+    /// `use_elapsed_time` is true.
+    sync_to_bars: bool,
     /// True if time should be measured in seconds, false if time should be measured in beats.
     beat_synchronized: bool,
 }
 
 impl TimingModeControl {
     pub fn from_yaml(mut yaml: YamlNode) -> Result<Self, String> {
-        let use_elapsed_time = if let Ok(child) = yaml.map_entry("default_source") {
-            child.parse_enumerated(&["note", "song"])? == 1
+        let source = if let Ok(child) = yaml.map_entry("default_source") {
+            child.parse_enumerated(&["note", "song", "bar"])?
         } else {
-            false
+            0
         };
         let beat_synchronized = if let Ok(child) = yaml.map_entry("default_units") {
             child.parse_enumerated(&["seconds", "beats"])? == 1
@@ -30,7 +33,8 @@ impl TimingModeControl {
             false
         };
         Ok(Self {
-            use_elapsed_time,
+            use_elapsed_time: source >= 1,
+            sync_to_bars: source == 2,
             beat_synchronized,
         })
     }
@@ -38,15 +42,33 @@ impl TimingModeControl {
     fn get_raw_value(&self) -> u8 {
         let source_flag = if self.use_elapsed_time { 0b1 } else { 0b0 };
         let unit_flag = if self.beat_synchronized { 0b10 } else { 0b00 };
-        source_flag | unit_flag
+        let bar_flag = if self.use_elapsed_time && self.sync_to_bars {
+            0b100
+        } else {
+            0b000
+        };
+        source_flag | unit_flag | bar_flag
     }
 
     pub fn uses_elapsed_time(&self) -> bool {
         self.use_elapsed_time
     }
 
+    pub fn syncs_to_bars(&self) -> bool {
+        self.use_elapsed_time && self.sync_to_bars
+    }
+
+    /// Cycles through note, song, and bar timing.
     pub fn toggle_source(&mut self) -> UpdateRequest {
-        self.use_elapsed_time = !self.use_elapsed_time;
+        if !self.use_elapsed_time {
+            self.use_elapsed_time = true;
+            self.sync_to_bars = false;
+        } else if !self.sync_to_bars {
+            self.sync_to_bars = true;
+        } else {
+            self.use_elapsed_time = false;
+            self.sync_to_bars = false;
+        }
         UpdateRequest::UpdateDynData
     }
 
@@ -73,11 +95,12 @@ impl Control for TimingModeControl {
     }
     fn deserialize(&mut self, des: &mut MiniDes) -> Result<(), ()> { 
         let raw_value = des.u8()?;
-        if raw_value > 0b11 {
+        if raw_value > 0b111 {
             return Err(());
         }
         self.use_elapsed_time = raw_value & 0b1 == 0b1;
         self.beat_synchronized = raw_value & 0b10 == 0b10;
+        self.sync_to_bars = raw_value & 0b100 == 0b100;
         Ok(())
     }
 }
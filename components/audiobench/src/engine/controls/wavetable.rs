@@ -0,0 +1,184 @@
+use crate::{
+    engine::{
+        audio_sample::AudioSample,
+        codegen::AutomationCode,
+        controls::{default_option_descriptions_for, BuiltinWaveform, Control, UpdateRequest},
+        data_transfer::{IOData, IOType},
+        parts::JackType,
+    },
+    registry::yaml::YamlNode,
+};
+use shared_util::{
+    mini_serde::{MiniDes, MiniSer},
+    prelude::*,
+};
+
+/// How many samples are in each frame, this is the same as what most other wavetable synths use
+/// so that their wavetables can be imported directly.
+pub const WAVETABLE_FRAME_LENGTH: usize = 2048;
+pub const MAX_WAVETABLE_FRAMES: usize = 256;
+/// How many frames there are in a wavetable that morphs between two builtin waveforms.
+pub const GENERATED_FRAME_COUNT: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavetableSource {
+    /// Crossfades from one of the default waveforms of waveform inputs to another. The values are
+    /// indexes into default_option_descriptions_for(JackType::Waveform).
+    Generated { from: usize, to: usize },
+    /// The frames were loaded from an audio file and are stored in the patch.
+    Imported,
+}
+
+#[derive(Clone, Debug)]
+pub struct WavetableControl {
+    source: WavetableSource,
+    /// Every frame one after another, each one is WAVETABLE_FRAME_LENGTH samples long.
+    frames: Rc<Vec<f32>>,
+}
+
+fn builtin_waveform(index: usize) -> BuiltinWaveform {
+    let code = default_option_descriptions_for(JackType::Waveform)[index].code;
+    BuiltinWaveform::from_code(code).unwrap()
+}
+
+fn generate_frames(from: usize, to: usize) -> Vec<f32> {
+    let (from, to) = (builtin_waveform(from), builtin_waveform(to));
+    let mut frames = Vec::with_capacity(GENERATED_FRAME_COUNT * WAVETABLE_FRAME_LENGTH);
+    for frame in 0..GENERATED_FRAME_COUNT {
+        let amount = frame as f32 / (GENERATED_FRAME_COUNT - 1) as f32;
+        for index in 0..WAVETABLE_FRAME_LENGTH {
+            let phase = index as f32 / WAVETABLE_FRAME_LENGTH as f32;
+            frames.push(from.at(phase) * (1.0 - amount) + to.at(phase) * amount);
+        }
+    }
+    frames
+}
+
+fn parse_default_waveform(
+    yaml: &mut YamlNode,
+    name: &str,
+    default: usize,
+) -> Result<usize, String> {
+    if let Ok(child) = yaml.map_entry(name) {
+        let names: Vec<_> = default_option_descriptions_for(JackType::Waveform)
+            .iter()
+            .map(|option| option.name.to_lowercase().replace(' ', "_"))
+            .collect();
+        let name_refs: Vec<_> = names.iter().map(|e| &e[..]).collect();
+        child.parse_enumerated(&name_refs[..])
+    } else {
+        Ok(default)
+    }
+}
+
+impl WavetableControl {
+    pub fn from_yaml(mut yaml: YamlNode) -> Result<Self, String> {
+        // Sine wave to ramp up unless the module says otherwise.
+        let from = parse_default_waveform(&mut yaml, "default_from", 3)?;
+        let to = parse_default_waveform(&mut yaml, "default_to", 1)?;
+        Ok(Self {
+            source: WavetableSource::Generated { from, to },
+            frames: Rc::new(generate_frames(from, to)),
+        })
+    }
+
+    pub fn get_source(&self) -> WavetableSource {
+        self.source
+    }
+
+    pub fn get_num_frames(&self) -> usize {
+        self.frames.len() / WAVETABLE_FRAME_LENGTH
+    }
+
+    pub fn borrow_frame(&self, index: usize) -> &[f32] {
+        &self.frames[index * WAVETABLE_FRAME_LENGTH..(index + 1) * WAVETABLE_FRAME_LENGTH]
+    }
+
+    /// from and to are indexes into default_option_descriptions_for(JackType::Waveform).
+    pub fn set_generated(&mut self, from: usize, to: usize) -> UpdateRequest {
+        let source = WavetableSource::Generated { from, to };
+        if self.source == source {
+            return UpdateRequest::Nothing;
+        }
+        self.source = source;
+        self.frames = Rc::new(generate_frames(from, to));
+        UpdateRequest::UpdateDynData
+    }
+
+    /// Splits the audio into frames, leftover samples at the end are ignored. The sample rate of
+    /// the audio does not matter since each frame is a single cycle.
+    pub fn import(&mut self, sample: &AudioSample) -> Result<UpdateRequest, String> {
+        let num_frames = sample.data.len() / WAVETABLE_FRAME_LENGTH;
+        if num_frames == 0 {
+            return Err(format!(
+                concat!(
+                    "ERROR: The file is {} samples long, wavetables must contain at least one ",
+                    "frame of {} samples."
+                ),
+                sample.data.len(),
+                WAVETABLE_FRAME_LENGTH
+            ));
+        }
+        let num_frames = num_frames.min(MAX_WAVETABLE_FRAMES);
+        self.source = WavetableSource::Imported;
+        self.frames = Rc::new(sample.data[..num_frames * WAVETABLE_FRAME_LENGTH].to_owned());
+        Ok(UpdateRequest::UpdateDynData)
+    }
+}
+
+#[rustfmt::skip]
+impl Control for WavetableControl {
+    fn get_parameter_types(&self) -> Vec<IOType> {
+        vec![IOType::FloatArray, IOType::Int]
+    }
+    fn get_parameter_values(&self) -> Vec<IOData> {
+        vec![
+            IOData::FloatArray((*self.frames).clone()),
+            IOData::Int(self.get_num_frames() as i32),
+        ]
+    }
+    fn generate_code(&self, params: &[&str], _automation_code: &AutomationCode) -> String {
+        format!("(frames={}, num_frames={})", params[0], params[1])
+    }
+    fn serialize(&self, ser: &mut MiniSer) {
+        match self.source {
+            WavetableSource::Generated { from, to } => {
+                ser.u1(0);
+                ser.u2(from as _);
+                ser.u2(to as _);
+            }
+            WavetableSource::Imported => {
+                ser.u1(1);
+                ser.u16(self.get_num_frames() as u16);
+                for value in &*self.frames {
+                    ser.i16((value.clam(-1.0, 1.0) * std::i16::MAX as f32) as i16);
+                }
+            }
+        }
+    }
+    fn deserialize(&mut self, des: &mut MiniDes) -> Result<(), ()> {
+        if des.u1()? == 0 {
+            let num_options = default_option_descriptions_for(JackType::Waveform).len();
+            let from = des.u2()? as usize;
+            let to = des.u2()? as usize;
+            if from >= num_options || to >= num_options {
+                return Err(());
+            }
+            self.source = WavetableSource::Generated { from, to };
+            self.frames = Rc::new(generate_frames(from, to));
+        } else {
+            let num_frames = des.u16()? as usize;
+            if !(1..=MAX_WAVETABLE_FRAMES).contains(&num_frames) {
+                return Err(());
+            }
+            let len = num_frames * WAVETABLE_FRAME_LENGTH;
+            let mut frames = Vec::with_capacity(len);
+            for _ in 0..len {
+                frames.push(des.i16()? as f32 / std::i16::MAX as f32);
+            }
+            self.source = WavetableSource::Imported;
+            self.frames = Rc::new(frames);
+        }
+        Ok(())
+    }
+}
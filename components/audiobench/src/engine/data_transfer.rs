@@ -13,6 +13,17 @@ pub struct GlobalParameters {
     pub sample_rate: usize,
 }
 
+impl GlobalParameters {
+    /// The parameters the note graph runs with when it is oversampled by the specified factor.
+    pub fn oversampled(&self, factor: usize) -> Self {
+        Self {
+            channels: self.channels,
+            buffer_length: self.buffer_length * factor,
+            sample_rate: self.sample_rate * factor,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DataFormat {
     pub global_params: GlobalParameters,
@@ -20,15 +31,27 @@ pub struct DataFormat {
     pub feedback_data_len: usize,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct GlobalData {
     // MIDI specifies each MIDI Channel has 128 controls.
     pub controller_values: [f32; 128],
     // The pitch wheel is seperate from other controls due to its higher precision.
     pub pitch_wheel: f32,
+    // Also known as channel aftertouch. Ranges from 0 to 1.
+    pub channel_pressure: f32,
     pub bpm: f32,
     pub elapsed_time: f32,
     pub elapsed_beats: f32,
+    // Song time only advances while the host's transport is playing.
+    pub playing: bool,
+    // True for the first buffer rendered after the transport starts playing.
+    pub transport_started: bool,
+    // Numerator and denominator, E.G. (6, 8) for 6/8 time.
+    pub time_signature: (u8, u8),
+    // Where the current bar started, measured in beats (quarter notes) like elapsed_beats.
+    pub bar_start_beats: f32,
+    // Start and end of the host's loop region in beats, if it is looping.
+    pub loop_beats: Option<(f32, f32)>,
 }
 
 impl GlobalData {
@@ -36,9 +59,41 @@ impl GlobalData {
         Self {
             controller_values: [0.0; 128],
             pitch_wheel: 0.0,
+            channel_pressure: 0.0,
             bpm: 120.0,
             elapsed_time: 0.0,
             elapsed_beats: 0.0,
+            // Frontends that don't report transport info should still have time moving forward.
+            playing: true,
+            transport_started: false,
+            time_signature: (4, 4),
+            bar_start_beats: 0.0,
+            loop_beats: None,
+        }
+    }
+
+    /// Returns how many beats (quarter notes) are in a single bar.
+    pub fn beats_per_bar(&self) -> f32 {
+        self.time_signature.0 as f32 * 4.0 / self.time_signature.1 as f32
+    }
+
+    /// Moves the transport forward by the specified amount of time, if it is playing. Keeps the
+    /// bar position and loop region consistent until the host tells us otherwise.
+    pub fn advance_transport(&mut self, seconds: f32) {
+        if !self.playing {
+            return;
+        }
+        self.elapsed_time += seconds;
+        self.elapsed_beats += seconds * self.bpm / 60.0;
+        if let Some((start, end)) = self.loop_beats {
+            if end > start && self.elapsed_beats >= end {
+                self.elapsed_beats -= end - start;
+                self.bar_start_beats = start;
+            }
+        }
+        let bar_length = self.beats_per_bar();
+        while self.elapsed_beats >= self.bar_start_beats + bar_length {
+            self.bar_start_beats += bar_length;
         }
     }
 
@@ -49,9 +104,18 @@ impl GlobalData {
         Ok(vec![
             Value::move_array(frame, self.controller_values.to_vec(), (128,))?,
             Value::new(frame, self.pitch_wheel)?,
+            Value::new(frame, self.channel_pressure)?,
             Value::new(frame, self.bpm)?,
             Value::new(frame, self.elapsed_time)?,
             Value::new(frame, self.elapsed_beats)?,
+            Value::new(frame, self.playing)?,
+            Value::new(frame, self.transport_started)?,
+            Value::new(frame, self.time_signature.0 as i32)?,
+            Value::new(frame, self.time_signature.1 as i32)?,
+            Value::new(frame, self.bar_start_beats)?,
+            Value::new(frame, self.loop_beats.is_some())?,
+            Value::new(frame, self.loop_beats.map(|l| l.0).unwrap_or(0.0))?,
+            Value::new(frame, self.loop_beats.map(|l| l.1).unwrap_or(0.0))?,
         ])
     }
 }
@@ -70,8 +134,17 @@ pub struct NoteData {
 #[derive(Clone, Debug, Default)]
 pub struct FeedbackData {
     pub widget_feeback: Vec<Vec<f32>>,
-    pub output_view: Vec<Vec<f32>>,
-    pub output_view_module_index: usize,
+    /// For each widget which shows every voice, the data it was sent by each active voice other
+    /// than the one the rest of this data came from.
+    pub voice_feedback: Vec<Vec<Vec<f32>>>,
+    /// The data shown in the output views of each watched module, along with the index of the
+    /// module in the graph.
+    pub output_views: Vec<(usize, Vec<Vec<f32>>)>,
+    /// The MIDI note the data was collected from, None if it came from the dummy note.
+    pub voice: Option<usize>,
+    /// How much of the real time available to render audio each module used, indexed by the
+    /// position of the module in the graph. Empty unless the code was generated with profiling.
+    pub module_cpu_usage: Vec<f32>,
 }
 
 /// Represents the data type of a variable which is either an input or output in the generated
@@ -130,10 +203,11 @@ pub struct DynDataCollector {
 }
 
 impl DynDataCollector {
-    pub fn collect(&self) -> Vec<IOData> {
+    /// `sample_rate` is the rate the note graph runs at, including oversampling.
+    pub fn collect(&self, sample_rate: usize) -> Vec<IOData> {
         let mut result = Vec::new();
         for control in &self.controls {
-            result.append(&mut control.borrow().get_parameter_values());
+            result.append(&mut control.borrow().get_parameter_values_at_rate(sample_rate));
         }
         result
     }
@@ -143,10 +217,13 @@ impl DynDataCollector {
 pub struct FeedbackDisplayer {
     /// Module the widget is in and the index of the widget in that module.
     widget_selectors: Vec<(Rcrc<Module>, usize)>,
+    /// The same, for the widgets which also take data from every other voice.
+    voice_widget_selectors: Vec<(Rcrc<Module>, usize)>,
 }
 
 impl FeedbackDisplayer {
-    pub fn display(&self, data: FeedbackData, on: Rc<ModuleGraph>) {
+    /// `sample_rate` is the rate the note graph runs at, including oversampling.
+    pub fn display(&self, data: FeedbackData, on: Rc<ModuleGraph>, sample_rate: usize) {
         if data.widget_feeback.len() != self.widget_selectors.len() {
             return;
         }
@@ -154,12 +231,23 @@ impl FeedbackDisplayer {
             let module_widget = on.get_widget_for_module(module).unwrap();
             module_widget.take_feedback_data(data.widget_feeback[index].clone(), *widget_index);
         }
+        for (index, (module, widget_index)) in self.voice_widget_selectors.iter().enumerate() {
+            let module_widget = on.get_widget_for_module(module).unwrap();
+            // Empty when no other voices were playing.
+            let voice_data = data.voice_feedback.get(index).cloned().unwrap_or_default();
+            module_widget.take_voice_feedback_data(voice_data, *widget_index);
+        }
         let real_graph_ptr: Rcrc<crate::engine::parts::ModuleGraph> = on.get_real_graph();
         let real_graph = real_graph_ptr.borrow();
-        if data.output_view_module_index < real_graph.borrow_modules().len() {
-            let module = &real_graph.borrow_modules()[data.output_view_module_index];
+        for (index, module) in real_graph.borrow_modules().iter().enumerate() {
             let module_widget = on.get_widget_for_module(module).unwrap();
-            module_widget.take_output_view_data(data.output_view);
+            module_widget.set_cpu_usage(data.module_cpu_usage.get(index).cloned());
+        }
+        for (module_index, output_view) in data.output_views {
+            if let Some(module) = real_graph.borrow_modules().get(module_index) {
+                let module_widget = on.get_widget_for_module(module).unwrap();
+                module_widget.take_output_view_data(output_view, sample_rate as f32);
+            }
         }
     }
 }
@@ -0,0 +1,205 @@
+use crate::{config::ENGINE_VERSION, engine::data_transfer::GlobalParameters, registry::Registry};
+use crossbeam_channel::Sender;
+use julia_helper::GeneratedCode;
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, OpenOptions},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// If a lock file is older than this, whoever made it probably crashed.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(60 * 30);
+/// Images are per sample rate, buffer length, etc. so a few are kept around.
+const MAX_CACHED_IMAGES: usize = 4;
+
+/// A system image which was built from exactly the same code the engine would otherwise compile.
+pub struct CachedImage {
+    pub julia_bin_dir: PathBuf,
+    pub image_path: PathBuf,
+}
+
+/// Keeps track of a Julia system image containing the registry code, so that Julia doesn't have to
+/// compile the libraries every time the engine starts. Images are keyed on the engine version,
+/// library versions, the registry source and the global parameters it was compiled with.
+#[derive(Clone)]
+pub struct ImageCache {
+    dir: PathBuf,
+    key: String,
+    file_stem: String,
+}
+
+impl ImageCache {
+    pub fn new(
+        registry: &Registry,
+        registry_source: &GeneratedCode,
+        parameters: &GlobalParameters,
+    ) -> Self {
+        let dir = directories::BaseDirs::new()
+            .map(|dirs| dirs.cache_dir().join("Audiobench"))
+            .unwrap_or_else(|| std::env::temp_dir().join("Audiobench"));
+        let mut libraries: Vec<_> = registry
+            .borrow_library_infos()
+            .map(|(name, info)| format!("{} {}", name, info.version))
+            .collect();
+        libraries.sort();
+        let mut source_hasher = DefaultHasher::new();
+        registry_source.as_str().hash(&mut source_hasher);
+        let key = format!(
+            "engine {}\nlibraries {}\nsource {:016x}\nparameters {} {} {}",
+            ENGINE_VERSION,
+            libraries.join(", "),
+            source_hasher.finish(),
+            parameters.channels,
+            parameters.buffer_length,
+            parameters.sample_rate
+        );
+        let mut key_hasher = DefaultHasher::new();
+        key.hash(&mut key_hasher);
+        let file_stem = format!("registry_{:016x}", key_hasher.finish());
+        Self {
+            dir,
+            key,
+            file_stem,
+        }
+    }
+
+    fn path_with_extension(&self, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", self.file_stem, extension))
+    }
+
+    /// Returns the image for the current key if it has already been built.
+    pub fn find_valid_image(&self) -> Option<CachedImage> {
+        let image_path = self.path_with_extension(julia_helper::SYSTEM_IMAGE_EXTENSION);
+        // The manifest is only written after the image is complete.
+        let manifest = fs::read_to_string(self.path_with_extension("manifest")).ok()?;
+        let mut lines = manifest.splitn(2, '\n');
+        let julia_bin_dir = PathBuf::from(lines.next()?);
+        if lines.next()? != self.key || !image_path.exists() {
+            return None;
+        }
+        Some(CachedImage {
+            julia_bin_dir,
+            image_path,
+        })
+    }
+
+    /// Builds an image for the current key on another thread, unless another instance of the
+    /// engine is already doing so. `global_code` should be everything the executor loads before
+    /// the note graph code. The outcome is reported through `log`.
+    pub fn spawn_rebuild(
+        &self,
+        julia_bin_dir: PathBuf,
+        global_code: Vec<GeneratedCode>,
+        log: Sender<String>,
+    ) {
+        if let Err(err) = fs::create_dir_all(&self.dir) {
+            let _ = log.send(format!(
+                "ERROR: Failed to create {}, caused by:\nERROR: {}",
+                self.dir.to_string_lossy(),
+                err
+            ));
+            return;
+        }
+        let lock_path = self.path_with_extension("lock");
+        let lock_age = fs::metadata(&lock_path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        match lock_age {
+            Some(age) if age < STALE_LOCK_AGE => return,
+            Some(_) => {
+                let _ = fs::remove_file(&lock_path);
+            }
+            None => (),
+        }
+        if OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .is_err()
+        {
+            return;
+        }
+
+        let this = self.clone();
+        std::thread::Builder::new()
+            .name("julia_image_builder".to_owned())
+            .spawn(move || {
+                let start = Instant::now();
+                let result = this.rebuild(&julia_bin_dir, &global_code[..]);
+                let _ = fs::remove_file(&lock_path);
+                let message = match result {
+                    Ok(()) => format!(
+                        concat!(
+                            "Built a cached copy of the libraries in {:.1}s, Audiobench will ",
+                            "start faster next time it is opened with the same settings."
+                        ),
+                        start.elapsed().as_secs_f32()
+                    ),
+                    Err(err) => format!(
+                        concat!(
+                            "Failed to build a cached copy of the libraries, Audiobench will ",
+                            "keep working but will be slower to start. Caused by:\n{}"
+                        ),
+                        err
+                    ),
+                };
+                let _ = log.send(message);
+            })
+            .unwrap();
+    }
+
+    fn rebuild(&self, julia_bin_dir: &Path, global_code: &[GeneratedCode]) -> Result<(), String> {
+        let image_path = self.path_with_extension(julia_helper::SYSTEM_IMAGE_EXTENSION);
+        let temp_image_path = self.dir.join(format!(
+            "{}_partial.{}",
+            self.file_stem,
+            julia_helper::SYSTEM_IMAGE_EXTENSION
+        ));
+        julia_helper::build_system_image(julia_bin_dir, &temp_image_path, global_code)?;
+        fs::rename(&temp_image_path, &image_path).map_err(|err| {
+            format!(
+                "ERROR: Failed to move {}, caused by:\nERROR: {}",
+                temp_image_path.to_string_lossy(),
+                err
+            )
+        })?;
+        let manifest = format!("{}\n{}", julia_bin_dir.to_string_lossy(), self.key);
+        let manifest_path = self.path_with_extension("manifest");
+        fs::write(&manifest_path, manifest).map_err(|err| {
+            format!(
+                "ERROR: Failed to write {}, caused by:\nERROR: {}",
+                manifest_path.to_string_lossy(),
+                err
+            )
+        })?;
+        self.remove_old_images();
+        Ok(())
+    }
+
+    fn remove_old_images(&self) {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(..) => return,
+        };
+        let mut manifests: Vec<_> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .map(|ext| ext == "manifest")
+                    .unwrap_or(false)
+            })
+            .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+            .collect();
+        manifests.sort();
+        let num_old = manifests.len().saturating_sub(MAX_CACHED_IMAGES);
+        for (_, manifest_path) in manifests.into_iter().take(num_old) {
+            let _ =
+                fs::remove_file(manifest_path.with_extension(julia_helper::SYSTEM_IMAGE_EXTENSION));
+            let _ = fs::remove_file(manifest_path);
+        }
+    }
+}
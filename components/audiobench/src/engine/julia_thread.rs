@@ -1,11 +1,25 @@
 use crate::engine::{
+    base::NOTE_EVENT_QUEUE_CAPACITY,
+    codegen::NoteGraphCode,
     data_transfer::{GlobalData, GlobalParameters, IOData},
-    program_wrapper::{AudiobenchExecutor, NoteTracker},
+    image_cache::ImageCache,
+    master_safety::{MasterSafety, MasterSafetySettings},
+    native_executor::NativeExecutor,
+    note_processor::NoteProcessor,
+    program_wrapper::{
+        AudiobenchExecutor, NonFiniteVoice, NoteGraphExecutor, NoteTracker, SharedJulia,
+    },
     Communication,
 };
 use crossbeam_channel::{Receiver, Sender};
 use julia_helper::GeneratedCode;
-use std::sync::Arc;
+use once_cell::sync::OnceCell;
+use shared_util::prelude::*;
+use std::{
+    sync::Arc,
+    thread::Thread,
+    time::{Duration, Instant},
+};
 
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum Status {
@@ -21,112 +35,342 @@ impl Status {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CompileStage {
+    /// Waiting for the Julia thread to have some spare time.
+    Queued,
+    /// Julia is parsing and evaluating the generated code.
+    Loading,
+    /// Julia is compiling the code by running it once. Audio keeps being rendered with the old code
+    /// until this is done.
+    Preheating,
+}
+
+/// Describes the note graph code the Julia thread is currently working on.
+#[derive(Clone, Copy, Debug)]
+pub struct CompileProgress {
+    pub stage: CompileStage,
+    pub started: Instant,
+}
+
+/// If this environment variable is set, Julia is not started and notes are played with
+/// NativeExecutor instead, which only supports some of the Factory modules.
+const NATIVE_BACKEND_VAR: &'static str = "AUDIOBENCH_NATIVE_BACKEND";
+
+struct CompileJob {
+    /// Taken out once the code is loaded.
+    code: Option<NoteGraphCode>,
+    dyn_data: Vec<IOData>,
+    generation: usize,
+    stage: CompileStage,
+    started: Instant,
+    load_time: Duration,
+}
+
+#[derive(Clone, Copy)]
 pub enum NoteEvent {
     StartNote { index: usize, velocity: f32 },
     ReleaseNote { index: usize },
 }
 
 pub struct RenderRequest {
+    /// Used to match up responses with requests, so that audio which took too long to render is
+    /// not played late.
+    pub id: u64,
     pub data: GlobalData,
     pub do_feedback: bool,
 }
 
 pub struct AudioResponse {
+    pub request_id: u64,
+    /// One of the buffers from Communication::free_audio_buffers, it should be put back there once
+    /// the audio has been copied out of it.
     pub audio: Vec<f32>,
-    // feedback_data: Option<Vec<f32>>
 }
 
-pub(super) fn entry(
-    comms: Arc<Communication>,
-    global_params: GlobalParameters,
-    registry_source: GeneratedCode,
-    default_patch_code: GeneratedCode,
-    dyn_data: Vec<IOData>,
-    render_pipe: Receiver<RenderRequest>,
-    poll_pipe: Receiver<()>,
-    audio_response_pipe: Sender<AudioResponse>,
-    error_report_pipe: Sender<String>,
-) {
-    let executor = AudiobenchExecutor::new(registry_source, &global_params).map_err(|err| {
-        format!(
-            "Failed to initialize execution environment! (See message log for details.)\n\n{}",
-            err
-        )
-    });
-    let mut executor = match executor {
-        Ok(value) => value,
-        Err(err) => {
-            error_report_pipe.send(err).unwrap();
-            comms.julia_thread_status.store(Status::Error);
-            panic!("Unrecoverable error.");
+/// Everything the Julia thread needs to serve an instance of the engine.
+pub(super) struct InstanceConnection {
+    pub comms: Arc<Communication>,
+    pub global_params: GlobalParameters,
+    pub registry_source: GeneratedCode,
+    /// Only used if this is the first instance, since Julia can only be started once.
+    pub image_cache: ImageCache,
+    pub default_patch_code: NoteGraphCode,
+    pub dyn_data: Vec<IOData>,
+    pub poll_pipe: Receiver<()>,
+    pub error_report_pipe: Sender<String>,
+    pub compile_log_pipe: Sender<String>,
+    pub non_finite_report_pipe: Sender<NonFiniteVoice>,
+}
+
+struct HostHandle {
+    thread: Thread,
+    new_instances: Sender<InstanceConnection>,
+}
+
+static HOST: OnceCell<HostHandle> = OnceCell::new();
+
+/// Adds an instance to the Julia thread shared by every engine in this process, starting the thread
+/// if necessary. Returns the thread so that the engine can unpark it after giving it work.
+pub(super) fn connect(connection: InstanceConnection) -> Result<Thread, String> {
+    let host = HOST.get_or_init(|| {
+        let (new_instances, receiver) = crossbeam_channel::unbounded();
+        let thread = std::thread::Builder::new()
+            .name("julia_executor".to_owned())
+            .spawn(move || host_entry(receiver))
+            .unwrap()
+            .thread()
+            .clone();
+        HostHandle {
+            thread,
+            new_instances,
         }
+    });
+    host.new_instances.send(connection).map_err(|_| {
+        "The Julia thread stopped unexpectedly, restart the application to try again.".to_owned()
+    })?;
+    host.thread.unpark();
+    Ok(host.thread.clone())
+}
+
+fn host_entry(new_instances: Receiver<InstanceConnection>) {
+    // Julia is started with whatever the first instance needs so that a cached image can be used.
+    let first = match new_instances.recv() {
+        Ok(connection) => connection,
+        Err(..) => return,
+    };
+    let julia = if std::env::var_os(NATIVE_BACKEND_VAR).is_some() {
+        let message = format!(
+            "{} is set, so Julia will not be used. Only some of the Factory modules will work.",
+            NATIVE_BACKEND_VAR
+        );
+        first.compile_log_pipe.send(message).unwrap();
+        None
+    } else {
+        start_julia(&first)
+    };
+
+    let mut host = JuliaHost {
+        julia,
+        instances: Vec::new(),
+        next_instance_id: 0,
+        new_instances,
+        next_render: 0,
+        next_compile: 0,
     };
-    let res = executor
-        .change_generated_code(default_patch_code)
-        .map_err(|err| {
-            format!(
-                "Default patch failed to compile! (See message log for details.)\n\n{}",
+    host.add_instance(first);
+    host.entry();
+}
+
+/// Returns None if Julia failed to start, in which case every instance falls back to
+/// NativeExecutor.
+fn start_julia(first: &InstanceConnection) -> Option<Rcrc<SharedJulia>> {
+    let cached_image = first.image_cache.find_valid_image();
+    let julia = SharedJulia::new(
+        cached_image.as_ref(),
+        &first.global_params,
+        &first.registry_source,
+    );
+    let julia = match julia {
+        Ok(value) => rcrc(value),
+        Err(err) => {
+            let message = format!(
+                concat!(
+                    "Failed to initialize execution environment! Only some of the Factory ",
+                    "modules will work. (See message log for details.)\n\n{}"
+                ),
                 err
-            )
-        });
-    if let Err(err) = res {
-        error_report_pipe.send(err).unwrap();
-        comms.julia_thread_status.store(Status::Error);
-        panic!("Unrecoverable error.");
-    }
-
-    let mut thread = JuliaThread {
-        comms,
-        executor,
-        global_params,
-        dyn_data,
-        notes: NoteTracker::new(),
-        render_pipe,
-        poll_pipe,
-        audio_response_pipe,
-        error_report_pipe,
+            );
+            first.error_report_pipe.send(message).unwrap();
+            return None;
+        }
     };
-    thread.entry();
+    if cached_image.is_none() {
+        match julia.borrow_mut().julia_bin_dir() {
+            Ok(julia_bin_dir) => first.image_cache.spawn_rebuild(
+                julia_bin_dir,
+                SharedJulia::image_code(&first.global_params, &first.registry_source),
+                first.compile_log_pipe.clone(),
+            ),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    Some(julia)
+}
+
+/// Serves every instance of the engine in the process from a single thread, since Julia does not
+/// support being used from multiple threads.
+struct JuliaHost {
+    /// None if notes are being played without Julia.
+    julia: Option<Rcrc<SharedJulia>>,
+    instances: Vec<HostedInstance>,
+    next_instance_id: usize,
+    new_instances: Receiver<InstanceConnection>,
+    /// Where the next search for work starts, so that one busy instance can't starve the others.
+    next_render: usize,
+    next_compile: usize,
+}
+
+impl JuliaHost {
+    fn add_instance(&mut self, connection: InstanceConnection) {
+        let id = self.next_instance_id;
+        self.next_instance_id += 1;
+        if let Some(instance) = HostedInstance::new(self.julia.clone(), id, connection) {
+            instance.set_status(Status::Ready);
+            self.instances.push(instance);
+        }
+    }
+
+    fn entry(&mut self) {
+        loop {
+            while let Ok(connection) = self.new_instances.try_recv() {
+                self.add_instance(connection);
+            }
+            // Forget instances which had unrecoverable errors or whose engines have been dropped.
+            self.instances
+                .retain(|instance| !instance.failed && Arc::strong_count(&instance.comms) > 1);
+            if !self.do_next_task() {
+                // Engines unpark this thread after giving it something to do.
+                std::thread::park();
+            }
+        }
+    }
+
+    /// Returns false if there was nothing to do.
+    fn do_next_task(&mut self) -> bool {
+        let count = self.instances.len();
+        // Rendering takes priority since audio threads are waiting on it. An instance can have
+        // several requests queued when it renders ahead, but only one is taken per turn, so going
+        // round robin serves every waiting instance's oldest request before its next ones.
+        for offset in 0..count {
+            let index = (self.next_render + offset) % count;
+            let instance = &mut self.instances[index];
+            if let Some(request) = instance.comms.render_requests.pop() {
+                self.next_render = (index + 1) % count;
+                instance.render(request);
+                instance.finish_task();
+                return true;
+            }
+        }
+        for instance in &mut self.instances {
+            // The audio thread can't use the poll pipe, so it only sets the flag.
+            let new_global_params = instance.comms.new_global_params.load().is_some();
+            if new_global_params || instance.poll_pipe.try_recv().is_ok() {
+                instance.poll_comms();
+                instance.finish_task();
+                return true;
+            }
+        }
+        // Only one stage of one compile job is done at a time so that render requests are checked
+        // in between.
+        for offset in 0..count {
+            let index = (self.next_compile + offset) % count;
+            let instance = &mut self.instances[index];
+            if instance.compile_job.is_some() {
+                self.next_compile = (index + 1) % count;
+                instance.advance_compile_job();
+                instance.finish_task();
+                return true;
+            }
+        }
+        false
+    }
 }
 
-struct JuliaThread {
+struct HostedInstance {
     comms: Arc<Communication>,
-    executor: AudiobenchExecutor,
+    executor: Box<dyn NoteGraphExecutor>,
     global_params: GlobalParameters,
-    dyn_data: Vec<IOData>,
     notes: NoteTracker,
-    render_pipe: Receiver<RenderRequest>,
+    note_processor: NoteProcessor,
+    master_safety: MasterSafety,
+    /// Reused every render to hold the events taken from Communication::note_events.
+    note_events: Vec<NoteEvent>,
+    /// See Communication::new_watched_modules.
+    watched_modules: Vec<usize>,
+    /// Compiling is split into stages so that audio can be rendered in between them.
+    compile_job: Option<CompileJob>,
     poll_pipe: Receiver<()>,
-    audio_response_pipe: Sender<AudioResponse>,
     error_report_pipe: Sender<String>,
+    compile_log_pipe: Sender<String>,
+    non_finite_report_pipe: Sender<NonFiniteVoice>,
+    /// Set after an unrecoverable error, the host stops serving the instance.
+    failed: bool,
 }
 
-impl JuliaThread {
+impl HostedInstance {
+    fn new(
+        julia: Option<Rcrc<SharedJulia>>,
+        id: usize,
+        connection: InstanceConnection,
+    ) -> Option<Self> {
+        let InstanceConnection {
+            comms,
+            global_params,
+            registry_source,
+            default_patch_code,
+            dyn_data,
+            poll_pipe,
+            error_report_pipe,
+            compile_log_pipe,
+            non_finite_report_pipe,
+            ..
+        } = connection;
+        let executor: Result<Box<dyn NoteGraphExecutor>, String> = match julia {
+            Some(julia) => AudiobenchExecutor::new(julia, id, registry_source, &global_params)
+                .map(|executor| Box::new(executor) as _),
+            None => Ok(Box::new(NativeExecutor::new(&global_params))),
+        };
+        let oversampling = comms.oversampling.load();
+        let executor = executor.and_then(|mut executor| {
+            executor.change_parameters(&global_params, oversampling)?;
+            executor.change_generated_code(default_patch_code, 0, dyn_data)?;
+            Ok(executor)
+        });
+        let executor = match executor {
+            Ok(value) => value,
+            Err(err) => {
+                let message = format!(
+                    "Default patch failed to compile! (See message log for details.)\n\n{}",
+                    err
+                );
+                error_report_pipe.send(message).unwrap();
+                comms.julia_thread_status.store(Status::Error);
+                return None;
+            }
+        };
+        Some(Self {
+            comms,
+            executor,
+            global_params,
+            notes: NoteTracker::new(),
+            note_processor: NoteProcessor::new(),
+            master_safety: MasterSafety::new(&global_params, MasterSafetySettings::disabled()),
+            note_events: Vec::with_capacity(NOTE_EVENT_QUEUE_CAPACITY),
+            watched_modules: Vec::new(),
+            compile_job: None,
+            poll_pipe,
+            error_report_pipe,
+            compile_log_pipe,
+            non_finite_report_pipe,
+            failed: false,
+        })
+    }
+
     fn set_status(&self, status: Status) {
         self.comms.julia_thread_status.store(status);
     }
 
-    fn entry(&mut self) {
-        self.set_status(Status::Ready);
-        loop {
-            crossbeam_channel::select! {
-                recv(self.render_pipe) -> msg => {
-                    if let Ok(request) = msg {
-                        self.render(request.data, request.do_feedback);
-                    } else {
-                        break;
-                    }
-                }
-                recv(self.poll_pipe) -> msg => {
-                    if msg.is_err() {
-                        break;
-                    }
-                    self.poll_comms();
-                }
-            }
+    fn finish_task(&self) {
+        if !self.failed {
             self.set_status(Status::Ready);
         }
-        self.set_status(Status::Error);
+    }
+
+    fn fail(&mut self, message: String) {
+        self.report_julia_error(message);
+        self.comms.compile_progress.store(None);
+        self.failed = true;
     }
 
     fn report_julia_error(&mut self, message: String) {
@@ -138,38 +382,110 @@ impl JuliaThread {
         if let Some(_) = self.comms.new_global_params.take() {
             self.set_status(Status::Busy);
             let params = self.comms.global_params.load();
-            let result = self.executor.change_parameters(&params);
+            let oversampling = self.comms.oversampling.load();
+            let result = self.executor.change_parameters(&params, oversampling);
             if let Err(err) = result {
                 let message = format!(
                     "Failed to load new parameter code, see message log for details.\n\n{}",
                     err
                 );
-                self.report_julia_error(message);
-                panic!("Unrecoverable error.");
+                self.fail(message);
+                return;
             }
             self.global_params = params;
-            self.preheat();
-        } else if let Some((code, dyn_data)) = self.comms.new_note_graph_code.take() {
-            self.set_status(Status::Busy);
+            let safety_settings = self.master_safety.get_settings();
+            self.master_safety = MasterSafety::new(&params, safety_settings);
+            // Reloading the code throws away the static data of every note.
             self.notes.silence_all();
-            self.dyn_data = dyn_data;
-            let res = self.executor.change_generated_code(code);
-            if let Err(err) = res {
-                let message = format!(
-                    "Failed to load new patch code, see message log for details.\n\n{}",
-                    err
-                );
-                self.report_julia_error(message);
-                panic!("Unrecoverable error.");
+            self.note_processor.forget_sounding_notes();
+            self.preheat(self.notes.current_generation());
+        } else if let Some((code, dyn_data, generation)) = self.comms.new_note_graph_code.take() {
+            // Only the latest version of the note graph is worth compiling.
+            if let Some(old_job) = self.compile_job.take() {
+                self.log_compile(format!(
+                    "Stopped compiling an outdated version of the note graph after {:.2}s.",
+                    old_job.started.elapsed().as_secs_f32()
+                ));
             }
-            self.preheat();
+            let job = CompileJob {
+                code: Some(code),
+                dyn_data,
+                generation,
+                stage: CompileStage::Queued,
+                started: Instant::now(),
+                load_time: Duration::default(),
+            };
+            self.publish_compile_progress(&job);
+            self.compile_job = Some(job);
         } else if let Some(data) = self.comms.new_dyn_data.take() {
-            self.dyn_data = data;
+            match &mut self.compile_job {
+                // The data is meant for the code that hasn't been loaded yet.
+                Some(job) if job.stage == CompileStage::Queued => job.dyn_data = data,
+                _ => self.executor.change_dyn_data(data),
+            }
+        }
+    }
+
+    fn log_compile(&self, message: String) {
+        self.compile_log_pipe.send(message).unwrap();
+    }
+
+    fn publish_compile_progress(&self, job: &CompileJob) {
+        self.comms.compile_progress.store(Some(CompileProgress {
+            stage: job.stage,
+            started: job.started,
+        }));
+    }
+
+    /// Does the next stage of the current compile job.
+    fn advance_compile_job(&mut self) {
+        let mut job = self.compile_job.take().unwrap();
+        self.set_status(Status::Busy);
+        match job.stage {
+            CompileStage::Queued | CompileStage::Loading => {
+                job.stage = CompileStage::Loading;
+                self.publish_compile_progress(&job);
+                let code = job.code.take().unwrap();
+                let dyn_data = std::mem::take(&mut job.dyn_data);
+                let res = self
+                    .executor
+                    .change_generated_code(code, job.generation, dyn_data);
+                if let Err(err) = res {
+                    let message = format!(
+                        "Failed to load new patch code, see message log for details.\n\n{}",
+                        err
+                    );
+                    self.fail(message);
+                    return;
+                }
+                job.load_time = job.started.elapsed();
+                job.stage = CompileStage::Preheating;
+                self.publish_compile_progress(&job);
+                self.compile_job = Some(job);
+            }
+            CompileStage::Preheating => {
+                self.preheat(job.generation);
+                // Notes that are already playing keep using the old code while new ones fade in.
+                let crossfade_time = self.comms.crossfade_time.load();
+                let crossfade_samples =
+                    (crossfade_time * self.global_params.sample_rate as f32) as usize;
+                self.notes
+                    .switch_generation(job.generation, crossfade_samples);
+                self.comms.loaded_generation.store(job.generation);
+                self.comms.compile_progress.store(None);
+                let total_time = job.started.elapsed();
+                self.log_compile(format!(
+                    "Compiled the note graph in {:.2}s ({:.2}s loading, {:.2}s preheating).",
+                    total_time.as_secs_f32(),
+                    job.load_time.as_secs_f32(),
+                    (total_time - job.load_time).as_secs_f32()
+                ));
+            }
         }
     }
 
-    fn preheat(&mut self) {
-        let result = self.executor.preheat(&mut self.notes, &self.dyn_data[..]);
+    fn preheat(&mut self, generation: usize) {
+        let result = self.executor.preheat(generation);
         if let Err(err) = result {
             let message = format!(
                 "Encountered Julia error while executing, see message log for details.\n\n{}",
@@ -181,29 +497,54 @@ impl JuliaThread {
         }
     }
 
-    fn render(&mut self, global_data: GlobalData, do_feedback: bool) {
+    fn render(&mut self, request: RenderRequest) {
         self.set_status(Status::Rendering);
-        let view_index = self.comms.module_view_index.load();
-        let mut nel = self.comms.note_events.lock().unwrap();
-        let note_events = std::mem::take(&mut *nel);
-        drop(nel);
+        let RenderRequest {
+            id,
+            data: global_data,
+            do_feedback,
+        } = request;
+        if let Some(watched_modules) = self.comms.new_watched_modules.take() {
+            self.watched_modules = watched_modules;
+        }
+        if let Some(voice) = self.comms.new_feedback_voice.take() {
+            self.notes.set_feedback_voice(voice);
+        }
+        self.note_events.clear();
+        while let Some(event) = self.comms.note_events.pop() {
+            self.note_events.push(event);
+        }
         self.notes.set_dummy_note_active(
             self.comms.do_dummy_note.load() || self.comms.do_dummy_note_once.load(),
         );
-        for event in note_events {
-            match event {
-                NoteEvent::StartNote { index, velocity } => self.notes.start_note(index, velocity),
-                NoteEvent::ReleaseNote { index } => self.notes.release_note(index),
-            }
+        if let Some(settings) = self.comms.new_note_processor_settings.take() {
+            self.note_processor.set_settings(settings);
         }
+        if let Some(settings) = self.comms.new_master_safety_settings.take() {
+            self.master_safety.set_settings(settings);
+        }
+        self.note_processor.process(
+            &self.note_events[..],
+            &global_data,
+            &self.global_params,
+            &mut self.notes,
+        );
 
-        let mut output = vec![0.0; self.global_params.channels * self.global_params.buffer_length];
+        // The pool is sized so that there is always a free buffer, see AUDIO_BUFFER_POOL_SIZE.
+        // Allocating another one here could leave the audio thread with nowhere to put it.
+        let mut output = match self.comms.free_audio_buffers.pop() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        output.resize(
+            self.global_params.channels * self.global_params.buffer_length,
+            0.0,
+        );
         let result = self.executor.execute(
             do_feedback,
-            view_index,
+            &self.watched_modules[..],
             &global_data,
             &mut self.notes,
-            &self.dyn_data[..],
             &mut output[..],
         );
         let new_feedback_data = match result {
@@ -219,12 +560,26 @@ impl JuliaThread {
                 None
             }
         };
+        self.master_safety.process(&mut output[..]);
+        self.comms
+            .master_gain_reduction
+            .store(self.master_safety.get_gain_reduction());
+        for voice in self.executor.take_non_finite_voices() {
+            // The UI turns these into warnings, if it has stopped listening nobody cares.
+            let _ = self.non_finite_report_pipe.send(voice);
+        }
         if new_feedback_data.is_some() {
             self.comms.do_dummy_note_once.store(false);
             self.comms.new_feedback.store(new_feedback_data);
         }
-        self.audio_response_pipe
-            .send(AudioResponse { audio: output })
-            .unwrap();
+        let response = AudioResponse {
+            request_id: id,
+            audio: output,
+        };
+        // This can only fail if the audio thread has stopped collecting responses, in which case
+        // nobody is interested in the audio anyway.
+        if let Err(response) = self.comms.rendered_audio.push(response) {
+            let _ = self.comms.free_audio_buffers.push(response.audio);
+        }
     }
 }
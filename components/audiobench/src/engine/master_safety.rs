@@ -0,0 +1,273 @@
+//! Processing applied to the final mix of all notes before it is handed to the host, to protect
+//! ears and speakers from patches that clip or drift away from zero.
+
+use crate::engine::data_transfer::GlobalParameters;
+use shared_util::mini_serde::{MiniDes, MiniSer};
+
+/// The lowest ceiling that can be stored in a patch, in dBFS.
+pub const MIN_CEILING: f32 = -12.0;
+/// Cutoff of the highpass filter used to remove DC offset.
+const DC_BLOCKER_CUTOFF: f32 = 10.0;
+/// How far ahead the limiter looks for peaks, this is also how much latency it adds.
+const LIMITER_LOOKAHEAD: f32 = 0.0015;
+/// How long the limiter takes to recover about two thirds of the gain it removed.
+const LIMITER_RELEASE: f32 = 0.1;
+/// The soft clipper is linear up to this fraction of the ceiling.
+const SOFT_CLIP_KNEE: f32 = 0.8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MasterSafetySettings {
+    /// Turns off every stage when false.
+    pub enabled: bool,
+    /// Removes DC offset with a highpass filter just below the audible range.
+    pub dc_blocker: bool,
+    /// Turns down the output before any peak, including peaks between samples, goes above the
+    /// ceiling.
+    pub limiter: bool,
+    /// Smoothly saturates whatever gets past the limiter so that nothing can exceed the ceiling.
+    pub soft_clip: bool,
+    /// The loudest the output is allowed to be, in dBFS.
+    pub ceiling: f32,
+}
+
+impl Default for MasterSafetySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dc_blocker: true,
+            limiter: true,
+            soft_clip: true,
+            ceiling: -1.0,
+        }
+    }
+}
+
+impl MasterSafetySettings {
+    /// Settings for patches saved before the safety stage existed, so that they sound the same as
+    /// they always did.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+
+    /// How many samples the safety stage delays the output by at the specified sample rate.
+    pub fn latency(&self, sample_rate: usize) -> usize {
+        if self.enabled && self.limiter {
+            lookahead_samples(sample_rate)
+        } else {
+            0
+        }
+    }
+
+    pub fn serialize(&self, ser: &mut MiniSer) {
+        ser.note("Master safety: ");
+        ser.bool(self.enabled);
+        ser.bool(self.dc_blocker);
+        ser.bool(self.limiter);
+        ser.bool(self.soft_clip);
+        ser.f32_in_range(self.ceiling, MIN_CEILING, 0.0);
+    }
+
+    pub fn deserialize(des: &mut MiniDes) -> Result<Self, ()> {
+        Ok(Self {
+            enabled: des.bool()?,
+            dc_blocker: des.bool()?,
+            limiter: des.bool()?,
+            soft_clip: des.bool()?,
+            ceiling: des.f32_in_range(MIN_CEILING, 0.0)?,
+        })
+    }
+
+    fn linear_ceiling(&self) -> f32 {
+        10f32.powf(self.ceiling / 20.0)
+    }
+}
+
+/// Estimates the highest absolute value the continuous signal reaches between the middle two of
+/// four consecutive samples, using Catmull-Rom interpolation.
+fn true_peak(history: &[f32; 4]) -> f32 {
+    let [a, b, c, d] = *history;
+    let mut peak = b.abs().max(c.abs());
+    for &t in &[0.25, 0.5, 0.75] {
+        let t2 = t * t;
+        let t3 = t2 * t;
+        let value = 0.5
+            * (2.0 * b
+                + (c - a) * t
+                + (2.0 * a - 5.0 * b + 4.0 * c - d) * t2
+                + (3.0 * b - a - 3.0 * c + d) * t3);
+        peak = peak.max(value.abs());
+    }
+    peak
+}
+
+fn soft_clip(value: f32, ceiling: f32) -> f32 {
+    let knee = ceiling * SOFT_CLIP_KNEE;
+    let magnitude = value.abs();
+    if magnitude <= knee {
+        value
+    } else {
+        let range = ceiling - knee;
+        (knee + range * ((magnitude - knee) / range).tanh()).copysign(value)
+    }
+}
+
+/// Applies MasterSafetySettings to audio. Everything it needs is allocated up front, so it can be
+/// used while rendering.
+pub struct MasterSafety {
+    settings: MasterSafetySettings,
+    channels: usize,
+    dc_coefficient: f32,
+    /// The last input and output of the DC blocker for each channel.
+    dc_state: Vec<(f32, f32)>,
+    /// The last four samples of each channel, used to find peaks between samples.
+    peak_history: Vec<[f32; 4]>,
+    /// How many frames the limiter looks ahead, at least 1.
+    lookahead: usize,
+    release_coefficient: f32,
+    /// Frames waiting to be output by the limiter, interleaved like the output.
+    delay: Vec<f32>,
+    /// The gain each of the last `lookahead + 1` frames needs to stay under the ceiling.
+    target_gains: Vec<f32>,
+    target_position: usize,
+    /// The gain after release smoothing for each of the last `lookahead` frames.
+    held_gains: Vec<f32>,
+    /// Where the next frame goes in `delay` and `held_gains`.
+    position: usize,
+    last_held_gain: f32,
+    /// How much the limiter turned down the last buffer, in dB.
+    gain_reduction: f32,
+}
+
+fn lookahead_samples(sample_rate: usize) -> usize {
+    ((LIMITER_LOOKAHEAD * sample_rate as f32) as usize).max(1)
+}
+
+impl MasterSafety {
+    pub fn new(params: &GlobalParameters, settings: MasterSafetySettings) -> Self {
+        let sample_rate = params.sample_rate as f32;
+        let lookahead = lookahead_samples(params.sample_rate);
+        let mut this = Self {
+            settings,
+            channels: params.channels,
+            dc_coefficient: (-2.0 * std::f32::consts::PI * DC_BLOCKER_CUTOFF / sample_rate).exp(),
+            dc_state: vec![(0.0, 0.0); params.channels],
+            peak_history: vec![[0.0; 4]; params.channels],
+            lookahead,
+            release_coefficient: (-1.0 / (LIMITER_RELEASE * sample_rate)).exp(),
+            delay: vec![0.0; lookahead * params.channels],
+            target_gains: vec![1.0; lookahead + 1],
+            target_position: 0,
+            held_gains: vec![1.0; lookahead],
+            position: 0,
+            last_held_gain: 1.0,
+            gain_reduction: 0.0,
+        };
+        this.reset();
+        this
+    }
+
+    /// Forgets all previous audio, E.G. after the notes were silenced.
+    pub fn reset(&mut self) {
+        for state in &mut self.dc_state {
+            *state = (0.0, 0.0);
+        }
+        for history in &mut self.peak_history {
+            *history = [0.0; 4];
+        }
+        for sample in &mut self.delay {
+            *sample = 0.0;
+        }
+        for gain in self
+            .target_gains
+            .iter_mut()
+            .chain(self.held_gains.iter_mut())
+        {
+            *gain = 1.0;
+        }
+        self.last_held_gain = 1.0;
+        self.gain_reduction = 0.0;
+    }
+
+    pub fn get_settings(&self) -> MasterSafetySettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: MasterSafetySettings) {
+        // The limiter's delay line holds old audio which would play again after turning it back on.
+        if settings.limiter != self.settings.limiter || settings.enabled != self.settings.enabled {
+            self.reset();
+        }
+        self.settings = settings;
+    }
+
+    /// How many dB the limiter turned the last processed buffer down by at most.
+    pub fn get_gain_reduction(&self) -> f32 {
+        self.gain_reduction
+    }
+
+    /// Processes interleaved audio in place.
+    pub fn process(&mut self, audio: &mut [f32]) {
+        self.gain_reduction = 0.0;
+        if !self.settings.enabled {
+            return;
+        }
+        let ceiling = self.settings.linear_ceiling();
+        let mut min_gain = 1.0f32;
+        for frame in audio.chunks_exact_mut(self.channels) {
+            if self.settings.dc_blocker {
+                for (sample, state) in frame.iter_mut().zip(self.dc_state.iter_mut()) {
+                    let output = *sample - state.0 + self.dc_coefficient * state.1;
+                    *state = (*sample, output);
+                    *sample = output;
+                }
+            }
+            if self.settings.limiter {
+                min_gain = min_gain.min(self.limit_frame(frame, ceiling));
+            }
+            if self.settings.soft_clip {
+                for sample in frame.iter_mut() {
+                    *sample = soft_clip(*sample, ceiling);
+                }
+            }
+        }
+        self.gain_reduction = -20.0 * min_gain.log10();
+    }
+
+    /// Swaps the frame with the one from `lookahead` frames ago, turned down so that no peak in
+    /// between goes above the ceiling. Returns the gain that was used.
+    fn limit_frame(&mut self, frame: &mut [f32], ceiling: f32) -> f32 {
+        let mut peak = 0.0f32;
+        for (sample, history) in frame.iter().zip(self.peak_history.iter_mut()) {
+            history.rotate_left(1);
+            history[3] = *sample;
+            peak = peak.max(true_peak(history)).max(sample.abs());
+        }
+        self.target_gains[self.target_position] = if peak > ceiling { ceiling / peak } else { 1.0 };
+        self.target_position = (self.target_position + 1) % self.target_gains.len();
+        // The gain has to be low enough for every peak that will be output within the next
+        // `lookahead` frames. Averaging these minimums afterwards fades the gain in smoothly while
+        // still reaching the target by the time the peak is output.
+        let target = self.target_gains.iter().cloned().fold(1.0, f32::min);
+        let held = if target < self.last_held_gain {
+            target
+        } else {
+            target + (self.last_held_gain - target) * self.release_coefficient
+        };
+        self.last_held_gain = held;
+        let position = self.position;
+        self.held_gains[position] = held;
+        let gain = self.held_gains.iter().sum::<f32>() / self.lookahead as f32;
+
+        let delayed = &mut self.delay[position * self.channels..(position + 1) * self.channels];
+        for (sample, delayed) in frame.iter_mut().zip(delayed.iter_mut()) {
+            let output = *delayed * gain;
+            *delayed = *sample;
+            *sample = output;
+        }
+        self.position = (position + 1) % self.lookahead;
+        gain
+    }
+}
@@ -1,10 +1,17 @@
+pub mod audio_sample;
 mod base;
 mod codegen;
 pub mod controls;
 pub mod data_transfer;
+mod image_cache;
 mod julia_thread;
+pub mod master_safety;
+mod native_executor;
+pub mod note_processor;
+pub mod oversampling;
 pub mod parts;
 mod program_wrapper;
 
 pub use base::*;
-pub use julia_thread::Status;
+pub use julia_thread::{CompileProgress, CompileStage, Status};
+pub use program_wrapper::FeedbackVoice;
@@ -0,0 +1,1090 @@
+use crate::{
+    engine::{
+        codegen::NoteGraphCode,
+        controls::{AnyControl, AutomationSource, BuiltinWaveform, Control},
+        data_transfer::{FeedbackData, GlobalData, GlobalParameters, IOData},
+        oversampling::Decimator,
+        parts::{JackType, ModuleGraph},
+        program_wrapper::{
+            ModuleProfiler, NonFiniteVoice, NoteGraphExecutor, NoteInput, NoteTracker,
+        },
+    },
+    gui::module_widgets::FeedbackMode,
+    registry::module_template::ModuleTemplate,
+};
+use std::time::Instant;
+
+/// Same as the oversampling in Oscillator.module.jl.
+const OSCILLATOR_OVERSAMPLING: usize = 4;
+/// Same as default_graph_resolution in the Factory library.
+const GRAPH_RESOLUTION: usize = 42;
+/// The most controls any of the modules in ModuleKind has, which is _4xMixer.
+const MAX_CONTROLS: usize = 8;
+/// Used instead of random values when drawing the noise graph so that it doesn't flicker, copied
+/// from Noise.module.jl.
+#[rustfmt::skip]
+const DUMMY_NOISE: [f32; 43] = [
+    0.3988945908, 0.8954911673, 0.0116554042, 0.0909389386, 0.0893340926, 0.4953123474,
+    0.5784687653, 0.2548134842, 0.1776265054, 0.3360827756, 0.3734218081, 0.6334027459,
+    0.8120340729, 0.1525260985, 0.0720461340, 0.3180398718, 0.3208139232, 0.9439490845,
+    0.0996337096, 0.3485065303, 0.7917933350, 0.8462610756, 0.4970552639, 0.9443231657,
+    0.1459758690, 0.1334774229, 0.0101744474, 0.2696308750, 0.1566415042, 0.2585378565,
+    0.3350715841, 0.6044406241, 0.0164770681, 0.5227222970, 0.3939237240, 0.1516453785,
+    0.7058609147, 0.4322837979, 0.3666769617, 0.9135396160, 0.7535281491, 0.1228587420,
+    0.0,
+];
+
+/// The Factory modules NativeExecutor knows how to run. Each one behaves the same as its Julia
+/// source, so patches sound the same whichever executor plays them (apart from Noise, which is
+/// random either way.)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ModuleKind {
+    Oscillator,
+    Envelope,
+    Lfo,
+    Amplifier,
+    /// Both _2xMixer and _4xMixer, their controls are pairs of inputs and amplitudes.
+    Mixer,
+    NoteOutput,
+    Noise,
+}
+
+impl ModuleKind {
+    fn from_template(template: &ModuleTemplate) -> Option<Self> {
+        if template.lib_name != "Factory" {
+            return None;
+        }
+        Some(match &template.module_name[..] {
+            "Oscillator" => Self::Oscillator,
+            "Envelope" => Self::Envelope,
+            "LFO" => Self::Lfo,
+            "Amplifier" => Self::Amplifier,
+            "_2xMixer" | "_4xMixer" => Self::Mixer,
+            "NoteOutput" => Self::NoteOutput,
+            "Noise" => Self::Noise,
+            _ => return None,
+        })
+    }
+}
+
+/// Where the value of a control comes from while the graph is running.
+#[derive(Clone, Debug)]
+enum ControlSource {
+    /// The output of another module, as an index into the list of signals.
+    Signal(usize),
+    NotePitch,
+    Silence,
+    Waveform(BuiltinWaveform),
+    StartTrigger,
+    ReleaseTrigger,
+    NeverTrigger,
+    /// A FloatInRange control. The item of dyn data contains its value followed by the scale and
+    /// offset of each automation lane.
+    Float {
+        dyn_data: usize,
+        lanes: Vec<usize>,
+    },
+    /// A control whose value is a single item of dyn data, like Duration or OptionChoice.
+    Parameter(usize),
+}
+
+impl ControlSource {
+    /// Interprets the code of a DefaultInputDescription.
+    fn from_default_code(code: &str) -> Option<Self> {
+        if let Some(waveform) = BuiltinWaveform::from_code(code) {
+            return Some(Self::Waveform(waveform));
+        }
+        Some(match code {
+            "StaticControlSignal(note_input.pitch)" => Self::NotePitch,
+            "StaticMonoAudio(0f0)" | "StaticControlSignal(0f0)" => Self::Silence,
+            "start_trigger" => Self::StartTrigger,
+            "release_trigger" => Self::ReleaseTrigger,
+            "StaticTrigger(false)" => Self::NeverTrigger,
+            _ => return None,
+        })
+    }
+
+    /// `automation` is where mix_automation left the value of the control.
+    fn evaluate<'a>(
+        &self,
+        dyn_data: &[IOData],
+        signals: &'a [Vec<f32>],
+        automation: &'a [f32],
+        context: &NoteContext,
+    ) -> Value<'a> {
+        match self {
+            Self::Signal(index) => Value::Signal(&signals[*index][..]),
+            Self::NotePitch => Value::Constant(context.note.pitch),
+            Self::Silence => Value::Constant(0.0),
+            Self::Waveform(waveform) => Value::Waveform(*waveform),
+            Self::StartTrigger => Value::TriggerAtStart(context.note.start_trigger),
+            Self::ReleaseTrigger => Value::TriggerAtStart(context.note.release_trigger),
+            Self::NeverTrigger => Value::TriggerAtStart(false),
+            Self::Float {
+                dyn_data: index,
+                lanes,
+            } => match &dyn_data[*index] {
+                IOData::FloatArray(_) if !lanes.is_empty() => Value::Signal(automation),
+                IOData::FloatArray(values) => Value::Constant(values[0]),
+                _ => Value::Constant(0.0),
+            },
+            Self::Parameter(index) => match &dyn_data[*index] {
+                IOData::Float(value) => Value::Constant(*value),
+                IOData::Int(value) => Value::Int(*value),
+                _ => Value::Int(0),
+            },
+        }
+    }
+
+    /// Mixes together the automation lanes of a FloatInRange control, does nothing for other
+    /// controls.
+    fn mix_automation(
+        &self,
+        dyn_data: &[IOData],
+        signals: &[Vec<f32>],
+        buffer_length: usize,
+        result: &mut Vec<f32>,
+    ) {
+        let (index, lanes) = match self {
+            Self::Float {
+                dyn_data: index,
+                lanes,
+            } if !lanes.is_empty() => (*index, lanes),
+            _ => return,
+        };
+        let values = match &dyn_data[index] {
+            IOData::FloatArray(values) => values,
+            _ => return,
+        };
+        result.clear();
+        result.resize(buffer_length, 0.0);
+        for (lane_index, signal) in lanes.iter().enumerate() {
+            let scale = values[1 + lane_index * 2];
+            let offset = values[2 + lane_index * 2];
+            for (value, automation) in result.iter_mut().zip(signals[*signal].iter()) {
+                *value += automation * scale + offset;
+            }
+        }
+        let num_lanes = lanes.len() as f32;
+        for value in result {
+            *value /= num_lanes;
+        }
+    }
+}
+
+/// The value of a control while its module is running. Like static signals in the Julia code,
+/// constants have the same value for the whole buffer.
+#[derive(Clone, Copy)]
+enum Value<'a> {
+    Signal(&'a [f32]),
+    Constant(f32),
+    /// A trigger which can only fire on the first sample of the buffer.
+    TriggerAtStart(bool),
+    Waveform(BuiltinWaveform),
+    Int(i32),
+}
+
+// describe_graph only accepts modules whose controls have the types these expect, so the
+// fallbacks are never used.
+impl<'a> Value<'a> {
+    fn signal(&self, sample: usize) -> f32 {
+        match self {
+            Self::Signal(values) => values[sample],
+            Self::Constant(value) => *value,
+            _ => 0.0,
+        }
+    }
+
+    fn trigger(&self, sample: usize) -> bool {
+        match self {
+            Self::TriggerAtStart(value) => *value && sample == 0,
+            _ => false,
+        }
+    }
+
+    fn waveform(&self) -> BuiltinWaveform {
+        match self {
+            Self::Waveform(waveform) => *waveform,
+            _ => BuiltinWaveform::Flat,
+        }
+    }
+
+    fn int(&self) -> i32 {
+        match self {
+            Self::Int(value) => *value,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum FeedbackSource {
+    /// The first sample of one of the module's controls.
+    Control(usize),
+    /// Whatever the module pushes to graph_feedback in its Julia source.
+    Graph,
+}
+
+#[derive(Clone, Debug)]
+struct NativeModule {
+    kind: ModuleKind,
+    /// Position of the module in the graph, which is what view indexes refer to.
+    graph_index: usize,
+    controls: Vec<ControlSource>,
+    /// Index of the module's output in the list of signals, if it has one.
+    output: Option<usize>,
+    /// Control outputs are viewed as their latest value instead of as a waveform.
+    output_is_control: bool,
+    /// Index of each feedback widget of the module in FeedbackData::widget_feeback.
+    feedback: Vec<(usize, FeedbackSource)>,
+}
+
+/// A description of a note graph which NativeExecutor can run without needing Julia.
+#[derive(Clone, Debug)]
+pub(super) struct NativeGraph {
+    /// In execution order.
+    modules: Vec<NativeModule>,
+    num_signals: usize,
+    num_feedback_widgets: usize,
+    /// Indexes in FeedbackData::widget_feeback of the widgets which take data from every voice.
+    voice_feedback_widgets: Vec<usize>,
+}
+
+fn unsupported_error(template: &ModuleTemplate) -> String {
+    format!(
+        "ERROR: The {} module ({}:{}) can only be used when Julia is available.",
+        template.label, template.lib_name, template.module_name
+    )
+}
+
+/// Returns an error if the graph uses modules or connections that NativeExecutor does not
+/// support. The execution order must be the same one used to collect dyn data.
+pub(super) fn describe_graph(
+    graph: &ModuleGraph,
+    execution_order: &[usize],
+) -> Result<NativeGraph, String> {
+    let modules = graph.borrow_modules();
+    // Every output of every module gets its own signal, feedback widgets are numbered the same way
+    // codegen numbers them.
+    let mut first_signals = Vec::with_capacity(modules.len());
+    let mut first_feedback_widgets = Vec::with_capacity(modules.len());
+    let mut num_signals = 0;
+    let mut num_feedback_widgets = 0;
+    for module in modules {
+        let module = module.borrow();
+        let template = module.template.borrow();
+        first_signals.push(num_signals);
+        first_feedback_widgets.push(num_feedback_widgets);
+        num_signals += template.outputs.len();
+        num_feedback_widgets += template
+            .widget_outlines
+            .iter()
+            .filter(|outline| outline.get_feedback_mode() != FeedbackMode::None)
+            .count();
+    }
+    let signal_of = |source: &AutomationSource| -> Result<usize, String> {
+        if let JackType::Pitch | JackType::Waveform | JackType::Trigger = source.get_type() {
+            return Err(
+                "ERROR: Only audio and control connections can be used when Julia is not available."
+                    .to_owned(),
+            );
+        }
+        // The graph contains every module that can be connected to.
+        let module_index = graph.index_of_module(&source.module).unwrap();
+        Ok(first_signals[module_index] + source.output_index)
+    };
+
+    let mut described = Vec::with_capacity(execution_order.len());
+    let mut voice_feedback_widgets = Vec::new();
+    let mut next_dyn_data = 0;
+    for &index in execution_order {
+        let module = modules[index].borrow();
+        let template = module.template.borrow();
+        let kind =
+            ModuleKind::from_template(&*template).ok_or_else(|| unsupported_error(&*template))?;
+        if module.controls.len() > MAX_CONTROLS {
+            return Err(unsupported_error(&*template));
+        }
+        let mut controls = Vec::with_capacity(module.controls.len());
+        for control in &module.controls {
+            let dyn_data = next_dyn_data;
+            next_dyn_data += control.as_dyn_ptr().borrow().get_parameter_types().len();
+            controls.push(match control {
+                AnyControl::Input(input) => {
+                    let input = input.borrow();
+                    if let Some(default) = input.get_used_default() {
+                        ControlSource::from_default_code(default.code)
+                            .ok_or_else(|| unsupported_error(&*template))?
+                    } else {
+                        ControlSource::Signal(signal_of(input.get_connected_automation()[0])?)
+                    }
+                }
+                AnyControl::FloatInRange(control) => {
+                    let control = control.borrow();
+                    let mut lanes = Vec::with_capacity(control.automation.len());
+                    for lane in &control.automation {
+                        lanes.push(signal_of(&lane.connection)?);
+                    }
+                    ControlSource::Float { dyn_data, lanes }
+                }
+                AnyControl::Duration(..)
+                | AnyControl::TimingMode(..)
+                | AnyControl::OptionChoice(..) => ControlSource::Parameter(dyn_data),
+                _ => return Err(unsupported_error(&*template)),
+            });
+        }
+        let mut feedback = Vec::new();
+        let mut next_widget = first_feedback_widgets[index];
+        for outline in &template.widget_outlines {
+            let source = match outline.get_feedback_mode() {
+                FeedbackMode::None => continue,
+                FeedbackMode::ControlSignal { control_index } => {
+                    FeedbackSource::Control(control_index)
+                }
+                FeedbackMode::ManualValue { .. } => FeedbackSource::Graph,
+                FeedbackMode::ManualValuePerVoice { .. } => {
+                    voice_feedback_widgets.push(next_widget);
+                    FeedbackSource::Graph
+                }
+            };
+            feedback.push((next_widget, source));
+            next_widget += 1;
+        }
+        described.push(NativeModule {
+            kind,
+            graph_index: index,
+            controls,
+            output: if !template.outputs.is_empty() {
+                Some(first_signals[index])
+            } else {
+                None
+            },
+            output_is_control: template
+                .outputs
+                .iter()
+                .any(|output| output.get_type() == JackType::Control),
+            feedback,
+        });
+    }
+
+    // Modules were described in execution order, but voice data is sent in the order of the graph.
+    voice_feedback_widgets.sort_unstable();
+    Ok(NativeGraph {
+        modules: described,
+        num_signals,
+        num_feedback_widgets,
+        voice_feedback_widgets,
+    })
+}
+
+/// Everything a module can know about the note it is playing, like NoteContext in the Julia code.
+struct NoteContext<'a> {
+    parameters: &'a GlobalParameters,
+    global: &'a GlobalData,
+    note: NoteInput,
+}
+
+impl<'a> NoteContext<'a> {
+    /// Same as get_timing in the Factory library, the result is written to `result`.
+    fn timing(&self, mode: i32, result: &mut Vec<f32>) {
+        let global_source = mode & 0b1 == 0b1;
+        let bar_source = global_source && mode & 0b100 == 0b100;
+        let beat_units = mode & 0b10 == 0b10;
+        let global = self.global;
+        let seconds_per_beat = 60.0 / global.bpm;
+        let mut value = if bar_source {
+            let beats_into_bar = global.elapsed_beats - global.bar_start_beats;
+            if beat_units {
+                beats_into_bar
+            } else {
+                beats_into_bar * seconds_per_beat
+            }
+        } else if global_source {
+            if beat_units {
+                global.elapsed_beats
+            } else {
+                global.elapsed_time
+            }
+        } else if beat_units {
+            self.note.elapsed_beats
+        } else {
+            self.note.elapsed_time
+        };
+        let sample_rate = self.parameters.sample_rate as f32;
+        let per_sample = if global_source && !global.playing {
+            0.0
+        } else if beat_units {
+            global.bpm / 60.0 / sample_rate
+        } else {
+            1.0 / sample_rate
+        };
+        let bar_length = if beat_units {
+            global.beats_per_bar()
+        } else {
+            global.beats_per_bar() * seconds_per_beat
+        };
+        result.clear();
+        for _ in 0..self.parameters.buffer_length {
+            result.push(value);
+            value += per_sample;
+            if bar_source && value >= bar_length {
+                value -= bar_length;
+            }
+        }
+    }
+}
+
+fn lerp(from: f32, to: f32, amount: f32) -> f32 {
+    to * amount + from * (1.0 - amount)
+}
+
+fn apply_lfo_strength(value: f32, strength: f32, mode: i32) -> f32 {
+    match mode {
+        0 => value * strength + (1.0 - strength),
+        1 => value * strength,
+        _ => value * strength - (1.0 - strength),
+    }
+}
+
+/// The equivalent of StaticData in the Julia code, each module only uses the fields it needs.
+#[derive(Clone, Copy, Debug)]
+struct ModuleState {
+    phase: f32,
+    start: f32,
+    releasing: bool,
+    last_value: f32,
+    old_value: f32,
+    new_value: f32,
+    old_value_time: f32,
+}
+
+impl ModuleState {
+    fn new() -> Self {
+        Self {
+            phase: 0.0,
+            start: -1000.0,
+            releasing: false,
+            last_value: 0.0,
+            old_value: 0.0,
+            new_value: 0.0,
+            old_value_time: 0.0,
+        }
+    }
+}
+
+/// Where a module puts the values shown by its graph widget.
+struct GraphFeedback<'a> {
+    data: &'a mut Vec<f32>,
+    /// False when only the values describing the module's own voice are wanted, like do_feedback
+    /// in the Julia source.
+    full: bool,
+}
+
+/// Where a module writes its results. The output should be overwritten, while note output is
+/// shared by the whole note graph.
+struct ModuleOutputs<'a> {
+    output: &'a mut [f32],
+    note_output: &'a mut [f32],
+}
+
+/// Runs a single module on a single note. Timing is scratch space for modules which need
+/// NoteContext::timing. Graph feedback is only provided when feedback was requested.
+fn run_module(
+    kind: ModuleKind,
+    inputs: &[Value],
+    state: &mut ModuleState,
+    context: &NoteContext,
+    outputs: ModuleOutputs,
+    timing: &mut Vec<f32>,
+    graph_feedback: Option<GraphFeedback>,
+) {
+    let ModuleOutputs {
+        output,
+        note_output,
+    } = outputs;
+    let buffer_length = output.len();
+    let sample_rate = context.parameters.sample_rate as f32;
+    match kind {
+        ModuleKind::Oscillator => {
+            let (pitch, waveform, amplitude) = (&inputs[0], inputs[1].waveform(), &inputs[2]);
+            let oversampling = OSCILLATOR_OVERSAMPLING as f32;
+            for s in 0..buffer_length {
+                let mut sample = 0.0;
+                let phase_delta = pitch.signal(s) / sample_rate / oversampling;
+                for _ in 0..OSCILLATOR_OVERSAMPLING {
+                    sample += waveform.at(state.phase);
+                    state.phase = (state.phase + phase_delta) % 1.0;
+                }
+                output[s] = sample * amplitude.signal(s) / oversampling;
+            }
+        }
+        ModuleKind::Envelope => {
+            let (reset_trigger, release_trigger, sustain) = (&inputs[0], &inputs[1], &inputs[2]);
+            let timing_mode = inputs[3].int();
+            let (attack_time, decay_time, release_time) = (&inputs[4], &inputs[5], &inputs[6]);
+            context.timing(timing_mode, timing);
+            for s in 0..buffer_length {
+                if !state.releasing {
+                    if reset_trigger.trigger(s) {
+                        state.start = timing[s];
+                    }
+                    if release_trigger.trigger(s) {
+                        state.start = timing[s];
+                        state.releasing = true;
+                    }
+                }
+                let mut now = timing[s] - state.start;
+                let value = if state.releasing {
+                    if now < release_time.signal(s) {
+                        lerp(state.last_value, 0.0, now / release_time.signal(s))
+                    } else {
+                        0.0
+                    }
+                } else {
+                    let value = if now < attack_time.signal(s) {
+                        now / attack_time.signal(s)
+                    } else {
+                        now -= attack_time.signal(s);
+                        if now < decay_time.signal(s) {
+                            lerp(1.0, sustain.signal(s), now / decay_time.signal(s))
+                        } else {
+                            sustain.signal(s)
+                        }
+                    };
+                    state.last_value = value;
+                    value
+                };
+                output[s] = value * 2.0 - 1.0;
+            }
+            if let Some(graph_feedback) = graph_feedback {
+                let attack = attack_time.signal(0);
+                let decay = decay_time.signal(0);
+                let release = release_time.signal(0);
+                let mut now_time = timing[0] - state.start;
+                if state.releasing {
+                    now_time = (now_time + attack + decay).min(attack + decay + release);
+                } else if now_time > attack + decay {
+                    now_time = attack + decay;
+                }
+                let multiplier = if timing_mode & 0b10 == 0b10 {
+                    60.0 / context.global.bpm
+                } else {
+                    1.0
+                };
+                let data = graph_feedback.data;
+                data.extend_from_slice(&[now_time * multiplier, output[0]]);
+                if graph_feedback.full {
+                    data.extend_from_slice(&[
+                        attack * multiplier,
+                        decay * multiplier,
+                        sustain.signal(0),
+                        release * multiplier,
+                    ]);
+                }
+            }
+        }
+        ModuleKind::Lfo => {
+            let (waveform, strength, offset) = (inputs[0].waveform(), &inputs[1], &inputs[2]);
+            let (timing_mode, cycle_time) = (inputs[3].int(), &inputs[4]);
+            let strength_mode = inputs[5].int();
+            context.timing(timing_mode, timing);
+            for s in 0..buffer_length {
+                let phase = (timing[s] / cycle_time.signal(s) + offset.signal(s) + 1.0) % 1.0;
+                output[s] =
+                    apply_lfo_strength(waveform.at(phase), strength.signal(s), strength_mode);
+            }
+            if let Some(graph_feedback) = graph_feedback {
+                let last = buffer_length - 1;
+                let offset = offset.signal(last);
+                let phase = timing[last] / cycle_time.signal(last);
+                let data = graph_feedback.data;
+                data.push((phase + 2.0) % 1.0);
+                data.push(output[last]);
+                if graph_feedback.full {
+                    for s in 0..GRAPH_RESOLUTION {
+                        let phase = (s as f32 / (GRAPH_RESOLUTION - 1) as f32 + offset) % 1.0;
+                        let strength = strength.signal(s.min(last));
+                        data.push(apply_lfo_strength(
+                            waveform.at(phase),
+                            strength,
+                            strength_mode,
+                        ));
+                    }
+                }
+            }
+        }
+        ModuleKind::Amplifier => {
+            let (input, gain) = (&inputs[0], &inputs[1]);
+            for s in 0..buffer_length {
+                output[s] = input.signal(s) * gain.signal(s);
+            }
+        }
+        ModuleKind::Mixer => {
+            for s in 0..buffer_length {
+                output[s] = inputs
+                    .chunks(2)
+                    .map(|pair| pair[0].signal(s) * pair[1].signal(s))
+                    .sum();
+            }
+        }
+        ModuleKind::NoteOutput => {
+            let (audio, volume) = (&inputs[0], &inputs[1]);
+            let channels = context.parameters.channels;
+            for s in 0..buffer_length {
+                let value = audio.signal(s) * volume.signal(s);
+                for channel in 0..channels {
+                    note_output[s * channels + channel] = value;
+                }
+            }
+        }
+        ModuleKind::Noise => {
+            let (amplitude, delay_mul, max_delay) = (&inputs[0], &inputs[1], &inputs[3]);
+            let smooth_mode = inputs[4].int();
+            // Noise always uses note time in seconds, whatever its timing mode says.
+            context.timing(0, timing);
+            for s in 0..buffer_length {
+                let delay_now = max_delay.signal(s) * delay_mul.signal(s);
+                let time_now = timing[s];
+                let value_now = if delay_now <= 1.0 / sample_rate {
+                    state.old_value = state.new_value;
+                    state.new_value = rand::random();
+                    state.old_value_time = time_now;
+                    state.old_value
+                } else {
+                    if state.old_value_time + delay_now <= time_now {
+                        state.old_value = state.new_value;
+                        state.new_value = rand::random();
+                        state.old_value_time += delay_now;
+                    }
+                    let factor = (time_now - state.old_value_time) / delay_now;
+                    if smooth_mode == 0 {
+                        state.old_value
+                    } else {
+                        lerp(state.old_value, state.new_value, factor)
+                    }
+                };
+                output[s] = (value_now * 2.0 - 1.0) * amplitude.signal(s);
+            }
+            // The noise graph has no cursor, so there is nothing to show for other voices.
+            if let Some(GraphFeedback { data, full: true }) = graph_feedback {
+                data.push(-2.0);
+                data.push(-2.0);
+                for s in 0..GRAPH_RESOLUTION {
+                    let pos = s as f32 / (1.0 + 40.0 * delay_mul.signal(0));
+                    let index = pos.floor() as usize;
+                    let value = if smooth_mode == 0 {
+                        DUMMY_NOISE[index]
+                    } else {
+                        lerp(DUMMY_NOISE[index], DUMMY_NOISE[index + 1], pos % 1.0)
+                    };
+                    data.push((value * 2.0 - 1.0) * amplitude.signal(0));
+                }
+            }
+        }
+    }
+}
+
+/// How much feedback data to collect from a note.
+#[derive(Clone, Copy)]
+enum FeedbackRequest<'a> {
+    None,
+    /// Only the data which widgets showing every voice take from each voice.
+    Voice,
+    /// All of it, including the output views of the watched modules.
+    Full {
+        watched_modules: &'a [usize],
+    },
+}
+
+/// Buffers used while running the graph, kept around between notes so that running it does not
+/// allocate anything unless feedback is requested.
+struct Scratch {
+    /// The output of every module.
+    signals: Vec<Vec<f32>>,
+    /// Mixed automation of the controls of the module which is running, indexed by control.
+    automation: Vec<Vec<f32>>,
+    timing: Vec<f32>,
+    graph_feedback: Vec<f32>,
+    /// Stands in for the output of modules which don't have one.
+    unused_output: Vec<f32>,
+}
+
+impl Scratch {
+    fn new(graph: &NativeGraph, buffer_length: usize) -> Self {
+        let buffers = |count: usize| -> Vec<Vec<f32>> {
+            (0..count)
+                .map(|_| Vec::with_capacity(buffer_length))
+                .collect()
+        };
+        Self {
+            signals: buffers(graph.num_signals),
+            automation: buffers(MAX_CONTROLS),
+            timing: Vec::with_capacity(buffer_length),
+            graph_feedback: Vec::new(),
+            unused_output: Vec::with_capacity(buffer_length),
+        }
+    }
+}
+
+struct Generation {
+    id: usize,
+    graph: NativeGraph,
+    scratch: Scratch,
+    dyn_data: Vec<IOData>,
+    /// Indexed by static index and then by position in the execution order.
+    static_data: Vec<Vec<ModuleState>>,
+    /// Seconds spent running each module since they were last collected, indexed by position in
+    /// the graph. None if the code was generated without profiling.
+    module_times: Option<Vec<f32>>,
+    /// Indexed by static index, the position in the graph of the first module which produced a
+    /// non-finite value during the last buffer. None if the code was generated without the check.
+    first_non_finite: Option<Vec<Option<usize>>>,
+}
+
+impl Generation {
+    fn reset_static_data(&mut self, static_index: usize) {
+        if self.static_data.len() <= static_index {
+            self.static_data.resize(static_index + 1, Vec::new());
+        }
+        self.static_data[static_index] = vec![ModuleState::new(); self.graph.modules.len()];
+    }
+
+    /// Runs the whole graph for one note, leaving its audio in note_audio. Returns feedback data
+    /// unless none was requested.
+    fn run(
+        &mut self,
+        context: &NoteContext,
+        static_index: usize,
+        request: FeedbackRequest,
+        note_audio: &mut Vec<f32>,
+    ) -> Option<FeedbackData> {
+        let buffer_length = context.parameters.buffer_length;
+        if self.static_data.len() <= static_index || self.static_data[static_index].is_empty() {
+            self.reset_static_data(static_index);
+        }
+        if let Some(first_non_finite) = &mut self.first_non_finite {
+            if first_non_finite.len() <= static_index {
+                first_non_finite.resize(static_index + 1, None);
+            }
+            first_non_finite[static_index] = None;
+        }
+        note_audio.clear();
+        note_audio.resize(buffer_length * context.parameters.channels, 0.0);
+        let mut feedback = if let FeedbackRequest::None = request {
+            None
+        } else {
+            Some(FeedbackData {
+                widget_feeback: vec![Vec::new(); self.graph.num_feedback_widgets],
+                voice_feedback: Vec::new(),
+                output_views: Vec::new(),
+                voice: None,
+                module_cpu_usage: Vec::new(),
+            })
+        };
+
+        let scratch = &mut self.scratch;
+        let states = &mut self.static_data[static_index];
+        for (module, state) in self.graph.modules.iter().zip(states.iter_mut()) {
+            // Modules never read their own output, so it can be taken out while they run.
+            let mut output = match module.output {
+                Some(index) => std::mem::take(&mut scratch.signals[index]),
+                None => std::mem::take(&mut scratch.unused_output),
+            };
+            output.resize(buffer_length, 0.0);
+            let signals = &scratch.signals[..];
+            for (source, automation) in module.controls.iter().zip(scratch.automation.iter_mut()) {
+                source.mix_automation(&self.dyn_data[..], signals, buffer_length, automation);
+            }
+            let mut inputs = [Value::Constant(0.0); MAX_CONTROLS];
+            let sources = module.controls.iter().zip(scratch.automation.iter());
+            for (input, (source, automation)) in inputs.iter_mut().zip(sources) {
+                *input = source.evaluate(&self.dyn_data[..], signals, &automation[..], context);
+            }
+            let inputs = &inputs[..module.controls.len()];
+            scratch.graph_feedback.clear();
+            let start = Instant::now();
+            run_module(
+                module.kind,
+                inputs,
+                state,
+                context,
+                ModuleOutputs {
+                    output: &mut output[..],
+                    note_output: &mut note_audio[..],
+                },
+                &mut scratch.timing,
+                match request {
+                    FeedbackRequest::None => None,
+                    FeedbackRequest::Voice => Some(GraphFeedback {
+                        data: &mut scratch.graph_feedback,
+                        full: false,
+                    }),
+                    FeedbackRequest::Full { .. } => Some(GraphFeedback {
+                        data: &mut scratch.graph_feedback,
+                        full: true,
+                    }),
+                },
+            );
+            if let Some(times) = &mut self.module_times {
+                times[module.graph_index] += start.elapsed().as_secs_f32();
+            }
+            if let Some(first_non_finite) = &mut self.first_non_finite {
+                let finite = |samples: &[f32]| samples.iter().all(|s| s.is_finite());
+                if first_non_finite[static_index].is_none()
+                    && !(finite(&output[..]) && finite(&note_audio[..]))
+                {
+                    first_non_finite[static_index] = Some(module.graph_index);
+                }
+            }
+            if let Some(feedback) = &mut feedback {
+                for (widget_index, source) in &module.feedback {
+                    feedback.widget_feeback[*widget_index] = match source {
+                        FeedbackSource::Control(index) => vec![inputs[*index].signal(0)],
+                        FeedbackSource::Graph => scratch.graph_feedback.clone(),
+                    };
+                }
+                let watched = match request {
+                    FeedbackRequest::Full { watched_modules } => {
+                        watched_modules.contains(&module.graph_index)
+                    }
+                    _ => false,
+                };
+                if watched && module.output.is_some() {
+                    let output_view = if module.output_is_control {
+                        vec![vec![output[buffer_length - 1]]]
+                    } else {
+                        // The whole buffer is sent so that the GUI can analyze its spectrum.
+                        vec![output.clone()]
+                    };
+                    feedback
+                        .output_views
+                        .push((module.graph_index, output_view));
+                }
+            }
+            match module.output {
+                Some(index) => scratch.signals[index] = output,
+                None => scratch.unused_output = output,
+            }
+        }
+        feedback
+    }
+}
+
+/// Runs note graphs made only of the modules in ModuleKind, without needing Julia. This makes it
+/// possible to produce audio where Julia is not installed or failed to start.
+pub(super) struct NativeExecutor {
+    /// The host's parameters.
+    parameters: GlobalParameters,
+    /// The parameters the note graph runs with, after oversampling.
+    graph_parameters: GlobalParameters,
+    decimator: Decimator,
+    /// The last item is the current generation.
+    generations: Vec<Generation>,
+    /// Reused between notes to hold the audio of each one.
+    note_audio: Vec<f32>,
+    profiler: ModuleProfiler,
+    non_finite_voices: Vec<NonFiniteVoice>,
+}
+
+impl NativeExecutor {
+    pub fn new(parameters: &GlobalParameters) -> Self {
+        Self {
+            parameters: parameters.clone(),
+            graph_parameters: parameters.clone(),
+            decimator: Decimator::new(parameters, 1),
+            generations: Vec::new(),
+            note_audio: Vec::with_capacity(parameters.buffer_length * parameters.channels),
+            profiler: ModuleProfiler::default(),
+            non_finite_voices: Vec::new(),
+        }
+    }
+
+    fn find_generation(
+        generations: &mut [Generation],
+        id: usize,
+    ) -> Result<&mut Generation, String> {
+        generations
+            .iter_mut()
+            .find(|generation| generation.id == id)
+            .ok_or_else(|| format!("ERROR: Generation {} of the note graph is not loaded.", id))
+    }
+
+    /// Forgets about old generations of code which no notes are using anymore.
+    fn unload_unused_generations(&mut self, notes: &NoteTracker) {
+        let current = self.generations.last().map(|generation| generation.id);
+        self.generations.retain(|generation| {
+            Some(generation.id) == current || notes.uses_generation(generation.id)
+        });
+    }
+}
+
+impl NoteGraphExecutor for NativeExecutor {
+    fn change_parameters(
+        &mut self,
+        parameters: &GlobalParameters,
+        oversampling: usize,
+    ) -> Result<(), String> {
+        self.parameters = parameters.clone();
+        self.graph_parameters = parameters.oversampled(oversampling);
+        self.decimator = Decimator::new(parameters, oversampling);
+        let buffer_length = self.graph_parameters.buffer_length;
+        for generation in &mut self.generations {
+            generation.scratch = Scratch::new(&generation.graph, buffer_length);
+        }
+        self.note_audio = Vec::with_capacity(buffer_length * parameters.channels);
+        Ok(())
+    }
+
+    fn change_generated_code(
+        &mut self,
+        code: NoteGraphCode,
+        generation: usize,
+        dyn_data: Vec<IOData>,
+    ) -> Result<(), String> {
+        let graph = code.native?;
+        let module_times = if code.options.profile {
+            Some(vec![0.0; graph.modules.len()])
+        } else {
+            None
+        };
+        let first_non_finite = if code.options.check_non_finite {
+            Some(Vec::new())
+        } else {
+            None
+        };
+        self.generations.retain(|other| other.id != generation);
+        self.generations.push(Generation {
+            id: generation,
+            scratch: Scratch::new(&graph, self.graph_parameters.buffer_length),
+            graph,
+            dyn_data,
+            static_data: Vec::new(),
+            module_times,
+            first_non_finite,
+        });
+        // Timings from the old generation are thrown away.
+        self.profiler.reset();
+        Ok(())
+    }
+
+    fn change_dyn_data(&mut self, dyn_data: Vec<IOData>) {
+        if let Some(generation) = self.generations.last_mut() {
+            generation.dyn_data = dyn_data;
+        }
+    }
+
+    fn take_non_finite_voices(&mut self) -> Vec<NonFiniteVoice> {
+        std::mem::take(&mut self.non_finite_voices)
+    }
+
+    // There is nothing to compile.
+    fn preheat(&mut self, generation: usize) -> Result<(), String> {
+        Self::find_generation(&mut self.generations, generation).map(|_| ())
+    }
+
+    fn execute(
+        &mut self,
+        do_feedback: bool,
+        watched_modules: &[usize],
+        global_data: &GlobalData,
+        notes: &mut NoteTracker,
+        audio_output: &mut [f32],
+    ) -> Result<Option<FeedbackData>, String> {
+        for (generation, index) in notes.take_static_indexes_to_reset() {
+            Self::find_generation(&mut self.generations, generation)?.reset_static_data(index);
+        }
+
+        let channels = self.parameters.channels;
+        assert!(audio_output.len() == self.parameters.buffer_length * channels);
+        let oversampling = self.decimator.get_factor();
+        let mix_output = self.decimator.clear_input();
+        let feedback_note = if do_feedback {
+            notes
+                .recommend_note_for_feedback()
+                .map(|note| (note.static_index, note.key))
+        } else {
+            None
+        };
+        let mut feedback_data = None;
+        // Indexed by widget and then by voice.
+        let mut voice_feedback = Vec::new();
+
+        let pitch_mul = (2.0f32).powf(global_data.pitch_wheel * 7.0 / 12.0);
+        let crossfade_samples = notes.crossfade_samples();
+        let mut is_dummy = notes.has_dummy_note();
+        let current_generation = notes.current_generation();
+        let mut notes_to_reset = Vec::new();
+        for note in notes.active_notes_mut() {
+            let context = NoteContext {
+                parameters: &self.graph_parameters,
+                global: global_data,
+                note: NoteInput::from(&note.data, &self.parameters, pitch_mul),
+            };
+            let request = if feedback_note.map(|(index, _)| index) == Some(note.static_index) {
+                FeedbackRequest::Full { watched_modules }
+            } else if feedback_note.is_some() && !is_dummy && note.generation == current_generation
+            {
+                // Older generations may have different feedback widgets than the ones on screen.
+                FeedbackRequest::Voice
+            } else {
+                FeedbackRequest::None
+            };
+            let generation = Self::find_generation(&mut self.generations, note.generation)?;
+            let feedback =
+                generation.run(&context, note.static_index, request, &mut self.note_audio);
+            match (request, feedback) {
+                (FeedbackRequest::Full { .. }, feedback) => feedback_data = feedback,
+                (FeedbackRequest::Voice, Some(mut feedback)) => {
+                    let widgets = &generation.graph.voice_feedback_widgets;
+                    voice_feedback.resize(widgets.len(), Vec::new());
+                    for (voice_data, widget_index) in voice_feedback.iter_mut().zip(widgets) {
+                        voice_data
+                            .push(std::mem::take(&mut feedback.widget_feeback[*widget_index]));
+                    }
+                }
+                _ => (),
+            }
+            if is_dummy {
+                // Don't process the audio of the dummy note.
+                is_dummy = false;
+                continue;
+            }
+            let finite = note.mix_into(
+                &self.note_audio[..],
+                mix_output,
+                channels,
+                oversampling,
+                crossfade_samples,
+            );
+            if !finite {
+                let module_index = generation
+                    .first_non_finite
+                    .as_ref()
+                    .and_then(|first| first[note.static_index]);
+                notes_to_reset.push((note.generation, note.static_index));
+                self.non_finite_voices.push(NonFiniteVoice {
+                    generation: note.generation,
+                    module_index,
+                });
+            }
+        }
+        self.decimator.decimate_into(audio_output);
+        for (generation, static_index) in notes_to_reset {
+            notes.reset_static_data_later(generation, static_index);
+        }
+
+        self.profiler.add_buffer(&self.parameters);
+        if let Some(feedback_data) = &mut feedback_data {
+            let current = self.generations.last_mut();
+            if let Some(times) = current.and_then(|g| g.module_times.as_mut()) {
+                let times = std::mem::replace(times, vec![0.0; times.len()]);
+                feedback_data.module_cpu_usage = self.profiler.take_usage(times);
+            }
+            feedback_data.voice = feedback_note.and_then(|(_, key)| key);
+            feedback_data.voice_feedback = voice_feedback;
+        }
+        notes.advance_all_notes(&self.parameters, global_data);
+        self.unload_unused_generations(notes);
+        Ok(feedback_data)
+    }
+}
@@ -0,0 +1,361 @@
+//! Sits between the note events coming from the host and the `NoteTracker`. This is where notes
+//! get created or rearranged by the arpeggiator, chord memory, and note repeat features.
+
+use crate::engine::{
+    data_transfer::{GlobalData, GlobalParameters},
+    julia_thread::NoteEvent,
+    program_wrapper::NoteTracker,
+};
+use rand::Rng;
+use shared_util::mini_serde::{MiniDes, MiniSer};
+
+/// The MIDI protocol can provide notes at 128 different pitches.
+const NUM_MIDI_NOTES: usize = 128;
+/// How many semitones above the played note the chord memory can reach.
+pub const CHORD_MEMORY_RANGE: usize = 24;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArpMode {
+    Off,
+    Up,
+    Down,
+    UpDown,
+    Random,
+    AsPlayed,
+}
+
+impl ArpMode {
+    pub const ALL: [ArpMode; 6] = [
+        ArpMode::Off,
+        ArpMode::Up,
+        ArpMode::Down,
+        ArpMode::UpDown,
+        ArpMode::Random,
+        ArpMode::AsPlayed,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Up => "Up",
+            Self::Down => "Down",
+            Self::UpDown => "Up/Down",
+            Self::Random => "Random",
+            Self::AsPlayed => "As Played",
+        }
+    }
+
+    fn ordinal(&self) -> u8 {
+        Self::ALL.iter().position(|mode| mode == self).unwrap() as u8
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteProcessorSettings {
+    pub arp_mode: ArpMode,
+    /// How many octaves the arpeggio spans, counting the octave of the held notes.
+    pub arp_octaves: u8,
+    /// How many beats each step of the arpeggiator or note repeat lasts.
+    pub rate: f32,
+    /// How much of each step the note is held for, from 0 to 1.
+    pub gate: f32,
+    /// Retriggers every held note on every step.
+    pub note_repeat: bool,
+    /// Plays the remembered chord instead of a single note for every key that is pressed.
+    pub chord_memory: bool,
+    /// Bit n is set if the remembered chord contains a note n semitones above the played note.
+    pub chord: u32,
+}
+
+impl Default for NoteProcessorSettings {
+    fn default() -> Self {
+        Self {
+            arp_mode: ArpMode::Off,
+            arp_octaves: 1,
+            rate: 0.25,
+            gate: 0.5,
+            note_repeat: false,
+            chord_memory: false,
+            // Major triad.
+            chord: 0b1001_0001,
+        }
+    }
+}
+
+impl NoteProcessorSettings {
+    /// Returns true if notes are only played on steps of the arpeggiator / note repeat clock.
+    pub fn is_stepped(&self) -> bool {
+        self.arp_mode != ArpMode::Off || self.note_repeat
+    }
+
+    pub fn serialize(&self, ser: &mut MiniSer) {
+        ser.note("Note processor: ");
+        ser.u3(self.arp_mode.ordinal());
+        ser.u2(self.arp_octaves - 1);
+        ser.f32(self.rate);
+        ser.f32_in_range(self.gate, 0.0, 1.0);
+        ser.bool(self.note_repeat);
+        ser.bool(self.chord_memory);
+        ser.u32(self.chord);
+    }
+
+    pub fn deserialize(des: &mut MiniDes) -> Result<Self, ()> {
+        let arp_mode = *ArpMode::ALL.get(des.u3()? as usize).ok_or(())?;
+        let arp_octaves = des.u2()? + 1;
+        let rate = des.f32()?;
+        if !(rate > 0.0) {
+            return Err(());
+        }
+        Ok(Self {
+            arp_mode,
+            arp_octaves,
+            rate,
+            gate: des.f32_in_range(0.0, 1.0)?,
+            note_repeat: des.bool()?,
+            chord_memory: des.bool()?,
+            chord: des.u32()?,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct HeldNote {
+    index: usize,
+    velocity: f32,
+}
+
+pub struct NoteProcessor {
+    settings: NoteProcessorSettings,
+    settings_changed: bool,
+    /// Keys the player is holding down, in the order they were pressed.
+    held: Vec<HeldNote>,
+    /// Notes which have been started on the NoteTracker and not released yet. In unstepped mode
+    /// the same note can show up multiple times if it is part of multiple chords, it will only be
+    /// released once all of them are gone.
+    sounding: Vec<usize>,
+    /// Used to time steps while the host is not playing.
+    free_running_beats: f32,
+    last_step: Option<i64>,
+    arp_position: usize,
+}
+
+impl NoteProcessor {
+    pub fn new() -> Self {
+        Self {
+            settings: Default::default(),
+            settings_changed: false,
+            held: Vec::with_capacity(NUM_MIDI_NOTES),
+            sounding: Vec::with_capacity(NUM_MIDI_NOTES),
+            free_running_beats: 0.0,
+            last_step: None,
+            arp_position: 0,
+        }
+    }
+
+    pub fn set_settings(&mut self, settings: NoteProcessorSettings) {
+        if settings != self.settings {
+            self.settings = settings;
+            self.settings_changed = true;
+        }
+    }
+
+    /// Call this after the notes in the NoteTracker have been removed without our knowledge.
+    pub fn forget_sounding_notes(&mut self) {
+        self.sounding.clear();
+        self.last_step = None;
+    }
+
+    /// Returns the notes that should be played for the given key, including the key itself.
+    fn expand(&self, note: HeldNote) -> impl Iterator<Item = HeldNote> {
+        let chord = if self.settings.chord_memory {
+            self.settings.chord | 0b1
+        } else {
+            0b1
+        };
+        (0..CHORD_MEMORY_RANGE)
+            .filter(move |interval| chord & (1 << interval) != 0)
+            .map(move |interval| note.index + interval)
+            .filter(|&index| index < NUM_MIDI_NOTES)
+            .map(move |index| HeldNote {
+                index,
+                velocity: note.velocity,
+            })
+    }
+
+    fn start(&mut self, notes: &mut NoteTracker, note: HeldNote) {
+        if !self.sounding.contains(&note.index) {
+            notes.start_note(note.index, note.velocity);
+        }
+        self.sounding.push(note.index);
+    }
+
+    fn release(&mut self, notes: &mut NoteTracker, index: usize) {
+        if let Some(position) = self.sounding.iter().position(|&other| other == index) {
+            self.sounding.remove(position);
+            if !self.sounding.contains(&index) {
+                notes.release_note(index);
+            }
+        }
+    }
+
+    fn release_all(&mut self, notes: &mut NoteTracker) {
+        for index in self.sounding.drain(..) {
+            notes.release_note(index);
+        }
+    }
+
+    /// Builds the full list of notes the arpeggiator cycles through, in order.
+    fn arp_sequence(&self) -> Vec<HeldNote> {
+        let mut played = Vec::new();
+        for &note in &self.held {
+            for note in self.expand(note) {
+                if !played
+                    .iter()
+                    .any(|other: &HeldNote| other.index == note.index)
+                {
+                    played.push(note);
+                }
+            }
+        }
+        if self.settings.arp_mode != ArpMode::AsPlayed {
+            played.sort_by_key(|note| note.index);
+        }
+        let mut sequence = Vec::new();
+        for octave in 0..self.settings.arp_octaves as usize {
+            for note in &played {
+                let index = note.index + octave * 12;
+                if index < NUM_MIDI_NOTES {
+                    sequence.push(HeldNote { index, ..*note });
+                }
+            }
+        }
+        match self.settings.arp_mode {
+            ArpMode::Down => sequence.reverse(),
+            ArpMode::UpDown if sequence.len() > 2 => {
+                // Don't repeat the top and bottom notes when changing direction.
+                let descending: Vec<_> = sequence[1..sequence.len() - 1]
+                    .iter()
+                    .rev()
+                    .cloned()
+                    .collect();
+                sequence.extend(descending);
+            }
+            _ => (),
+        }
+        sequence
+    }
+
+    pub fn process(
+        &mut self,
+        events: &[NoteEvent],
+        global_data: &GlobalData,
+        global_params: &GlobalParameters,
+        notes: &mut NoteTracker,
+    ) {
+        if self.settings_changed {
+            self.settings_changed = false;
+            self.release_all(notes);
+            self.last_step = None;
+            if !self.settings.is_stepped() {
+                for note in self.held.clone() {
+                    for note in self.expand(note) {
+                        self.start(notes, note);
+                    }
+                }
+            }
+        }
+
+        if !self.settings.is_stepped() {
+            for &event in events {
+                match event {
+                    NoteEvent::StartNote { index, velocity } => {
+                        if self.held.iter().any(|note| note.index == index) {
+                            continue;
+                        }
+                        let note = HeldNote { index, velocity };
+                        self.held.push(note);
+                        for note in self.expand(note) {
+                            self.start(notes, note);
+                        }
+                    }
+                    NoteEvent::ReleaseNote { index } => {
+                        if let Some(position) = self.held.iter().position(|n| n.index == index) {
+                            let note = self.held.remove(position);
+                            for note in self.expand(note) {
+                                self.release(notes, note.index);
+                            }
+                        }
+                    }
+                }
+            }
+            return;
+        }
+
+        for &event in events {
+            match event {
+                NoteEvent::StartNote { index, velocity } => {
+                    if !self.held.iter().any(|note| note.index == index) {
+                        self.held.push(HeldNote { index, velocity });
+                    }
+                }
+                NoteEvent::ReleaseNote { index } => self.held.retain(|note| note.index != index),
+            }
+        }
+
+        let buffer_time = global_params.buffer_length as f32 / global_params.sample_rate as f32;
+        let beats = if global_data.playing {
+            self.free_running_beats = global_data.elapsed_beats;
+            global_data.elapsed_beats
+        } else {
+            self.free_running_beats += buffer_time * global_data.bpm / 60.0;
+            self.free_running_beats
+        };
+
+        if self.held.is_empty() {
+            self.release_all(notes);
+            self.last_step = None;
+            self.arp_position = 0;
+            return;
+        }
+
+        let position = beats / self.settings.rate;
+        let step = position.floor() as i64;
+        let new_step = self.last_step != Some(step);
+        if new_step {
+            if self.last_step.is_some() {
+                self.arp_position += 1;
+            }
+            self.last_step = Some(step);
+            self.release_all(notes);
+        }
+        // Notes always start at the beginning of a step, even when the gate is shorter than a
+        // single buffer.
+        let in_gate = new_step || position - (step as f32) < self.settings.gate;
+
+        let mut desired = Vec::new();
+        if in_gate {
+            if self.settings.arp_mode == ArpMode::Off {
+                for &note in &self.held {
+                    desired.extend(self.expand(note));
+                }
+            } else {
+                let sequence = self.arp_sequence();
+                if self.settings.arp_mode == ArpMode::Random && new_step {
+                    self.arp_position = rand::thread_rng().gen_range(0, sequence.len());
+                }
+                desired.push(sequence[self.arp_position % sequence.len()]);
+            }
+        }
+
+        for index in self.sounding.clone() {
+            if !desired.iter().any(|note| note.index == index) {
+                self.release(notes, index);
+            }
+        }
+        for note in desired {
+            if !self.sounding.contains(&note.index) {
+                self.start(notes, note);
+            }
+        }
+    }
+}
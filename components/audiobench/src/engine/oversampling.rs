@@ -0,0 +1,135 @@
+//! Running the note graph at a multiple of the host's sample rate keeps naive oscillators and FM
+//! from aliasing. The result is filtered and brought back down to the host's rate by a Decimator.
+
+use crate::engine::data_transfer::GlobalParameters;
+
+/// Every factor the note graph can be oversampled by, in the order they are stored in patches.
+pub const OVERSAMPLING_FACTORS: [usize; 4] = [1, 2, 4, 8];
+/// How many filter taps the anti-aliasing filter uses per unit of oversampling. More taps make the
+/// transition between the passband and the stopband sharper.
+const TAPS_PER_FACTOR: usize = 64;
+/// Where the passband of the anti-aliasing filter ends, as a fraction of the host's Nyquist
+/// frequency. The stopband starts at the host's Nyquist frequency.
+const PASSBAND_EDGE: f32 = 0.84;
+
+/// Returns a linear phase lowpass filter which removes everything above the host's Nyquist
+/// frequency from audio oversampled by the specified factor. This is a Blackman-windowed sinc.
+fn anti_aliasing_taps(factor: usize) -> Vec<f32> {
+    use std::f32::consts::PI;
+    let len = TAPS_PER_FACTOR * factor + 1;
+    let middle = (len / 2) as f32;
+    // In cycles per oversampled sample.
+    let cutoff = (1.0 + PASSBAND_EDGE) / 2.0 * 0.5 / factor as f32;
+    let mut taps: Vec<f32> = (0..len)
+        .map(|index| {
+            let x = index as f32 - middle;
+            let sinc = if x == 0.0 {
+                2.0 * cutoff
+            } else {
+                (2.0 * PI * cutoff * x).sin() / (PI * x)
+            };
+            let phase = 2.0 * PI * index as f32 / (len - 1) as f32;
+            let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            sinc * window
+        })
+        .collect();
+    // Normalize so that the gain at DC is exactly 1.
+    let sum: f32 = taps.iter().sum();
+    for tap in &mut taps {
+        *tap /= sum;
+    }
+    taps
+}
+
+/// How many of the host's samples a Decimator for the specified factor delays audio by, rounded to
+/// the nearest sample.
+pub fn decimator_latency(factor: usize) -> usize {
+    if factor == 1 {
+        return 0;
+    }
+    // The filter is linear phase, so it delays everything by half its length in oversampled
+    // samples. Each output is taken at the newest sample of its frame's group instead of the
+    // oldest, which makes up for `factor - 1` of them.
+    let delay = TAPS_PER_FACTOR * factor / 2 - (factor - 1);
+    (delay + factor / 2) / factor
+}
+
+/// Turns oversampled audio back into audio at the host's sample rate. Everything it needs is
+/// allocated up front, so it can be used while rendering.
+pub struct Decimator {
+    factor: usize,
+    channels: usize,
+    taps: Vec<f32>,
+    /// Oversampled audio is mixed into this before it is decimated, channels are interleaved.
+    input: Vec<f32>,
+    /// For each channel, the last `taps.len() - 1` samples of the previous buffer followed by
+    /// space for the current buffer.
+    history: Vec<Vec<f32>>,
+}
+
+impl Decimator {
+    /// `params` are the host's parameters, not the oversampled ones.
+    pub fn new(params: &GlobalParameters, factor: usize) -> Self {
+        assert!(factor >= 1);
+        let taps = if factor > 1 {
+            anti_aliasing_taps(factor)
+        } else {
+            vec![1.0]
+        };
+        let input_length = params.buffer_length * factor;
+        let history = vec![vec![0.0; taps.len() - 1 + input_length]; params.channels];
+        Self {
+            factor,
+            channels: params.channels,
+            taps,
+            input: vec![0.0; input_length * params.channels],
+            history,
+        }
+    }
+
+    pub fn get_factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Returns the buffer that the oversampled audio of every note should be mixed into, filled
+    /// with silence.
+    pub fn clear_input(&mut self) -> &mut [f32] {
+        for sample in &mut self.input {
+            *sample = 0.0;
+        }
+        &mut self.input[..]
+    }
+
+    /// Filters the audio mixed into the input buffer and writes every `factor`th sample of it to
+    /// the output, which has the host's buffer length.
+    pub fn decimate_into(&mut self, output: &mut [f32]) {
+        assert_eq!(output.len() * self.factor, self.input.len());
+        if self.factor == 1 {
+            output.copy_from_slice(&self.input[..]);
+            return;
+        }
+        let overlap = self.taps.len() - 1;
+        for (channel, history) in self.history.iter_mut().enumerate() {
+            for (frame, sample) in history[overlap..].iter_mut().enumerate() {
+                *sample = self.input[frame * self.channels + channel];
+            }
+            for (frame, output) in output
+                .iter_mut()
+                .skip(channel)
+                .step_by(self.channels)
+                .enumerate()
+            {
+                // The newest sample the filter looks at is the last of this frame's group.
+                let end = overlap + (frame + 1) * self.factor;
+                let window = &history[end - self.taps.len()..end];
+                *output = window
+                    .iter()
+                    .zip(self.taps.iter().rev())
+                    .map(|(sample, tap)| sample * tap)
+                    .sum();
+            }
+            let len = history.len();
+            history.copy_within(len - overlap.., 0);
+        }
+    }
+}
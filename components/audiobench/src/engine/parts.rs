@@ -11,13 +11,21 @@ pub enum JackType {
     Pitch,
     Waveform,
     Audio,
+    /// A slowly changing signal used for modulating controls, like the output of an LFO.
+    Control,
     Trigger,
 }
 
 impl JackType {
     pub fn from_yaml(yaml: &YamlNode) -> Result<Self, String> {
-        let names = vec!["pitch", "waveform", "audio", "trigger"];
-        let values = vec![Self::Pitch, Self::Waveform, Self::Audio, Self::Trigger];
+        let names = vec!["pitch", "waveform", "audio", "control", "trigger"];
+        let values = vec![
+            Self::Pitch,
+            Self::Waveform,
+            Self::Audio,
+            Self::Control,
+            Self::Trigger,
+        ];
         Ok(values[yaml.parse_enumerated(&names[..])?])
     }
 
@@ -26,6 +34,7 @@ impl JackType {
             "pitch" => Ok(Self::Pitch),
             "waveform" => Ok(Self::Waveform),
             "audio" => Ok(Self::Audio),
+            "control" => Ok(Self::Control),
             "trigger" => Ok(Self::Trigger),
             _ => Err(()),
         }
@@ -36,6 +45,7 @@ impl JackType {
             Self::Pitch => "Factory:pitch",
             Self::Waveform => "Factory:waveform",
             Self::Audio => "Factory:audio",
+            Self::Control => "Factory:control",
             Self::Trigger => "Factory:trigger",
         }
     }
@@ -100,6 +110,9 @@ pub struct Module {
     pub template: Rcrc<ModuleTemplate>,
     pub controls: Vec<AnyControl>,
     pub pos: (f32, f32),
+    /// Pinned modules send their outputs back to the GUI even while they are not hovered. This is
+    /// not saved in patches.
+    pub pinned: bool,
 }
 
 impl Module {
@@ -112,6 +125,7 @@ impl Module {
             template,
             controls,
             pos: (0.0, 0.0),
+            pinned: false,
         }
     }
 
@@ -1,8 +1,14 @@
-use crate::engine::data_transfer::{FeedbackData, GlobalData, GlobalParameters, IOData, NoteData};
+use crate::engine::{
+    codegen::{self, CodeGenOptions, NoteGraphCode},
+    data_transfer::{FeedbackData, GlobalData, GlobalParameters, IOData, NoteData},
+    image_cache::CachedImage,
+    oversampling::Decimator,
+};
 use array_macro::array;
 use jlrs_derive::IntoJulia;
 use julia_helper::{DataType, ExecutionEngine, GeneratedCode, JuliaStruct, TypedArray, Value};
-use std::collections::HashSet;
+use shared_util::prelude::*;
+use std::{cmp::Ordering, collections::HashSet, path::PathBuf};
 
 /// The MIDI protocol can provide notes at 128 different pitches.
 const NUM_MIDI_NOTES: usize = 128;
@@ -10,11 +16,14 @@ const NUM_MIDI_NOTES: usize = 128;
 const MIN_SILENT_TIME: f32 = 0.1;
 /// Notes must have every sample be of this magnitude or less to be considered silent.
 const SILENT_CUTOFF: f32 = 1e-5;
+/// How many versions of the generated code can be loaded at once. Once notes are playing with more
+/// than this, the ones using the oldest versions are crossfaded onto the newest one.
+const MAX_LOADED_GENERATIONS: usize = 3;
 
 #[repr(C)]
 #[derive(Clone, Copy, JuliaStruct, IntoJulia)]
-#[jlrs(julia_type = "Main.Registry.Factory.Lib.NoteInput")]
-struct NoteInput {
+#[jlrs(julia_type = "Main.EngineTypes.NoteInput")]
+pub(super) struct NoteInput {
     pub pitch: f32,
     pub velocity: f32,
     pub elapsed_time: f32,
@@ -23,8 +32,23 @@ struct NoteInput {
     pub release_trigger: bool,
 }
 
+/// Types that the engine passes to Julia, they are defined outside the registry so that every
+/// runtime uses the same ones.
+const ENGINE_TYPES: &'static str = r#"
+module EngineTypes
+struct NoteInput
+    pitch::Float32
+    velocity::Float32
+    elapsed_time::Float32
+    elapsed_beats::Float32
+    start_trigger::Bool
+    release_trigger::Bool
+end
+end
+"#;
+
 impl NoteInput {
-    fn from(other: &NoteData, params: &GlobalParameters, pitch_mul: f32) -> Self {
+    pub(super) fn from(other: &NoteData, params: &GlobalParameters, pitch_mul: f32) -> Self {
         Self {
             pitch: other.pitch * pitch_mul,
             velocity: other.velocity,
@@ -36,29 +60,140 @@ impl NoteInput {
     }
 }
 
+/// Used to crossfade between notes playing on old code and their replacements on new code.
+#[derive(Clone, Copy, Debug)]
+enum Fade {
+    None,
+    /// How many samples of the fade have elapsed.
+    In(usize),
+    Out(usize),
+}
+
+impl Fade {
+    /// Returns the volume of the note after the specified number of additional samples.
+    fn gain(&self, offset: usize, length: usize) -> f32 {
+        let progress = |elapsed: usize| {
+            if elapsed + offset >= length {
+                1.0
+            } else {
+                (elapsed + offset) as f32 / length as f32
+            }
+        };
+        match self {
+            Self::None => 1.0,
+            Self::In(elapsed) => progress(*elapsed),
+            Self::Out(elapsed) => 1.0 - progress(*elapsed),
+        }
+    }
+}
+
 #[derive(Debug)]
-struct CompleteNoteData {
-    data: NoteData,
+pub(super) struct CompleteNoteData {
+    pub data: NoteData,
+    /// The MIDI note which started this note, None for the dummy note.
+    pub key: Option<usize>,
     silent_samples: usize,
-    static_index: usize,
+    /// The highest absolute value in the last buffer of audio this note produced.
+    peak: f32,
+    pub static_index: usize,
+    /// Which version of the generated code this note is played with.
+    pub generation: usize,
+    fade: Fade,
+}
+
+impl CompleteNoteData {
+    /// Adds audio this note produced to the output, applying its crossfade, and keeps track of
+    /// how long the note has been silent. Denormal samples are flushed to zero. If the audio
+    /// contains NaN or infinite samples, nothing is added and false is returned, so that one
+    /// broken voice can't poison everything else the host is mixing it with. The audio can be
+    /// oversampled, the crossfade and silence are still measured in samples at the host's rate.
+    #[must_use]
+    pub fn mix_into(
+        &mut self,
+        audio: &[f32],
+        audio_output: &mut [f32],
+        channels: usize,
+        oversampling: usize,
+        crossfade_samples: usize,
+    ) -> bool {
+        let frames = audio_output.len() / channels / oversampling;
+        if audio.iter().any(|sample| !sample.is_finite()) {
+            // Count it as silence so that released notes still get cleaned up eventually.
+            self.silent_samples += frames;
+            return false;
+        }
+        let mut silent = true;
+        self.peak = 0.0;
+        for i in 0..audio_output.len() {
+            let gain = self
+                .fade
+                .gain(i / channels / oversampling, crossfade_samples);
+            if audio[i].is_normal() {
+                audio_output[i] += audio[i] * gain;
+            }
+            silent &= audio[i].abs() < SILENT_CUTOFF;
+            self.peak = self.peak.max(audio[i].abs());
+        }
+        if silent {
+            self.silent_samples += frames;
+        } else {
+            self.silent_samples = 0;
+        }
+        true
+    }
+}
+
+/// A note which was muted for a buffer and had its static data reset because it produced NaN or
+/// infinite samples.
+#[derive(Clone, Copy, Debug)]
+pub struct NonFiniteVoice {
+    /// Which version of the generated code the note was played with.
+    pub generation: usize,
+    /// Position in the graph of the first module which produced a non-finite value, if the code
+    /// was generated with CodeGenOptions::check_non_finite and one was found.
+    pub module_index: Option<usize>,
+}
+
+/// Which note feedback and output view data is collected from when several notes are playing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedbackVoice {
+    /// The note which started most recently.
+    Youngest,
+    /// The note which has been playing the longest.
+    Oldest,
+    /// The note which produced the loudest audio in the last buffer.
+    Loudest,
+    /// The specified MIDI note, when it is playing.
+    Note(usize),
 }
 
 pub struct NoteTracker {
+    feedback_voice: FeedbackVoice,
     dummy_note: Option<CompleteNoteData>,
     held_notes: [Option<CompleteNoteData>; NUM_MIDI_NOTES],
     decaying_notes: Vec<CompleteNoteData>,
+    /// Notes which are still being played with a version of the generated code that is being
+    /// unloaded, they are removed once they are silent or have faded out.
+    retiring_notes: Vec<CompleteNoteData>,
     reserved_static_indexes: HashSet<usize>,
-    static_indexes_to_reset: Vec<usize>,
+    /// Pairs of generation and static index.
+    static_indexes_to_reset: Vec<(usize, usize)>,
+    generation: usize,
+    crossfade_samples: usize,
 }
 
 impl NoteTracker {
     pub fn new() -> Self {
         Self {
+            feedback_voice: FeedbackVoice::Youngest,
             dummy_note: None,
             held_notes: array![None; NUM_MIDI_NOTES],
             decaying_notes: Vec::new(),
+            retiring_notes: Vec::new(),
             reserved_static_indexes: HashSet::new(),
             static_indexes_to_reset: Vec::new(),
+            generation: 0,
+            crossfade_samples: 0,
         }
     }
 
@@ -68,10 +203,126 @@ impl NoteTracker {
             static_index += 1;
         }
         self.reserved_static_indexes.insert(static_index);
-        self.static_indexes_to_reset.push(static_index);
+        self.static_indexes_to_reset
+            .push((self.generation, static_index));
         static_index
     }
 
+    /// Makes new notes play with a new version of the generated code. Notes which are already
+    /// playing keep using the code they were started with, unless that would leave more than
+    /// MAX_LOADED_GENERATIONS versions loaded. Held notes on the versions being unloaded are
+    /// restarted with the new code (without retriggering them) and crossfaded with the old ones
+    /// over the specified number of samples, decaying notes on them are faded out.
+    pub fn switch_generation(&mut self, generation: usize, crossfade_samples: usize) {
+        self.generation = generation;
+        self.crossfade_samples = crossfade_samples;
+        // The Julia thread will restart it on the new code if it is still needed.
+        self.stop_dummy_note();
+        let to_unload = self.generations_to_unload();
+        for index in (0..self.decaying_notes.len()).rev() {
+            if to_unload.contains(&self.decaying_notes[index].generation) {
+                let note = self.decaying_notes.remove(index);
+                self.retiring_notes.push(note);
+            }
+        }
+        for index in 0..NUM_MIDI_NOTES {
+            let old_note = match &self.held_notes[index] {
+                Some(note) if to_unload.contains(&note.generation) => {
+                    self.held_notes[index].take().unwrap()
+                }
+                _ => continue,
+            };
+            let static_index = self.reserve_static_index();
+            self.held_notes[index] = Some(CompleteNoteData {
+                data: NoteData {
+                    start_trigger: false,
+                    ..old_note.data
+                },
+                key: old_note.key,
+                silent_samples: 0,
+                peak: old_note.peak,
+                static_index,
+                generation,
+                fade: if crossfade_samples > 0 {
+                    Fade::In(0)
+                } else {
+                    Fade::None
+                },
+            });
+            self.retiring_notes.push(old_note);
+        }
+        for note in &mut self.retiring_notes {
+            note.fade = match note.fade {
+                Fade::None => Fade::Out(0),
+                // Start fading out from the volume it had already reached.
+                Fade::In(elapsed) => Fade::Out(crossfade_samples.saturating_sub(elapsed)),
+                Fade::Out(elapsed) => Fade::Out(elapsed),
+            };
+        }
+        if crossfade_samples == 0 {
+            for note in self.retiring_notes.drain(..) {
+                self.reserved_static_indexes.remove(&note.static_index);
+            }
+        }
+    }
+
+    /// Returns the old generations which notes are still playing with that have to go so that no
+    /// more than MAX_LOADED_GENERATIONS are loaded alongside the current one.
+    fn generations_to_unload(&self) -> Vec<usize> {
+        let mut in_use: Vec<usize> = self
+            .held_notes
+            .iter()
+            .filter_map(|o| o.as_ref())
+            .chain(self.decaying_notes.iter())
+            .map(|note| note.generation)
+            .filter(|generation| *generation != self.generation)
+            .collect();
+        // Newest first, since those are the ones to keep.
+        in_use.sort_unstable_by(|a, b| b.cmp(a));
+        in_use.dedup();
+        in_use
+            .into_iter()
+            .skip(MAX_LOADED_GENERATIONS - 1)
+            .collect()
+    }
+
+    pub fn current_generation(&self) -> usize {
+        self.generation
+    }
+
+    pub(super) fn crossfade_samples(&self) -> usize {
+        self.crossfade_samples
+    }
+
+    pub(super) fn has_dummy_note(&self) -> bool {
+        self.dummy_note.is_some()
+    }
+
+    /// Returns pairs of generation and static index whose static data should be reset before the
+    /// notes using them are played.
+    pub(super) fn take_static_indexes_to_reset(&mut self) -> Vec<(usize, usize)> {
+        std::mem::take(&mut self.static_indexes_to_reset)
+    }
+
+    /// Makes the note using the specified static data start over from a clean state the next time
+    /// it is played.
+    pub(super) fn reset_static_data_later(&mut self, generation: usize, static_index: usize) {
+        self.static_indexes_to_reset
+            .push((generation, static_index));
+    }
+
+    /// Returns true if any notes are still being played with the specified generation of code.
+    pub fn uses_generation(&self, generation: usize) -> bool {
+        generation == self.generation
+            || self
+                .held_notes
+                .iter()
+                .filter_map(|o| o.as_ref())
+                .chain(self.decaying_notes.iter())
+                .chain(self.retiring_notes.iter())
+                .any(|note| note.generation == generation)
+    }
+
     pub fn start_dummy_note(&mut self) {
         if self.dummy_note.is_none() {
             let static_index = self.reserve_static_index();
@@ -84,8 +335,12 @@ impl NoteTracker {
                     start_trigger: true,
                     release_trigger: false,
                 },
+                key: None,
                 silent_samples: 0,
+                peak: 0.0,
                 static_index,
+                generation: self.generation,
+                fade: Fade::None,
             });
         }
     }
@@ -109,6 +364,7 @@ impl NoteTracker {
         self.stop_dummy_note();
         self.held_notes = array![None; NUM_MIDI_NOTES];
         self.decaying_notes.clear();
+        self.retiring_notes.clear();
         self.reserved_static_indexes.clear();
     }
 
@@ -131,8 +387,12 @@ impl NoteTracker {
                 start_trigger: true,
                 release_trigger: false,
             },
+            key: Some(index),
             silent_samples: 0,
+            peak: 0.0,
             static_index,
+            generation: self.generation,
+            fade: Fade::None,
         });
     }
 
@@ -144,7 +404,20 @@ impl NoteTracker {
         }
     }
 
-    fn advance_all_notes(&mut self, global_params: &GlobalParameters, global_data: &GlobalData) {
+    fn advance_fade_in(note: &mut CompleteNoteData, buffer_len: usize, crossfade_samples: usize) {
+        if let Fade::In(elapsed) = &mut note.fade {
+            *elapsed += buffer_len;
+            if *elapsed >= crossfade_samples {
+                note.fade = Fade::None;
+            }
+        }
+    }
+
+    pub(super) fn advance_all_notes(
+        &mut self,
+        global_params: &GlobalParameters,
+        global_data: &GlobalData,
+    ) {
         let sample_rate = global_params.sample_rate as f32;
         let buffer_len = global_params.buffer_length;
         let min_silent_samples = (MIN_SILENT_TIME * sample_rate) as usize;
@@ -154,6 +427,25 @@ impl NoteTracker {
             if note.silent_samples >= min_silent_samples {
                 assert!(self.reserved_static_indexes.remove(&note.static_index));
                 self.decaying_notes.remove(index);
+            } else {
+                note.data.elapsed_samples += buffer_len;
+                note.data.elapsed_beats += buffer_beats;
+                note.data.start_trigger = false;
+                note.data.release_trigger = false;
+                Self::advance_fade_in(note, buffer_len, self.crossfade_samples);
+            }
+        }
+        for index in (0..self.retiring_notes.len()).rev() {
+            let note = &mut self.retiring_notes[index];
+            let faded_out = if let Fade::Out(elapsed) = &mut note.fade {
+                *elapsed += buffer_len;
+                *elapsed >= self.crossfade_samples
+            } else {
+                false
+            };
+            if faded_out || note.silent_samples >= min_silent_samples {
+                assert!(self.reserved_static_indexes.remove(&note.static_index));
+                self.retiring_notes.remove(index);
             } else {
                 note.data.elapsed_samples += buffer_len;
                 note.data.elapsed_beats += buffer_beats;
@@ -165,6 +457,7 @@ impl NoteTracker {
             note.data.elapsed_samples += buffer_len;
             note.data.elapsed_beats += buffer_beats;
             note.data.start_trigger = false;
+            Self::advance_fade_in(note, buffer_len, self.crossfade_samples);
         }
         if let Some(note) = &mut self.dummy_note {
             note.data.elapsed_samples += buffer_len;
@@ -173,124 +466,506 @@ impl NoteTracker {
         }
     }
 
-    fn recommend_note_for_feedback(&self) -> Option<usize> {
-        let mut youngest_time = std::usize::MAX;
-        for note in self.held_notes.iter().filter_map(|o| o.as_ref()) {
-            youngest_time = youngest_time.min(note.data.elapsed_samples);
-        }
-        // If there are no held notes, it is okay to display a decaying note insteaad.
-        if youngest_time == std::usize::MAX {
-            for note in &self.decaying_notes {
-                youngest_time = youngest_time.min(note.data.elapsed_samples);
-            }
-        }
-        for note in self.held_notes.iter().filter_map(|o| o.as_ref()) {
-            if note.data.elapsed_samples == youngest_time {
-                return Some(note.static_index);
-            }
-        }
-        for note in &self.decaying_notes {
-            if note.data.elapsed_samples == youngest_time {
-                return Some(note.static_index);
+    pub fn set_feedback_voice(&mut self, voice: FeedbackVoice) {
+        self.feedback_voice = voice;
+    }
+
+    fn pick_feedback_voice<'a>(
+        &self,
+        notes: impl Iterator<Item = &'a CompleteNoteData>,
+    ) -> Option<&'a CompleteNoteData> {
+        match self.feedback_voice {
+            FeedbackVoice::Youngest => notes.min_by_key(|note| note.data.elapsed_samples),
+            FeedbackVoice::Oldest => notes.max_by_key(|note| note.data.elapsed_samples),
+            FeedbackVoice::Loudest => {
+                notes.max_by(|a, b| a.peak.partial_cmp(&b.peak).unwrap_or(Ordering::Equal))
             }
-        }
-        if let Some(note) = &self.dummy_note {
-            Some(note.static_index)
-        } else {
-            None
+            FeedbackVoice::Note(key) => notes
+                .filter(|note| note.key == Some(key))
+                .min_by_key(|note| note.data.elapsed_samples),
         }
     }
 
-    fn active_notes_mut(&mut self) -> impl Iterator<Item = &mut CompleteNoteData> {
+    /// Picks a note according to the feedback voice setting, falling back to the dummy note. Only
+    /// notes playing with the current generation of code are considered, since the feedback of
+    /// older generations may not match the widgets which are currently on screen.
+    pub(super) fn recommend_note_for_feedback(&self) -> Option<&CompleteNoteData> {
+        let generation = self.generation;
+        let current = move |note: &&CompleteNoteData| note.generation == generation;
+        let held_notes = self.held_notes.iter().filter_map(|o| o.as_ref());
+        // If there are no held notes, it is okay to display a decaying note insteaad.
+        self.pick_feedback_voice(held_notes.filter(current))
+            .or_else(|| self.pick_feedback_voice(self.decaying_notes.iter().filter(current)))
+            .or_else(|| self.dummy_note.as_ref())
+    }
+
+    pub(super) fn active_notes_mut(&mut self) -> impl Iterator<Item = &mut CompleteNoteData> {
         let dummy_iter = self.dummy_note.iter_mut();
         let held_iter = self.held_notes.iter_mut().filter_map(|o| o.as_mut());
         let decaying_iter = self.decaying_notes.iter_mut();
-        dummy_iter.chain(held_iter.chain(decaying_iter))
+        let retiring_iter = self.retiring_notes.iter_mut();
+        dummy_iter.chain(held_iter.chain(decaying_iter.chain(retiring_iter)))
     }
 }
 
-pub(super) struct AudiobenchExecutor {
-    base: ExecutionEngine,
+/// Keeps track of how much audio has been rendered since module timings were last collected, to
+/// turn those timings into how much of the available real time each module used.
+#[derive(Default)]
+pub(super) struct ModuleProfiler {
+    audio_time: f32,
+}
+
+impl ModuleProfiler {
+    pub fn add_buffer(&mut self, parameters: &GlobalParameters) {
+        self.audio_time += parameters.buffer_length as f32 / parameters.sample_rate as f32;
+    }
+
+    /// Starts counting again, should be called whenever the timings are thrown away.
+    pub fn reset(&mut self) {
+        self.audio_time = 0.0;
+    }
+
+    /// Converts seconds spent in each module since the last call into fractions of real time.
+    pub fn take_usage(&mut self, module_times: Vec<f32>) -> Vec<f32> {
+        let audio_time = self.audio_time.max(std::f32::EPSILON);
+        self.reset();
+        module_times.into_iter().map(|t| t / audio_time).collect()
+    }
+}
+
+/// A version of the generated note graph code which is loaded into Julia. Old versions stay loaded
+/// until all the notes which were started with them have faded out.
+struct Generation {
+    id: usize,
+    module_name: String,
+    source: GeneratedCode,
+    dyn_data: Vec<IOData>,
+    options: CodeGenOptions,
+}
+
+fn beautify_stack_trace(trace: String) -> String {
+    let mut trace = &trace[..];
+    let mut result = String::new();
+    while let Some(index) = trace.find("exec(") {
+        result.push_str(&trace[..index + 5]);
+        trace = &trace[index + 5..];
+        let end = trace.find(')').unwrap_or(0);
+        trace = &trace[end..];
+    }
+    result.push_str(trace);
+    result
+}
+
+/// Something that can run the note graph. `AudiobenchExecutor` compiles it with Julia, while
+/// `NativeExecutor` interprets it directly and only supports a few of the Factory modules.
+pub(super) trait NoteGraphExecutor {
+    /// `parameters` are the host's parameters, the note graph is run `oversampling` times faster
+    /// than them and decimated back down to the host's sample rate.
+    fn change_parameters(
+        &mut self,
+        parameters: &GlobalParameters,
+        oversampling: usize,
+    ) -> Result<(), String>;
+
+    /// Loads a new version of the note graph alongside the old ones.
+    fn change_generated_code(
+        &mut self,
+        code: NoteGraphCode,
+        generation: usize,
+        dyn_data: Vec<IOData>,
+    ) -> Result<(), String>;
+
+    /// Changes the dynamic data used by the current generation of code.
+    fn change_dyn_data(&mut self, dyn_data: Vec<IOData>);
+
+    /// Returns the notes which were muted by execute() since the last call.
+    fn take_non_finite_voices(&mut self) -> Vec<NonFiniteVoice>;
+
+    /// Gets the specified generation ready to play notes without any delay.
+    fn preheat(&mut self, generation: usize) -> Result<(), String>;
+
+    /// This handles everything from global setup, note iteration, program execution, note
+    /// teardown, and finally global teardown. Returns feedback data if it was requested and a note
+    /// was available to collect it from. Watched modules are the indexes of the modules whose
+    /// outputs should be retrieved.
+    fn execute(
+        &mut self,
+        do_feedback: bool,
+        watched_modules: &[usize],
+        global_data: &GlobalData,
+        notes: &mut NoteTracker,
+        audio_output: &mut [f32],
+    ) -> Result<Option<FeedbackData>, String>;
+}
+
+/// A copy of the registry compiled for a particular set of global parameters, in a module named
+/// by `runtime_module_name` which contains `Parameters` and `Registry`.
+struct Runtime {
     parameters: GlobalParameters,
     registry_source: GeneratedCode,
-    generated_source: GeneratedCode,
-    loaded: bool,
+    /// How many executors currently point their namespace at this runtime.
+    users: usize,
 }
 
-impl AudiobenchExecutor {
-    fn beautify_stack_trace(trace: String) -> String {
-        let mut trace = &trace[..];
-        let mut result = String::new();
-        while let Some(index) = trace.find("exec(") {
-            result.push_str(&trace[..index + 5]);
-            trace = &trace[index + 5..];
-            let end = trace.find(')').unwrap_or(0);
-            trace = &trace[end..];
-        }
-        result.push_str(trace);
-        result
+fn runtime_module_name(index: usize) -> String {
+    format!("Runtime{}", index)
+}
+
+/// Julia can only be started once per process, so every instance of the engine shares it. Instances
+/// with the same parameters and libraries also share a runtime, which is compiled only once.
+/// Runtimes nobody uses anymore stay loaded in case they are needed again, until their module is
+/// replaced by the next runtime which has to be compiled.
+pub(super) struct SharedJulia {
+    base: ExecutionEngine,
+    /// Indexed by the number in the runtime's module name. None if loading a runtime into that
+    /// module failed, so it can't be used until something else is loaded into it.
+    runtimes: Vec<Option<Runtime>>,
+}
+
+impl SharedJulia {
+    fn engine_types_code() -> GeneratedCode {
+        GeneratedCode::from_unique_source("Generated:engine_types.jl", ENGINE_TYPES)
+    }
+
+    fn parameter_code(parameters: &GlobalParameters) -> GeneratedCode {
+        let parameter_code = format!(
+            concat!(
+                "module Parameters\n",
+                "    const channels = {}\n",
+                "    const buffer_length = {}\n",
+                "    const sample_rate = {}f0\n",
+                "    export channels, buffer_length, sample_rate\n",
+                "end\n",
+            ),
+            parameters.channels, parameters.buffer_length, parameters.sample_rate
+        );
+        GeneratedCode::from_unique_source("Generated:parameters.jl", &parameter_code)
+    }
+
+    fn runtime_code(
+        index: usize,
+        parameters: &GlobalParameters,
+        registry_source: &GeneratedCode,
+    ) -> GeneratedCode {
+        let mut code = GeneratedCode::new();
+        code.append(
+            &format!("module {}\n", runtime_module_name(index)),
+            "generated",
+        );
+        code.append_code(&Self::parameter_code(parameters));
+        code.append_code(registry_source);
+        code.append("\nend\n", "generated");
+        code
     }
 
+    /// Everything that gets loaded before any instance is added if the first instance has the
+    /// specified parameters and registry, in order, for building a system image.
+    pub fn image_code(
+        parameters: &GlobalParameters,
+        registry_source: &GeneratedCode,
+    ) -> Vec<GeneratedCode> {
+        vec![
+            Self::engine_types_code(),
+            Self::runtime_code(0, parameters, registry_source),
+        ]
+    }
+
+    /// If an image is provided, it must have been built from `image_code()` with the same
+    /// parameters and registry source.
     pub fn new(
-        registry_source: GeneratedCode,
+        image: Option<&CachedImage>,
         parameters: &GlobalParameters,
+        registry_source: &GeneratedCode,
     ) -> Result<Self, String> {
+        if let Some(image) = image {
+            let base = ExecutionEngine::with_system_image(
+                &image.julia_bin_dir,
+                &image.image_path,
+                Self::image_code(parameters, registry_source),
+            );
+            match base {
+                Ok(base) => {
+                    return Ok(Self {
+                        base,
+                        runtimes: vec![Some(Runtime {
+                            parameters: parameters.clone(),
+                            registry_source: registry_source.clone(),
+                            users: 0,
+                        })],
+                    });
+                }
+                Err(err) => eprintln!("{}\nFalling back to compiling from scratch.", err),
+            }
+        }
         let mut base = ExecutionEngine::new();
         // base.add_global_code(julia_helper::include_packed_library!("StaticArrays"))
         //     .unwrap();
-        let mut this = AudiobenchExecutor {
+        base.add_global_code(Self::engine_types_code())?;
+        Ok(Self {
             base,
-            // This is a quick and dirty way of getting the executor to rebuild when we use
-            // change_parameters for the first time.
-            parameters: GlobalParameters {
-                channels: 999,
-                buffer_length: 999,
-                sample_rate: 999,
-            },
+            runtimes: Vec::new(),
+        })
+    }
+
+    pub fn julia_bin_dir(&mut self) -> Result<PathBuf, String> {
+        self.base.julia_bin_dir()
+    }
+
+    /// Returns the index of a runtime containing a registry compiled with the specified
+    /// parameters, compiling a new one if necessary. It counts as being used until
+    /// `release_runtime` is called with the index.
+    fn acquire_runtime(
+        &mut self,
+        parameters: &GlobalParameters,
+        registry_source: &GeneratedCode,
+    ) -> Result<usize, String> {
+        let existing = self.runtimes.iter().position(|runtime| match runtime {
+            Some(runtime) => {
+                &runtime.parameters == parameters
+                    && runtime.registry_source.as_str() == registry_source.as_str()
+            }
+            None => false,
+        });
+        if let Some(index) = existing {
+            self.runtimes[index].as_mut().unwrap().users += 1;
+            return Ok(index);
+        }
+        // Replace a runtime nobody is using instead of piling up copies of the registry.
+        let unused = self
+            .runtimes
+            .iter()
+            .position(|runtime| runtime.as_ref().map(|r| r.users == 0).unwrap_or(true));
+        let index = unused.unwrap_or(self.runtimes.len());
+        if index == self.runtimes.len() {
+            self.runtimes.push(None);
+        } else {
+            // If loading fails the module may be left half defined.
+            self.runtimes[index] = None;
+        }
+        self.base
+            .add_global_code(Self::runtime_code(index, parameters, registry_source))
+            .map_err(beautify_stack_trace)?;
+        self.runtimes[index] = Some(Runtime {
+            parameters: parameters.clone(),
+            registry_source: registry_source.clone(),
+            users: 1,
+        });
+        Ok(index)
+    }
+
+    fn release_runtime(&mut self, index: usize) {
+        if let Some(runtime) = &mut self.runtimes[index] {
+            runtime.users -= 1;
+        }
+    }
+}
+
+/// Runs the code of a single instance of the engine. Everything it loads is kept inside a module
+/// named by `instance_namespace` so that it does not interfere with other instances.
+pub(super) struct AudiobenchExecutor {
+    julia: Rcrc<SharedJulia>,
+    namespace: String,
+    /// None until change_parameters is called for the first time.
+    parameters: Option<GlobalParameters>,
+    /// Brings the audio of the oversampled note graph back to the host's sample rate, None until
+    /// change_parameters is called for the first time.
+    decimator: Option<Decimator>,
+    registry_source: GeneratedCode,
+    /// The index of the runtime the namespace points to, None until change_parameters succeeds
+    /// for the first time.
+    runtime: Option<usize>,
+    /// The last item is the current generation.
+    generations: Vec<Generation>,
+    profiler: ModuleProfiler,
+    non_finite_voices: Vec<NonFiniteVoice>,
+}
+
+fn instance_namespace(instance_id: usize) -> String {
+    format!("Instance{}", instance_id)
+}
+
+impl AudiobenchExecutor {
+    pub fn new(
+        julia: Rcrc<SharedJulia>,
+        instance_id: usize,
+        registry_source: GeneratedCode,
+        parameters: &GlobalParameters,
+    ) -> Result<Self, String> {
+        let mut this = AudiobenchExecutor {
+            julia,
+            namespace: instance_namespace(instance_id),
+            parameters: None,
+            decimator: None,
             registry_source,
-            generated_source: GeneratedCode::from_unique_source("blank", ""),
-            loaded: false,
+            runtime: None,
+            generations: Vec::new(),
+            profiler: ModuleProfiler::default(),
+            non_finite_voices: Vec::new(),
         };
-        this.change_parameters(parameters)?;
+        this.change_parameters(parameters, 1)?;
         Ok(this)
     }
 
-    pub fn change_parameters(&mut self, parameters: &GlobalParameters) -> Result<(), String> {
-        if &self.parameters == parameters {
+    fn parameters(&self) -> &GlobalParameters {
+        self.parameters
+            .as_ref()
+            .expect("change_parameters is called on creation")
+    }
+
+    /// Forgets about old generations of code which no notes are using anymore.
+    pub fn unload_unused_generations(&mut self, notes: &NoteTracker) {
+        let current = self.generations.last().map(|generation| generation.id);
+        self.generations.retain(|generation| {
+            Some(generation.id) == current || notes.uses_generation(generation.id)
+        });
+    }
+
+    fn find_generation(generations: &[Generation], id: usize) -> Result<&Generation, String> {
+        generations
+            .iter()
+            .find(|generation| generation.id == id)
+            .ok_or_else(|| format!("ERROR: Generation {} of the note graph is not loaded.", id))
+    }
+
+    fn reset_static_data(&mut self, generation: usize, index: usize) -> Result<(), String> {
+        let generation = Self::find_generation(&self.generations, generation)?;
+        self.julia.borrow_mut().base.call_fn(
+            &[
+                "Main",
+                &self.namespace,
+                &generation.module_name,
+                "static_init",
+            ],
+            |frame, inputs| {
+                inputs.push(Value::new(frame, index)?);
+                Ok(())
+            },
+            |_, _| Ok(()),
+        )
+    }
+
+    /// Returns how much of the available real time each module of the current generation used
+    /// since the last call, or an empty list if it was not generated with profiling.
+    fn take_module_cpu_usage(&mut self) -> Result<Vec<f32>, String> {
+        let generation = match self.generations.last() {
+            Some(generation) if generation.options.profile => generation,
+            _ => return Ok(Vec::new()),
+        };
+        let times = self.julia.borrow_mut().base.call_fn(
+            &[
+                "Main",
+                &self.namespace,
+                &generation.module_name,
+                "take_module_times",
+            ],
+            |_, _| Ok(()),
+            |frame, output| {
+                let times = output.cast::<TypedArray<'_, '_, f32>>()?;
+                let times = times.inline_data(frame)?.into_slice();
+                Ok(times.iter().map(|ns| ns * 1e-9).collect())
+            },
+        );
+        let times = times.map_err(beautify_stack_trace)?;
+        Ok(self.profiler.take_usage(times))
+    }
+
+    /// Returns which module first produced a non-finite value during the last buffer of the
+    /// specified note, if the code checks for them.
+    fn find_non_finite_module(
+        &mut self,
+        generation: usize,
+        static_index: usize,
+    ) -> Result<Option<usize>, String> {
+        let generation = Self::find_generation(&self.generations, generation)?;
+        if !generation.options.check_non_finite {
+            return Ok(None);
+        }
+        let result = self.julia.borrow_mut().base.call_fn(
+            &[
+                "Main",
+                &self.namespace,
+                &generation.module_name,
+                "first_non_finite_module",
+            ],
+            |frame, inputs| {
+                inputs.push(Value::new(frame, static_index)?);
+                Ok(())
+            },
+            |_, output| output.cast::<i64>(),
+        );
+        let position = result.map_err(beautify_stack_trace)?;
+        Ok(if position > 0 {
+            Some(position as usize - 1)
+        } else {
+            None
+        })
+    }
+}
+
+impl Drop for AudiobenchExecutor {
+    fn drop(&mut self) {
+        // Lets the runtime be replaced once no other instance uses it.
+        if let Some(runtime) = self.runtime {
+            self.julia.borrow_mut().release_runtime(runtime);
+        }
+    }
+}
+
+impl NoteGraphExecutor for AudiobenchExecutor {
+    fn change_parameters(
+        &mut self,
+        parameters: &GlobalParameters,
+        oversampling: usize,
+    ) -> Result<(), String> {
+        let current_oversampling = self.decimator.as_ref().map(|d| d.get_factor());
+        if self.parameters.as_ref() == Some(parameters)
+            && current_oversampling == Some(oversampling)
+        {
             return Ok(());
         }
-        self.loaded = false;
-        self.parameters = parameters.clone();
-        let parameter_code = format!(
-            concat!(
-                "module Parameters\n",
-                "    const channels = {}\n",
-                "    const buffer_length = {}\n",
-                "    const sample_rate = {}f0\n",
-                "    export channels, buffer_length, sample_rate\n",
-                "end\n",
-            ),
-            parameters.channels, parameters.buffer_length, parameters.sample_rate
+        self.parameters = Some(parameters.clone());
+        self.decimator = Some(Decimator::new(parameters, oversampling));
+        let mut julia = self.julia.borrow_mut();
+        // The Julia code only ever sees the oversampled parameters.
+        let runtime =
+            julia.acquire_runtime(&parameters.oversampled(oversampling), &self.registry_source)?;
+        if let Some(old_runtime) = self.runtime.replace(runtime) {
+            julia.release_runtime(old_runtime);
+        }
+        // Redefine the namespace so that it points to the registry compiled with the new
+        // parameters.
+        let namespace_code = format!(
+            "module {}\n    const Registry = Main.{}.Registry\nend\n",
+            self.namespace,
+            runtime_module_name(runtime)
         );
-        let parameter_code =
-            GeneratedCode::from_unique_source("Generated:parameters.jl", &parameter_code);
-        self.base
-            .add_global_code(parameter_code)
-            .map_err(Self::beautify_stack_trace)?;
-        // Redefine the registry module because it may have been previously compiled with old
+        julia
+            .base
+            .add_global_code(GeneratedCode::from_unique_source(
+                "Generated:namespace.jl",
+                &namespace_code,
+            ))
+            .map_err(beautify_stack_trace)?;
+        // Redefine the generated modules because they may have been previously compiled with old
         // parameters.
-        self.base
-            .add_global_code(self.registry_source.clone())
-            .map_err(Self::beautify_stack_trace)?;
-        // Redefine the Generated module to be blank because it may have been previously compiled
-        // with old parameters.
-        self.base
-            .add_global_code(self.generated_source.clone())
-            .map_err(Self::beautify_stack_trace)?;
+        for generation in &self.generations {
+            julia
+                .base
+                .add_code_to_module(&self.namespace, generation.source.clone())
+                .map_err(beautify_stack_trace)?;
+        }
         Ok(())
     }
 
-    pub fn change_generated_code(&mut self, generated_code: GeneratedCode) -> Result<(), String> {
+    /// The Julia code must define the module named by codegen::generated_module_name(generation).
+    fn change_generated_code(
+        &mut self,
+        code: NoteGraphCode,
+        generation: usize,
+        dyn_data: Vec<IOData>,
+    ) -> Result<(), String> {
+        let generated_code = code.julia;
+        let options = code.options;
         let mut temp_file = std::env::temp_dir();
         temp_file.push("audiobench_note_graph_code.jl");
         if std::fs::write(temp_file.clone(), generated_code.as_str()).is_err() {
@@ -299,100 +974,125 @@ impl AudiobenchExecutor {
                 temp_file
             ));
         }
-        self.generated_source = generated_code.clone();
-        self.base
-            .add_global_code(generated_code)
-            .map_err(Self::beautify_stack_trace)?;
-        self.loaded = true;
+        self.julia
+            .borrow_mut()
+            .base
+            .add_code_to_module(&self.namespace, generated_code.clone())
+            .map_err(beautify_stack_trace)?;
+        // If the same generation is loaded twice, replace the old version.
+        self.generations.retain(|other| other.id != generation);
+        self.generations.push(Generation {
+            id: generation,
+            module_name: codegen::generated_module_name(generation),
+            source: generated_code,
+            dyn_data,
+            options,
+        });
+        // Timings from the old generation are thrown away.
+        self.profiler.reset();
         Ok(())
     }
 
-    fn reset_static_data(&mut self, index: usize) -> Result<(), String> {
-        self.base.call_fn(
-            &["Main", "Generated", "static_init"],
-            |frame, inputs| {
-                inputs.push(Value::new(frame, index)?);
-                Ok(())
-            },
-            |_, _| Ok(()),
-        )
+    fn change_dyn_data(&mut self, dyn_data: Vec<IOData>) {
+        if let Some(generation) = self.generations.last_mut() {
+            generation.dyn_data = dyn_data;
+        }
     }
 
-    // Runs the main function once to make sure everything is compiled.
-    pub fn preheat(&mut self, notes: &mut NoteTracker, dyn_data: &[IOData]) -> Result<(), String> {
-        let was_dummy_note_active = notes.dummy_note.is_some();
-        notes.start_dummy_note();
-        for index in std::mem::take(&mut notes.static_indexes_to_reset) {
-            self.reset_static_data(index)?;
-        }
-        let note = notes.dummy_note.as_ref().unwrap();
-        let note_input = NoteInput::from(&note.data, &self.parameters, 1.0);
-        let static_index = note.static_index;
+    fn take_non_finite_voices(&mut self) -> Vec<NonFiniteVoice> {
+        std::mem::take(&mut self.non_finite_voices)
+    }
+
+    // Runs the main function of the specified generation once to make sure everything is
+    // compiled. Static index 0 of that generation is used as scratch space, whichever note uses it
+    // next will reset it first.
+    fn preheat(&mut self, generation: usize) -> Result<(), String> {
+        self.reset_static_data(generation, 0)?;
+        let note_data = NoteData {
+            pitch: 440.0,
+            velocity: 1.0,
+            elapsed_samples: 0,
+            elapsed_beats: 0.0,
+            start_trigger: true,
+            release_trigger: false,
+        };
+        let note_input = NoteInput::from(&note_data, self.parameters(), 1.0);
+        let generation = Self::find_generation(&self.generations, generation)?;
+        let dyn_data = &generation.dyn_data;
         let global_data = GlobalData::new();
-        self.base.call_fn(
-            &["Main", "Generated", "exec"],
+        self.julia.borrow_mut().base.call_fn(
+            &["Main", &self.namespace, &generation.module_name, "exec"],
             |frame, inputs| {
                 inputs.append(&mut global_data.as_julia_values(frame)?);
                 inputs.push(Value::new(frame, false)?); // do_feedback
                 inputs.push(Value::new(frame, note_input)?);
-                inputs.push(Value::new(frame, static_index)?);
-                inputs.push(Value::new(frame, 0)?);
+                inputs.push(Value::new(frame, 0usize)?);
+                inputs.push(IOData::IntArray(Vec::new()).as_julia_value(frame)?); // watched_modules
                 for item in dyn_data {
                     inputs.push(item.as_julia_value(frame)?);
                 }
                 Ok(())
             },
-            |frame, output| Ok(()),
-        )?;
-        notes.set_dummy_note_active(was_dummy_note_active);
-        Ok(())
+            |_, _| Ok(()),
+        )
     }
 
-    /// This handles everything from global setup, note iteration, program execution, note teardown,
-    /// and finally global teardown. Returns true if feedback data was updated. View index is which
-    /// module's outputs should be retrieved.
-    pub fn execute(
+    fn execute(
         &mut self,
         do_feedback: bool,
-        view_index: usize,
+        watched_modules: &[usize],
         global_data: &GlobalData,
         notes: &mut NoteTracker,
-        dyn_data: &[IOData],
         audio_output: &mut [f32],
     ) -> Result<Option<FeedbackData>, String> {
-        for index in std::mem::take(&mut notes.static_indexes_to_reset) {
-            self.reset_static_data(index)?;
+        for (generation, index) in notes.take_static_indexes_to_reset() {
+            self.reset_static_data(generation, index)?;
         }
 
-        let channels = self.parameters.channels;
-        let buf_len = self.parameters.buffer_length;
+        let parameters = self.parameters().clone();
+        let channels = parameters.channels;
+        let buf_len = parameters.buffer_length;
         assert!(audio_output.len() == buf_len * channels);
 
-        for i in 0..buf_len * channels {
-            audio_output[i] = 0.0;
-        }
+        let decimator = self.decimator.as_mut().unwrap();
+        let oversampling = decimator.get_factor();
+        let mix_output = decimator.clear_input();
         let feedback_note = if do_feedback {
-            notes.recommend_note_for_feedback()
+            notes
+                .recommend_note_for_feedback()
+                .map(|note| (note.static_index, note.key))
         } else {
             None
         };
         let mut feedback_data = None;
+        // Indexed by widget and then by voice.
+        let mut voice_feedback: Vec<Vec<Vec<f32>>> = Vec::new();
 
         let pitch_mul = (2.0f32).powf(global_data.pitch_wheel * 7.0 / 12.0);
-        let mut is_dummy = notes.dummy_note.is_some();
+        let crossfade_samples = notes.crossfade_samples();
+        let mut is_dummy = notes.has_dummy_note();
+        let current_generation = notes.current_generation();
+        let mut non_finite = Vec::new();
+        let mut julia = self.julia.borrow_mut();
         for note in notes.active_notes_mut() {
-            let note_input = NoteInput::from(&note.data, &self.parameters, pitch_mul);
+            let note_input = NoteInput::from(&note.data, &parameters, pitch_mul);
             let static_index = note.static_index;
-            let do_feedback = feedback_note == Some(static_index);
+            let do_feedback = feedback_note.map(|(index, _)| index) == Some(static_index);
+            // Older generations may have different feedback widgets than the ones on screen.
+            let do_voice_feedback =
+                feedback_note.is_some() && !do_feedback && note.generation == current_generation;
+            let generation = Self::find_generation(&self.generations, note.generation)?;
+            let dyn_data = &generation.dyn_data;
 
-            let res = self.base.call_fn(
-                &["Main", "Generated", "exec"],
+            let res = julia.base.call_fn(
+                &["Main", &self.namespace, &generation.module_name, "exec"],
                 |frame, inputs| {
                     inputs.append(&mut global_data.as_julia_values(frame)?);
                     inputs.push(Value::new(frame, do_feedback)?);
                     inputs.push(Value::new(frame, note_input)?);
                     inputs.push(Value::new(frame, static_index)?);
-                    inputs.push(Value::new(frame, view_index)?);
+                    let watched_modules = watched_modules.iter().map(|i| *i as i32).collect();
+                    inputs.push(IOData::IntArray(watched_modules).as_julia_value(frame)?);
                     for item in dyn_data {
                         inputs.push(item.as_julia_value(frame)?);
                     }
@@ -425,13 +1125,24 @@ impl AudiobenchExecutor {
                                 )))
                             }
                         };
-                        for index in 0..julia_view_data.n_fields() {
-                            let field = julia_view_data.get_nth_field(frame, index)?;
-                            let field = field.cast::<TypedArray<'_, '_, f32>>()?;
-                            let field = field.inline_data(frame)?.into_slice();
-                            native_feedback.output_view.push(Vec::from(field));
+                        // There is an entry for every module, which is empty unless the module
+                        // is watched.
+                        for module_index in 0..julia_view_data.n_fields() {
+                            let module_view = julia_view_data.get_nth_field(frame, module_index)?;
+                            if module_view.n_fields() == 0 {
+                                continue;
+                            }
+                            let mut output_view = Vec::new();
+                            for index in 0..module_view.n_fields() {
+                                let field = module_view.get_nth_field(frame, index)?;
+                                let field = field.cast::<TypedArray<'_, '_, f32>>()?;
+                                let field = field.inline_data(frame)?.into_slice();
+                                output_view.push(Vec::from(field));
+                            }
+                            native_feedback
+                                .output_views
+                                .push((module_index, output_view));
                         }
-                        native_feedback.output_view_module_index = view_index;
                         feedback_data = Some(native_feedback);
                     }
 
@@ -440,6 +1151,25 @@ impl AudiobenchExecutor {
                         is_dummy = false;
                         return Ok(Ok(()));
                     }
+                    if do_voice_feedback {
+                        let julia_voice_data = match output.get_nth_field(frame, 3) {
+                            Ok(v) => v,
+                            Err(err) => {
+                                return Ok(Err(format!(
+                                    "ERROR: Failed to retrieve voice feedback, caused by:\n{:?}",
+                                    err
+                                )))
+                            }
+                        };
+                        let num_widgets = julia_voice_data.n_fields();
+                        voice_feedback.resize(num_widgets, Vec::new());
+                        for (index, widget_data) in voice_feedback.iter_mut().enumerate() {
+                            let field = julia_voice_data.get_nth_field(frame, index)?;
+                            let field = field.cast::<TypedArray<'_, '_, f32>>()?;
+                            let field = field.inline_data(frame)?.into_slice();
+                            widget_data.push(Vec::from(field));
+                        }
+                    }
                     // 0-based index, not Julia index.
                     let audio = match output.get_nth_field(frame, 0) {
                         Ok(v) => v,
@@ -460,24 +1190,35 @@ impl AudiobenchExecutor {
                         }
                     };
                     let audio = audio.inline_data(frame)?.into_slice();
-                    let mut silent = true;
-                    for i in 0..buf_len * channels {
-                        audio_output[i] += audio[i];
-                        silent &= audio[i].abs() < SILENT_CUTOFF;
-                    }
-                    if silent {
-                        note.silent_samples += buf_len;
-                    } else {
-                        note.silent_samples = 0;
+                    if !note.mix_into(audio, mix_output, channels, oversampling, crossfade_samples)
+                    {
+                        non_finite.push((note.generation, note.static_index));
                     }
 
                     Ok(Ok(()))
                 },
             );
-            res.map_err(Self::beautify_stack_trace)??;
+            res.map_err(beautify_stack_trace)??;
         }
+        drop(julia);
+        self.decimator.as_mut().unwrap().decimate_into(audio_output);
 
-        notes.advance_all_notes(&self.parameters, global_data);
+        for (generation, static_index) in non_finite {
+            let module_index = self.find_non_finite_module(generation, static_index)?;
+            notes.reset_static_data_later(generation, static_index);
+            self.non_finite_voices.push(NonFiniteVoice {
+                generation,
+                module_index,
+            });
+        }
+        self.profiler.add_buffer(&parameters);
+        if let Some(feedback_data) = &mut feedback_data {
+            feedback_data.module_cpu_usage = self.take_module_cpu_usage()?;
+            feedback_data.voice = feedback_note.and_then(|(_, key)| key);
+            feedback_data.voice_feedback = voice_feedback;
+        }
+        notes.advance_all_notes(&parameters, global_data);
+        self.unload_unused_generations(notes);
         Ok(feedback_data)
     }
 }
@@ -2,7 +2,7 @@ use std::cmp::Ordering;
 
 use crate::{
     engine::{controls::Control, parts::JackType, Status, UiThreadEngine},
-    gui::{constants::*, top_level::*},
+    gui::{constants::*, master_analyzer::MasterAnalyzer, top_level::*},
     registry::{save_data::Patch, Registry},
     scui_config::{DropTarget, MaybeMouseBehavior, Renderer},
 };
@@ -28,6 +28,7 @@ impl InteractionHint {
         use JackType::*;
         match ty {
             Audio => 0,
+            Control => 0,
             Pitch => 1,
             Trigger => 1,
             Waveform => 1,
@@ -106,6 +107,20 @@ impl StatusMessage {
             color: COLOR_ERROR,
         }
     }
+
+    fn warning(text: String) -> Self {
+        Self {
+            text,
+            color: COLOR_WARNING,
+        }
+    }
+
+    fn info(text: String) -> Self {
+        Self {
+            text,
+            color: COLOR_BG2,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -115,6 +130,8 @@ pub enum TabArchetype {
     ModuleBrowser(Rc<graph::ModuleGraph>),
     LibraryInfo,
     MessageLog,
+    NoteProcessor,
+    Analyzer,
 }
 
 impl TabArchetype {
@@ -158,6 +175,20 @@ impl TabArchetype {
                     false
                 }
             }
+            NoteProcessor => {
+                if let NoteProcessor = other {
+                    true
+                } else {
+                    false
+                }
+            }
+            Analyzer => {
+                if let Analyzer = other {
+                    true
+                } else {
+                    false
+                }
+            }
         }
     }
 
@@ -168,6 +199,8 @@ impl TabArchetype {
             Self::ModuleBrowser(add_to) => Rc::new(ModuleBrowser::new(parent, add_to)) as _,
             Self::LibraryInfo => Rc::new(LibraryInfo::new(parent)) as _,
             Self::MessageLog => Rc::new(MessageLog::new(parent)) as _,
+            Self::NoteProcessor => Rc::new(NoteProcessorEditor::new(parent)) as _,
+            Self::Analyzer => Rc::new(Analyzer::new(parent)) as _,
         }
     }
 }
@@ -177,8 +210,11 @@ pub struct GuiState {
     pub engine: Rcrc<UiThreadEngine>,
     pub current_patch_index: Option<usize>,
     pub patch_list: ObservablePtr<Vec<Rcrc<Patch>>>,
+    /// Analysis of the audio most recently output by the engine.
+    pub master_analyzer: MasterAnalyzer,
     messages: Vec<StatusMessage>,
-    last_message: bool,
+    /// Index of the message shown in the header, if any.
+    last_message: Option<usize>,
     tooltip: Tooltip,
     // Yeah I know  this is doing Rc<Rc<Widget>> but I don't know what else to do at the moment.
     tabs: Vec<Rc<dyn GuiTab>>,
@@ -210,8 +246,9 @@ impl GuiState {
             engine,
             current_patch_index,
             patch_list: observable(patch_list),
+            master_analyzer: Default::default(),
             messages: Vec::new(),
-            last_message: false,
+            last_message: None,
             tooltip: Default::default(),
             tabs: Vec::new(),
             current_tab_index: 0,
@@ -286,8 +323,8 @@ impl GuiState {
     }
 
     pub fn add_message(&mut self, message: StatusMessage) {
+        self.last_message = Some(self.messages.len());
         self.messages.push(message);
-        self.last_message = true;
     }
 
     pub fn add_success_message(&mut self, message: String) {
@@ -298,16 +335,21 @@ impl GuiState {
         self.add_message(StatusMessage::error(message))
     }
 
+    pub fn add_warning_message(&mut self, message: String) {
+        self.add_message(StatusMessage::warning(message))
+    }
+
+    /// Adds a message to the message log without showing it in the header.
+    pub fn add_info_message(&mut self, message: String) {
+        self.messages.push(StatusMessage::info(message));
+    }
+
     pub fn clear_last_message(&mut self) {
-        self.last_message = false;
+        self.last_message = None;
     }
 
     pub fn borrow_last_message(&self) -> Option<&StatusMessage> {
-        if self.last_message {
-            self.messages.last()
-        } else {
-            None
-        }
+        self.last_message.map(|index| &self.messages[index])
     }
 
     pub fn borrow_all_messages(&self) -> &[StatusMessage] {
@@ -381,6 +423,15 @@ impl WidgetImpl<Renderer, DropTarget> for Root {
             for error in new_errors {
                 state.add_error_message(error);
             }
+            let warnings = state.engine.borrow_mut().take_warnings();
+            for warning in warnings {
+                state.add_warning_message(warning);
+            }
+            let compile_log = state.engine.borrow_mut().take_compile_log();
+            for message in compile_log {
+                state.add_info_message(message);
+            }
+            state.master_analyzer.update(&*state.engine.borrow());
         });
 
         renderer.set_color(&COLOR_BG0);
@@ -0,0 +1,183 @@
+use crate::{
+    engine::UiThreadEngine,
+    gui::{
+        constants::*,
+        spectrum::{Spectrum, SPECTRUM_MIN_DB},
+    },
+    scui_config::Renderer,
+};
+use scui::Vec2D;
+use shared_util::prelude::*;
+use std::{collections::VecDeque, time::Instant};
+
+/// How many of the most recent frames of the master output are kept, must be a power of two so
+/// that the spectrum does not need padding.
+pub const MASTER_HISTORY_LENGTH: usize = 2048;
+/// Levels are clamped to this value in level meters.
+pub const METER_MIN_DB: f32 = -60.0;
+/// How long the peak level stays in place before it starts falling.
+const PEAK_HOLD_TIME: f32 = 1.0;
+/// How quickly the peak level falls after it has been held, in decibels per second.
+const PEAK_FALL_RATE: f32 = 20.0;
+
+fn to_db(amplitude: f32) -> f32 {
+    20.0 * amplitude.max(1e-9).log10()
+}
+
+/// Peak and RMS level of one channel, in decibels.
+#[derive(Clone, Copy, Debug)]
+pub struct ChannelLevel {
+    pub peak: f32,
+    pub rms: f32,
+    /// The highest recent peak, which falls slowly so that short peaks can be read.
+    pub held_peak: f32,
+    held_peak_age: f32,
+}
+
+impl Default for ChannelLevel {
+    fn default() -> Self {
+        Self {
+            peak: SPECTRUM_MIN_DB,
+            rms: SPECTRUM_MIN_DB,
+            held_peak: SPECTRUM_MIN_DB,
+            held_peak_age: 0.0,
+        }
+    }
+}
+
+impl ChannelLevel {
+    fn update(&mut self, samples: impl Iterator<Item = f32>, elapsed: f32) {
+        let (mut peak, mut sum, mut count) = (0.0f32, 0.0, 0);
+        for sample in samples {
+            peak = peak.max(sample.abs());
+            sum += sample * sample;
+            count += 1;
+        }
+        self.peak = to_db(peak);
+        self.rms = to_db((sum / count.max(1) as f32).sqrt());
+        self.held_peak_age += elapsed;
+        let fall = (self.held_peak_age - PEAK_HOLD_TIME).clam(0.0, elapsed);
+        self.held_peak = (self.held_peak - fall * PEAK_FALL_RATE).max(SPECTRUM_MIN_DB);
+        if self.peak >= self.held_peak {
+            self.held_peak = self.peak;
+            self.held_peak_age = 0.0;
+        }
+    }
+}
+
+/// Keeps track of the audio the engine has output most recently so that it can be displayed as
+/// an oscilloscope, level meters and a spectrum.
+pub struct MasterAnalyzer {
+    /// Oldest frame first.
+    history: VecDeque<(f32, f32)>,
+    levels: [ChannelLevel; 2],
+    spectrum: Spectrum,
+    last_update: Instant,
+}
+
+impl Default for MasterAnalyzer {
+    fn default() -> Self {
+        Self {
+            history: vec![(0.0, 0.0); MASTER_HISTORY_LENGTH].into(),
+            levels: Default::default(),
+            spectrum: Default::default(),
+            last_update: Instant::now(),
+        }
+    }
+}
+
+impl MasterAnalyzer {
+    /// Analyzes whatever audio the engine has output since the last time this was called.
+    pub fn update(&mut self, engine: &UiThreadEngine) {
+        let mut new_frames = 0;
+        while let Some(frame) = engine.take_master_frame() {
+            self.history.pop_front();
+            self.history.push_back(frame);
+            new_frames += 1;
+        }
+        if new_frames == 0 {
+            return;
+        }
+        let elapsed = self.last_update.elapsed().as_secs_f32();
+        self.last_update = Instant::now();
+        let new_frames = new_frames.min(MASTER_HISTORY_LENGTH);
+        let recent = self.history.iter().skip(MASTER_HISTORY_LENGTH - new_frames);
+        self.levels[0].update(recent.clone().map(|frame| frame.0), elapsed);
+        self.levels[1].update(recent.map(|frame| frame.1), elapsed);
+        let mono: Vec<f32> = self
+            .history
+            .iter()
+            .map(|(left, right)| (left + right) / 2.0)
+            .collect();
+        self.spectrum
+            .update(&mono[..], engine.get_host_sample_rate() as f32);
+    }
+
+    pub fn borrow_history(&self) -> &VecDeque<(f32, f32)> {
+        &self.history
+    }
+
+    /// Left channel first.
+    pub fn get_levels(&self) -> [ChannelLevel; 2] {
+        self.levels
+    }
+
+    pub fn borrow_spectrum(&self) -> &Spectrum {
+        &self.spectrum
+    }
+}
+
+impl Renderer {
+    /// Draws the most recent `length` frames of the master output, the left channel in the top
+    /// half and the right channel in the bottom half.
+    pub fn draw_master_scope(&mut self, analyzer: &MasterAnalyzer, size: Vec2D, length: usize) {
+        let history = analyzer.borrow_history();
+        let length = length.min(history.len());
+        let start = history.len() - length;
+        let half = size.y / 2.0;
+        self.set_color(&COLOR_FG1);
+        self.set_alpha(0.3);
+        self.draw_line((0.0, half), (size.x, half), 1.0);
+        self.set_alpha(1.0);
+        for channel in 0..2 {
+            let center = half * (channel as f32 + 0.5);
+            let point = |index: usize| {
+                let frame = history[start + index];
+                let sample = if channel == 0 { frame.0 } else { frame.1 };
+                let x = index as f32 / (length - 1).max(1) as f32 * size.x;
+                Vec2D::new(x, center - sample.clam(-1.0, 1.0) * half / 2.0)
+            };
+            let mut old_point = point(0);
+            for index in 1..length {
+                let new_point = point(index);
+                self.draw_line(old_point, new_point, 1.0);
+                old_point = new_point;
+            }
+        }
+    }
+
+    /// Draws a vertical bar for each channel which is filled up to the RMS level, with lines at
+    /// the current and held peak levels.
+    pub fn draw_master_meter(&mut self, analyzer: &MasterAnalyzer, size: Vec2D) {
+        let bar_width = size.x / 2.0;
+        let height_of = |db: f32| {
+            db.from_range_to_range(METER_MIN_DB, 0.0, 0.0, size.y)
+                .clam(0.0, size.y)
+        };
+        for (channel, level) in analyzer.get_levels().iter().enumerate() {
+            let x = channel as f32 * bar_width;
+            let rms = height_of(level.rms);
+            self.set_color(&COLOR_EDITABLE);
+            self.draw_rect((x + 1.0, size.y - rms), (bar_width - 2.0, rms));
+            self.set_color(&COLOR_FG1);
+            let peak = size.y - height_of(level.peak);
+            self.draw_line((x + 1.0, peak), (x + bar_width - 1.0, peak), 1.0);
+            // Anything at or above 0dB is clipping.
+            if level.held_peak >= 0.0 {
+                self.set_color(&COLOR_ERROR);
+            }
+            let held_peak = size.y - height_of(level.held_peak);
+            self.draw_line((x + 1.0, held_peak), (x + bar_width - 1.0, held_peak), 2.0);
+        }
+    }
+}
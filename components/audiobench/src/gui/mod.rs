@@ -1,8 +1,10 @@
 mod base;
 pub mod constants;
 pub mod graphics;
+pub mod master_analyzer;
 pub mod module_widgets;
 pub mod mouse_behaviors;
+pub mod spectrum;
 pub mod top_level;
 mod ui_widgets;
 
@@ -0,0 +1,250 @@
+use crate::{
+    engine::controls::{Control, CurveControl},
+    gui::{
+        constants::*,
+        module_widgets::ModuleWidgetImpl,
+        mouse_behaviors::{ContinuouslyMutateControl, MutateControl},
+        InteractionHint, Tooltip,
+    },
+    scui_config::{DropTarget, MaybeMouseBehavior, Renderer},
+};
+use scui::{MouseMods, Vec2D, WidgetImpl};
+use shared_util::prelude::*;
+
+yaml_widget_boilerplate::make_widget_outline! {
+    widget_struct: CurveEditor,
+    constructor: new(
+        parent: ParentRef,
+        pos: GridPos,
+        size: GridSize,
+        curve_control: CurveControlRef,
+        tooltip: String,
+    ),
+    // Feedback for where the cursor is, as a fraction of the width of the curve.
+    feedback: ManualValue,
+}
+
+scui::widget! {
+    pub CurveEditor
+    State {
+        tooltip: String,
+        curve_control: Rcrc<CurveControl>,
+        pos: Vec2D,
+        size: Vec2D,
+        cursor: f32,
+    }
+}
+
+/// How close the mouse has to be to a point to grab it instead of the segment behind it.
+const GRAB_RADIUS: f32 = GRID_P * 1.5;
+const HANDLE_SIZE: f32 = GRID_P;
+/// How many lines each curved segment is drawn with.
+const LINES_PER_SEGMENT: usize = 16;
+
+impl CurveEditor {
+    fn new(
+        parent: &impl CurveEditorParent,
+        pos: Vec2D,
+        size: Vec2D,
+        curve_control: Rcrc<CurveControl>,
+        tooltip: String,
+    ) -> Rc<Self> {
+        let state = CurveEditorState {
+            tooltip,
+            curve_control,
+            pos,
+            size,
+            cursor: -1.0,
+        };
+        Rc::new(Self::create(parent, state))
+    }
+
+    /// Returns the index of the point under the mouse, if there is one.
+    fn point_at(self: &Rc<Self>, pos: Vec2D) -> Option<usize> {
+        let state = self.state.borrow();
+        let size = state.size;
+        let control = state.curve_control.borrow();
+        let mut nearest = None;
+        let mut nearest_distance = GRAB_RADIUS;
+        for (index, point) in control.get_points().iter().enumerate() {
+            let point_pos = Vec2D::new(point.x * size.x, (1.0 - point.y) * size.y);
+            let offset = point_pos - pos;
+            let distance = (offset.x * offset.x + offset.y * offset.y).sqrt();
+            if distance <= nearest_distance {
+                nearest = Some(index);
+                nearest_distance = distance;
+            }
+        }
+        nearest
+    }
+
+    fn point_tooltip(control: &CurveControl, index: usize) -> Tooltip {
+        let (min, max) = control.get_range();
+        let point = control.get_points()[index];
+        Tooltip {
+            text: format!(
+                "{}% through, {}",
+                format_decimal(point.x * 100.0, 3),
+                format_decimal(point.y * (max - min) + min, 3)
+            ),
+            interaction: vec![
+                InteractionHint::LeftClickAndDrag,
+                InteractionHint::PrecisionModifier,
+                InteractionHint::SnappingModifier,
+            ],
+        }
+    }
+}
+
+impl WidgetImpl<Renderer, DropTarget> for CurveEditor {
+    fn get_pos_impl(self: &Rc<Self>) -> Vec2D {
+        self.state.borrow().pos
+    }
+
+    fn get_size_impl(self: &Rc<Self>) -> Vec2D {
+        self.state.borrow().size
+    }
+
+    fn get_mouse_behavior_impl(
+        self: &Rc<Self>,
+        pos: Vec2D,
+        mods: &MouseMods,
+    ) -> MaybeMouseBehavior {
+        let point_index = self.point_at(pos);
+        let state = self.state.borrow();
+        let size = state.size;
+        let cref = Rc::clone(&state.curve_control);
+        if mods.right_click {
+            // Right clicking a point removes it, right clicking anywhere else adds one.
+            return if let Some(index) = point_index {
+                MutateControl::wrap(self, move || cref.borrow_mut().remove_point(index))
+            } else {
+                let (x, y) = (pos.x / size.x, 1.0 - pos.y / size.y);
+                MutateControl::wrap(self, move || cref.borrow_mut().add_point(x, y))
+            };
+        }
+        let control = state.curve_control.borrow();
+        if let Some(index) = point_index {
+            let point = control.get_points()[index];
+            let (mut x, mut y) = (point.x, point.y);
+            drop(control);
+            ContinuouslyMutateControl::wrap_2d(self, move |delta, steps| {
+                x = (x + delta.x / size.x).clam(0.0, 1.0);
+                y = (y - delta.y / size.y).clam(0.0, 1.0);
+                let (final_x, final_y) = if let Some(steps) = steps {
+                    (x.snap(0.0, 1.0, steps), y.snap(0.0, 1.0, steps))
+                } else {
+                    (x, y)
+                };
+                let mut control = cref.borrow_mut();
+                let update = control.move_point(index, final_x, final_y);
+                (update, Some(Self::point_tooltip(&control, index)))
+            })
+        } else {
+            // Dragging a segment changes its curvature.
+            let points = control.get_points();
+            let position = pos.x / size.x;
+            let segment = points
+                .iter()
+                .position(|point| point.x > position)
+                .unwrap_or(points.len() - 1)
+                .max(1)
+                - 1;
+            let mut curvature = points[segment].curvature;
+            // Dragging up should always bend the segment upwards.
+            let direction = if points[segment + 1].y < points[segment].y {
+                -1.0
+            } else {
+                1.0
+            };
+            drop(control);
+            ContinuouslyMutateControl::wrap(self, move |delta, steps| {
+                curvature = (curvature + delta * direction / 100.0).clam(-1.0, 1.0);
+                let final_curvature = if let Some(steps) = steps {
+                    curvature.snap(-1.0, 1.0, steps)
+                } else {
+                    curvature
+                };
+                let update = cref.borrow_mut().set_curvature(segment, final_curvature);
+                let tooltip = Tooltip {
+                    text: format!("Curvature: {}", format_decimal(final_curvature, 3)),
+                    interaction: vec![
+                        InteractionHint::SnappingModifier,
+                        InteractionHint::PrecisionModifier,
+                    ],
+                };
+                (update, Some(tooltip))
+            })
+        }
+    }
+
+    fn on_hover_impl(self: &Rc<Self>, _pos: Vec2D) -> Option<()> {
+        let tooltip = Tooltip {
+            text: self.state.borrow().tooltip.clone(),
+            interaction: vec![
+                InteractionHint::LeftClickAndDrag,
+                InteractionHint::RightClick,
+            ],
+        };
+        self.with_gui_state_mut(|state| {
+            state.set_tooltip(tooltip);
+        });
+        Some(())
+    }
+
+    fn draw_impl(self: &Rc<Self>, g: &mut Renderer) {
+        let state = self.state.borrow();
+        let size = state.size;
+        const HS: f32 = HANDLE_SIZE;
+        g.set_color(&COLOR_BG0);
+        g.draw_rounded_rect(0, size, CORNER_SIZE);
+
+        let control = state.curve_control.borrow();
+        let (min, max) = control.get_range();
+        // Mark where zero is if the curve goes through it.
+        if min < 0.0 && max > 0.0 {
+            let y = 0.0f32.from_range_to_range(min, max, size.y, 0.0);
+            g.set_color(&COLOR_FG1);
+            g.set_alpha(0.3);
+            g.draw_line((0.0, y), (size.x, y), 1.0);
+            g.set_alpha(1.0);
+        }
+
+        let to_screen = |x: f32, y: f32| Vec2D::new(x * size.x, (1.0 - y) * size.y);
+        let points = control.get_points();
+        g.set_color(&COLOR_EDITABLE);
+        for segment in points.windows(2) {
+            let (start, end) = (segment[0], segment[1]);
+            let mut old_point = to_screen(start.x, start.y);
+            for step in 1..=LINES_PER_SEGMENT {
+                let x = start.x + (end.x - start.x) * step as f32 / LINES_PER_SEGMENT as f32;
+                let new_point = to_screen(x, control.value_at(x));
+                g.draw_line(old_point, new_point, 2.0);
+                old_point = new_point;
+            }
+        }
+        for point in points {
+            let center = to_screen(point.x, point.y);
+            g.draw_rect(center - HS / 2.0, HS);
+        }
+
+        if state.cursor >= 0.0 {
+            let x = state.cursor.min(1.0) * size.x;
+            g.set_color(&COLOR_FG1);
+            g.set_alpha(0.5);
+            g.draw_line((x, 0.0), (x, size.y), 2.0);
+            g.set_alpha(1.0);
+        }
+    }
+}
+
+impl ModuleWidgetImpl for CurveEditor {
+    fn represented_control(self: &Rc<Self>) -> Option<Rcrc<dyn Control>> {
+        Some(Rc::clone(&self.state.borrow().curve_control) as _)
+    }
+
+    fn take_feedback_data(self: &Rc<Self>, data: Vec<f32>) {
+        assert_eq!(data.len(), 1);
+        self.state.borrow_mut().cursor = data[0];
+    }
+}
@@ -13,8 +13,8 @@ yaml_widget_boilerplate::make_widget_outline! {
         pos: GridPos,
         size: GridSize,
     ),
-    // 4 for parameters, 2 for cursor.
-    feedback: ManualValue,
+    // 2 for cursor, 4 for parameters. Other voices only send their cursor.
+    feedback: ManualValuePerVoice,
 }
 
 scui::widget! {
@@ -23,6 +23,7 @@ scui::widget! {
         pos: Vec2D,
         size: Vec2D,
         feedback: Option<[f32; 6]>,
+        voice_cursors: Vec<(f32, f32)>,
     }
 }
 
@@ -32,12 +33,13 @@ impl EnvelopeGraph {
             pos,
             size,
             feedback: None,
+            voice_cursors: Vec::new(),
         };
         Rc::new(Self::create(parent, state))
     }
 }
 
-const BLANK_FEEDBACK: [f32; 6] = [0.0, 0.1, 1.0, 0.2, 0.0, -1.0];
+const BLANK_FEEDBACK: [f32; 6] = [0.0, -1.0, 0.0, 0.1, 1.0, 0.2];
 
 impl WidgetImpl<Renderer, DropTarget> for EnvelopeGraph {
     fn get_pos_impl(self: &Rc<Self>) -> Vec2D {
@@ -59,10 +61,10 @@ impl WidgetImpl<Renderer, DropTarget> for EnvelopeGraph {
 
         g.set_color(&COLOR_FG1);
         let (a, d, s, r) = (
-            feedback_data[0],
-            feedback_data[1],
             feedback_data[2],
             feedback_data[3],
+            feedback_data[4],
+            feedback_data[5],
         );
         let total_duration = (a + d + r).max(0.2); // to prevent div0
         let w = state.size.x;
@@ -78,16 +80,20 @@ impl WidgetImpl<Renderer, DropTarget> for EnvelopeGraph {
         g.set_alpha(0.5);
         g.draw_line((decay_x, -CS), (decay_x, h + CS), 1.0);
         g.draw_line((release_x, -CS), (release_x, h + CS), 1.0);
-        let cursor_pos = Vec2D::new(
-            feedback_data[4] / total_duration * w,
-            (-feedback_data[5] * 0.5 + 0.5) * h,
-        );
-        g.draw_line((cursor_pos.x, 0.0), (cursor_pos.x, h), 1.0);
-        g.draw_line((0.0, cursor_pos.y), (w, cursor_pos.y), 1.0);
-        g.set_alpha(1.0);
+        let cursor_pos = |(time, value): (f32, f32)| {
+            Vec2D::new(time / total_duration * w, (-value * 0.5 + 0.5) * h)
+        };
+        let main_cursor = cursor_pos((feedback_data[0], feedback_data[1]));
+        g.draw_line((main_cursor.x, 0.0), (main_cursor.x, h), 1.0);
+        g.draw_line((0.0, main_cursor.y), (w, main_cursor.y), 1.0);
         const DOT_SIZE: f32 = 8.0;
         const DR: f32 = DOT_SIZE / 2.0;
-        g.draw_pie(cursor_pos - DR, DR * 2.0, 0.0, 0.0, PI * 2.0);
+        // Every other voice that is playing gets a smaller, fainter dot.
+        for cursor in &state.voice_cursors {
+            g.draw_pie(cursor_pos(*cursor) - DR / 2.0, DR, 0.0, 0.0, PI * 2.0);
+        }
+        g.set_alpha(1.0);
+        g.draw_pie(main_cursor - DR, DR * 2.0, 0.0, 0.0, PI * 2.0);
 
         let ms = (total_duration * 1000.0) as i32;
         let ms_text = if ms > 999 {
@@ -104,4 +110,12 @@ impl ModuleWidgetImpl for EnvelopeGraph {
         assert_eq!(data.len(), 6);
         self.state.borrow_mut().feedback = Some(data.try_into().unwrap());
     }
+
+    fn take_voice_feedback_data(self: &Rc<Self>, data: Vec<Vec<f32>>) {
+        self.state.borrow_mut().voice_cursors = data
+            .into_iter()
+            .filter(|voice| voice.len() == 2)
+            .map(|voice| (voice[0], voice[1]))
+            .collect();
+    }
 }
@@ -1,3 +1,4 @@
+mod curve_editor;
 mod duration_box;
 mod envelope_graph;
 mod frequency_box;
@@ -9,12 +10,17 @@ mod mini_knob;
 mod option_box;
 mod option_icon_grid;
 mod outline_stuff;
+mod sample_waveform;
 mod timing_selector;
 mod traits;
 mod trigger_sequence;
 mod value_sequence;
+mod waveform_drawer;
 mod waveform_graph;
+mod wavetable_shapes;
+mod wavetable_view;
 
+pub use curve_editor::*;
 pub use duration_box::*;
 pub use envelope_graph::*;
 pub use frequency_box::*;
@@ -26,8 +32,12 @@ pub use mini_knob::*;
 pub use option_box::*;
 pub use option_icon_grid::*;
 pub use outline_stuff::*;
+pub use sample_waveform::*;
 pub use timing_selector::*;
 pub use traits::*;
 pub use trigger_sequence::*;
 pub use value_sequence::*;
+pub use waveform_drawer::*;
 pub use waveform_graph::*;
+pub use wavetable_shapes::*;
+pub use wavetable_view::*;
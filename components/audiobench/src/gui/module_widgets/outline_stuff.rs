@@ -1,13 +1,18 @@
 use crate::gui::module_widgets::*;
 
+/// ManualValuePerVoice is like ManualValue, but the vector is also collected from every other
+/// active voice. Modules should always push the values describing their own voice first and only
+/// push the rest when do_feedback is true.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum FeedbackMode {
     None,
     ControlSignal { control_index: usize },
     ManualValue { name: String },
+    ManualValuePerVoice { name: String },
 }
 
 yaml_widget_boilerplate::make_widget_outline_enum![
+    CurveEditor,
     DurationBox,
     EnvelopeGraph,
     FrequencyBox,
@@ -18,10 +23,14 @@ yaml_widget_boilerplate::make_widget_outline_enum![
     MiniKnob,
     OptionBox,
     OptionIconGrid,
+    SampleWaveform,
     TimingSelector,
     TriggerSequence,
     TriggerSequenceLength,
     ValueSequence,
     ValueSequenceLength,
+    WaveformDrawer,
     WaveformGraph,
+    WavetableShapes,
+    WavetableView,
 ];
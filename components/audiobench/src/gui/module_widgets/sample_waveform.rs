@@ -0,0 +1,323 @@
+use crate::{
+    engine::{
+        audio_sample::{AudioSample, SUPPORTED_EXTENSIONS},
+        controls::{Control, SampleControl, SampleMarker, SampleSource, UpdateRequest},
+        UiThreadEngine,
+    },
+    gui::{constants::*, module_widgets::ModuleWidgetImpl, InteractionHint, Tooltip},
+    scui_config::{DropTarget, GuiState, MaybeMouseBehavior, Renderer},
+};
+use scui::{GuiInterface, GuiInterfaceProvider, MouseBehavior, MouseMods, Vec2D, WidgetImpl};
+use shared_util::prelude::*;
+use std::path::Path;
+
+yaml_widget_boilerplate::make_widget_outline! {
+    widget_struct: SampleWaveform,
+    constructor: new(
+        parent: ParentRef,
+        pos: GridPos,
+        size: GridSize,
+        sample_control: SampleControlRef,
+        tooltip: String,
+    ),
+    // Feedback for where the playhead is, as a fraction of the length of the sample.
+    feedback: ManualValue,
+}
+
+scui::widget! {
+    pub SampleWaveform
+    State {
+        tooltip: String,
+        sample_control: Rcrc<SampleControl>,
+        pos: Vec2D,
+        size: Vec2D,
+        /// The lowest and highest value in each column of pixels, along with the sample they were
+        /// computed from.
+        peaks: Option<(Rc<AudioSample>, Vec<(f32, f32)>)>,
+        playhead: f32,
+    }
+}
+
+const MARKER_HANDLE_SIZE: f32 = GRID_P;
+
+impl SampleWaveform {
+    fn new(
+        parent: &impl SampleWaveformParent,
+        pos: Vec2D,
+        size: Vec2D,
+        sample_control: Rcrc<SampleControl>,
+        tooltip: String,
+    ) -> Rc<Self> {
+        let state = SampleWaveformState {
+            tooltip,
+            sample_control,
+            pos,
+            size,
+            peaks: None,
+            playhead: -1.0,
+        };
+        Rc::new(Self::create(parent, state))
+    }
+}
+
+fn compute_peaks(sample: &AudioSample, columns: usize) -> Vec<(f32, f32)> {
+    let len = sample.data.len();
+    (0..columns)
+        .map(|column| {
+            let start = column * len / columns;
+            let end = ((column + 1) * len / columns).max(start + 1).min(len);
+            sample.data[start.min(len)..end]
+                .iter()
+                .fold((0.0f32, 0.0f32), |(min, max), value| {
+                    (min.min(*value), max.max(*value))
+                })
+        })
+        .collect()
+}
+
+/// Decodes a file the user picked. Files which are part of a library are referenced instead of
+/// being embedded in the patch.
+fn load_sample_file(
+    control: &Rcrc<SampleControl>,
+    state: &GuiState,
+    path: &Path,
+) -> Result<UpdateRequest, String> {
+    let registry = state.registry.borrow();
+    if let Some(name) = registry.get_sample_name_by_path(path) {
+        let sample = Rc::clone(registry.get_sample_by_name(name).unwrap());
+        return Ok(control
+            .borrow_mut()
+            .set_library_sample(name.to_owned(), sample));
+    }
+    let file_name = path.to_string_lossy();
+    let buffer = std::fs::read(path).map_err(|err| {
+        format!(
+            "ERROR: Failed to read {}, caused by:\nERROR: {}",
+            file_name, err
+        )
+    })?;
+    let sample = AudioSample::decode(&file_name, &buffer[..])?;
+    control.borrow_mut().set_embedded_sample(sample)
+}
+
+struct DragSampleMarker {
+    engine: Rcrc<UiThreadEngine>,
+    gui_interface: Rc<GuiInterface<GuiState, DropTarget>>,
+    control: Rcrc<SampleControl>,
+    marker: SampleMarker,
+    width: f32,
+    /// Where the marker would be if it was not kept between the other markers.
+    value: f32,
+}
+
+impl MouseBehavior<DropTarget> for DragSampleMarker {
+    fn on_drag(&mut self, delta: Vec2D, mods: &MouseMods) {
+        let mut delta = delta.x / self.width;
+        if mods.precise {
+            delta *= PRECISION_MULTIPLIER;
+        }
+        self.value = (self.value + delta).clam(0.0, 1.0);
+        let mut control = self.control.borrow_mut();
+        let update = control.set_marker(self.marker, self.value);
+        let duration = control
+            .get_sample()
+            .map(|s| s.get_duration())
+            .unwrap_or(0.0);
+        let position = control.get_marker(self.marker) * duration;
+        drop(control);
+        let tooltip = Tooltip {
+            text: format!("{}: {}s", self.marker.name(), format_decimal(position, 4)),
+            interaction: vec![
+                InteractionHint::LeftClickAndDrag,
+                InteractionHint::PrecisionModifier,
+            ],
+        };
+        self.gui_interface.state.borrow_mut().set_tooltip(tooltip);
+        if let UpdateRequest::UpdateDynData = update {
+            self.engine.borrow_mut().reload_dyn_data();
+        }
+    }
+
+    fn on_drop(self: Box<Self>, _drop_target: Option<DropTarget>) {
+        self.engine.borrow().set_dummy_note_active(false);
+    }
+
+    fn on_double_click(self: Box<Self>) {
+        let patterns: Vec<String> = SUPPORTED_EXTENSIONS
+            .iter()
+            .map(|extension| format!("*.{}", extension))
+            .collect();
+        let patterns: Vec<&str> = patterns.iter().map(|p| &p[..]).collect();
+        let path = tinyfiledialogs::open_file_dialog(
+            "Load Sample",
+            "",
+            Some((&patterns[..], "Audio Files")),
+        );
+        let path = if let Some(path) = path {
+            path
+        } else {
+            return;
+        };
+        let mut state = self.gui_interface.state.borrow_mut();
+        match load_sample_file(&self.control, &*state, Path::new(&path)) {
+            Ok(..) => {
+                drop(state);
+                self.engine.borrow_mut().reload_dyn_data();
+                self.engine.borrow().activate_dummy_note_once();
+            }
+            Err(err) => state.add_error_message(err),
+        }
+    }
+}
+
+impl WidgetImpl<Renderer, DropTarget> for SampleWaveform {
+    fn get_pos_impl(self: &Rc<Self>) -> Vec2D {
+        self.state.borrow().pos
+    }
+
+    fn get_size_impl(self: &Rc<Self>) -> Vec2D {
+        self.state.borrow().size
+    }
+
+    fn get_mouse_behavior_impl(
+        self: &Rc<Self>,
+        pos: Vec2D,
+        _mods: &MouseMods,
+    ) -> MaybeMouseBehavior {
+        let state = self.state.borrow();
+        let control = state.sample_control.borrow();
+        // The top half moves the start and end of the sample, the bottom half moves the loop.
+        let candidates = if pos.y < state.size.y / 2.0 {
+            [SampleMarker::Start, SampleMarker::End]
+        } else {
+            [SampleMarker::LoopStart, SampleMarker::LoopEnd]
+        };
+        let position = pos.x / state.size.x;
+        // Picks the nearest marker, or the one that can move towards the mouse if they overlap.
+        let midpoint =
+            (control.get_marker(candidates[0]) + control.get_marker(candidates[1])) / 2.0;
+        let marker = if position < midpoint {
+            candidates[0]
+        } else {
+            candidates[1]
+        };
+        let int = self.provide_gui_interface();
+        let engine = Rc::clone(&int.state.borrow().engine);
+        Some(Box::new(DragSampleMarker {
+            engine,
+            gui_interface: int,
+            control: Rc::clone(&state.sample_control),
+            marker,
+            width: state.size.x,
+            value: control.get_marker(marker),
+        }))
+    }
+
+    fn on_hover_impl(self: &Rc<Self>, _pos: Vec2D) -> Option<()> {
+        let state = self.state.borrow();
+        let description = match state.sample_control.borrow().get_source() {
+            SampleSource::None => "No sample loaded".to_owned(),
+            SampleSource::Library(name) => name.clone(),
+            SampleSource::Embedded => "Sample stored in the patch".to_owned(),
+        };
+        let tooltip = Tooltip {
+            text: format!("{} ({})", state.tooltip, description),
+            interaction: vec![
+                InteractionHint::LeftClickAndDrag,
+                InteractionHint::DoubleClick,
+            ],
+        };
+        drop(state);
+        self.with_gui_state_mut(|state| {
+            state.set_tooltip(tooltip);
+        });
+        Some(())
+    }
+
+    fn draw_impl(self: &Rc<Self>, g: &mut Renderer) {
+        let mut state = self.state.borrow_mut();
+        let size = state.size;
+        const CS: f32 = CORNER_SIZE;
+        const HS: f32 = MARKER_HANDLE_SIZE;
+        g.set_color(&COLOR_BG0);
+        g.draw_rounded_rect(0, size, CS);
+
+        let control_ptr = Rc::clone(&state.sample_control);
+        let control = control_ptr.borrow();
+        let sample = if let Some(sample) = control.get_sample() {
+            sample
+        } else {
+            g.set_color(&COLOR_FG0);
+            let text = if let SampleSource::Library(name) = control.get_source() {
+                format!("{} is missing", name)
+            } else {
+                "Double-click to load a sample".to_owned()
+            };
+            g.draw_text(FONT_SIZE, 0, size, (0, 0), 1, &text);
+            return;
+        };
+
+        let columns = size.x.max(1.0) as usize;
+        let up_to_date = match &state.peaks {
+            Some((peaks_sample, peaks)) => {
+                Rc::ptr_eq(peaks_sample, sample) && peaks.len() == columns
+            }
+            None => false,
+        };
+        if !up_to_date {
+            state.peaks = Some((Rc::clone(sample), compute_peaks(sample, columns)));
+        }
+        let peaks = &state.peaks.as_ref().unwrap().1;
+        g.set_color(&COLOR_EDITABLE);
+        for (column, (min, max)) in peaks.iter().enumerate() {
+            let x = column as f32 + 0.5;
+            let top = max.from_range_to_range(-1.0, 1.0, size.y, 0.0);
+            let bottom = min.from_range_to_range(-1.0, 1.0, size.y, 0.0);
+            g.draw_line((x, top), (x, bottom.max(top + 1.0)), 1.0);
+        }
+
+        // Darken everything outside of the part that gets played.
+        let start = control.get_marker(SampleMarker::Start) * size.x;
+        let end = control.get_marker(SampleMarker::End) * size.x;
+        g.set_color(&COLOR_BG0);
+        g.set_alpha(0.7);
+        g.draw_rect(0, (start, size.y));
+        g.draw_rect((end, 0.0), (size.x - end, size.y));
+        let loop_start = control.get_marker(SampleMarker::LoopStart) * size.x;
+        let loop_end = control.get_marker(SampleMarker::LoopEnd) * size.x;
+        g.set_color(&COLOR_FG1);
+        g.set_alpha(0.1);
+        g.draw_rect((loop_start, 0.0), (loop_end - loop_start, size.y));
+        g.set_alpha(1.0);
+
+        for marker in &SampleMarker::ALL {
+            let x = control.get_marker(*marker) * size.x;
+            g.draw_line((x, 0.0), (x, size.y), 1.0);
+            // Handles are drawn in the half of the widget that moves them.
+            let y = match marker {
+                SampleMarker::Start | SampleMarker::End => 0.0,
+                SampleMarker::LoopStart | SampleMarker::LoopEnd => size.y - HS,
+            };
+            g.draw_rect((x - HS / 2.0, y), (HS, HS));
+        }
+
+        if state.playhead >= 0.0 {
+            g.set_color(&COLOR_FG1);
+            g.set_alpha(0.5);
+            let x = state.playhead * size.x;
+            g.draw_line((x, 0.0), (x, size.y), 2.0);
+            g.set_alpha(1.0);
+        }
+    }
+}
+
+impl ModuleWidgetImpl for SampleWaveform {
+    fn represented_control(self: &Rc<Self>) -> Option<Rcrc<dyn Control>> {
+        Some(Rc::clone(&self.state.borrow().sample_control) as _)
+    }
+
+    fn take_feedback_data(self: &Rc<Self>, data: Vec<f32>) {
+        assert_eq!(data.len(), 1);
+        self.state.borrow_mut().playhead = data[0];
+    }
+}
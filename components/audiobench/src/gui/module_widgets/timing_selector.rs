@@ -25,6 +25,7 @@ scui::widget! {
         control: Rcrc<TimingModeControl>,
         note_icon: usize,
         song_icon: usize,
+        bar_icon: usize,
         time_icon: usize,
         beats_icon: usize,
     }
@@ -44,6 +45,7 @@ impl TimingSelector {
             control,
             note_icon: registry.lookup_icon("Factory:note").unwrap(),
             song_icon: registry.lookup_icon("Factory:treble_clef").unwrap(),
+            bar_icon: registry.lookup_icon("Factory:bar").unwrap(),
             time_icon: registry.lookup_icon("Factory:time").unwrap(),
             beats_icon: registry.lookup_icon("Factory:metronome").unwrap(),
         };
@@ -54,6 +56,20 @@ impl TimingSelector {
         self.state.borrow().control.borrow().uses_elapsed_time()
     }
 
+    fn bar_value(&self) -> bool {
+        self.state.borrow().control.borrow().syncs_to_bars()
+    }
+
+    fn source_name(&self) -> &'static str {
+        if self.bar_value() {
+            "bar"
+        } else if self.source_value() {
+            "song"
+        } else {
+            "note"
+        }
+    }
+
     fn type_value(&self) -> bool {
         self.state.borrow().control.borrow().is_beat_synchronized()
     }
@@ -86,7 +102,7 @@ impl WidgetImpl<Renderer, DropTarget> for TimingSelector {
             text: if pos.x < grid(2) / 2.0 {
                 format!(
                     "Change timing source, current value is \"{}\"",
-                    if self.source_value() { "song" } else { "note" }
+                    self.source_name()
                 )
             } else {
                 format!(
@@ -114,7 +130,9 @@ impl WidgetImpl<Renderer, DropTarget> for TimingSelector {
         g.set_color(&COLOR_BG0);
         g.draw_rounded_rect(0.0, (grid(2), CS * 2.0 + ICON_SIZE), CS);
         g.draw_white_icon(
-            if self.source_value() {
+            if self.bar_value() {
+                state.bar_icon
+            } else if self.source_value() {
                 state.song_icon
             } else {
                 state.note_icon
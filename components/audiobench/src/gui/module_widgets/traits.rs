@@ -15,12 +15,17 @@ pub trait ModuleWidgetImpl: WidgetImpl<Renderer, DropTarget> {
     }
 
     fn take_feedback_data(self: &Rc<Self>, _data: Vec<f32>) {}
+
+    /// Only called for widgets using FeedbackMode::ManualValuePerVoice, with the data from every
+    /// active voice other than the one take_feedback_data() was given data from.
+    fn take_voice_feedback_data(self: &Rc<Self>, _data: Vec<Vec<f32>>) {}
 }
 
 pub trait ModuleWidget: Widget<Renderer, DropTarget> {
     fn represented_control(self: &Self) -> Option<Rcrc<dyn Control>>;
     fn use_input_style_wires(self: &Self) -> bool;
     fn take_feedback_data(self: &Self, data: Vec<f32>);
+    fn take_voice_feedback_data(self: &Self, data: Vec<Vec<f32>>);
 }
 
 impl<T> ModuleWidget for Rc<T>
@@ -39,4 +44,8 @@ where
     fn take_feedback_data(self: &Self, data: Vec<f32>) {
         ModuleWidgetImpl::take_feedback_data(self, data)
     }
+
+    fn take_voice_feedback_data(self: &Self, data: Vec<Vec<f32>>) {
+        ModuleWidgetImpl::take_voice_feedback_data(self, data)
+    }
 }
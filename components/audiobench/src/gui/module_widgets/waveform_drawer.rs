@@ -0,0 +1,215 @@
+use crate::{
+    engine::controls::{
+        Control, DrawMode, DrawnWaveformControl, DRAWN_WAVEFORM_LENGTH, NUM_HARMONICS,
+    },
+    gui::{
+        constants::*,
+        module_widgets::ModuleWidgetImpl,
+        mouse_behaviors::{ContinuouslyMutateControl, MutateControl},
+        InteractionHint, Tooltip,
+    },
+    scui_config::{DropTarget, MaybeMouseBehavior, Renderer},
+};
+use scui::{MouseMods, Vec2D, WidgetImpl};
+use shared_util::prelude::*;
+
+yaml_widget_boilerplate::make_widget_outline! {
+    widget_struct: WaveformDrawer,
+    constructor: new(
+        parent: ParentRef,
+        pos: GridPos,
+        size: GridSize,
+        control: DrawnWaveformControlRef,
+        tooltip: String,
+    ),
+}
+
+scui::widget! {
+    pub WaveformDrawer
+    State {
+        pos: Vec2D,
+        size: Vec2D,
+        control: Rcrc<DrawnWaveformControl>,
+        tooltip: String,
+    }
+}
+
+/// Space at the top of the widget for switching between modes.
+const HEADER_HEIGHT: f32 = FONT_SIZE + GRID_P;
+const MODES: [(DrawMode, &str); 2] = [
+    (DrawMode::Pencil, "Pencil"),
+    (DrawMode::Harmonics, "Harmonics"),
+];
+
+impl WaveformDrawer {
+    fn new(
+        parent: &impl WaveformDrawerParent,
+        pos: Vec2D,
+        size: Vec2D,
+        control: Rcrc<DrawnWaveformControl>,
+        tooltip: String,
+    ) -> Rc<Self> {
+        let state = WaveformDrawerState {
+            pos,
+            size,
+            control,
+            tooltip,
+        };
+        Rc::new(Self::create(parent, state))
+    }
+}
+
+impl WidgetImpl<Renderer, DropTarget> for WaveformDrawer {
+    fn get_pos_impl(self: &Rc<Self>) -> Vec2D {
+        self.state.borrow().pos
+    }
+
+    fn get_size_impl(self: &Rc<Self>) -> Vec2D {
+        self.state.borrow().size
+    }
+
+    fn get_mouse_behavior_impl(
+        self: &Rc<Self>,
+        pos: Vec2D,
+        _mods: &MouseMods,
+    ) -> MaybeMouseBehavior {
+        let state = self.state.borrow();
+        let size = state.size;
+        let cref = Rc::clone(&state.control);
+        if pos.y < HEADER_HEIGHT {
+            let mode = if pos.x < size.x / 2.0 {
+                DrawMode::Pencil
+            } else {
+                DrawMode::Harmonics
+            };
+            return MutateControl::wrap(self, move || cref.borrow_mut().set_mode(mode));
+        }
+        let area = size - (0.0, HEADER_HEIGHT);
+        let mode = state.control.borrow().get_mode();
+        match mode {
+            DrawMode::Pencil => {
+                let index_at = |x: f32| {
+                    ((x / area.x * DRAWN_WAVEFORM_LENGTH as f32) as usize)
+                        .min(DRAWN_WAVEFORM_LENGTH - 1)
+                };
+                let value_at = |y: f32| 1.0 - (y - HEADER_HEIGHT) / area.y * 2.0;
+                let mut mouse = pos;
+                let mut last = (index_at(mouse.x.max(0.0)), value_at(mouse.y));
+                ContinuouslyMutateControl::wrap_2d(self, move |delta, _steps| {
+                    mouse += delta;
+                    let now = (index_at(mouse.x.max(0.0)), value_at(mouse.y));
+                    let update = cref.borrow_mut().draw_line(last.0, last.1, now.0, now.1);
+                    last = now;
+                    (update, None)
+                })
+            }
+            DrawMode::Harmonics => {
+                let harmonic =
+                    ((pos.x / size.x * NUM_HARMONICS as f32) as usize).min(NUM_HARMONICS - 1);
+                let mut amplitude = state.control.borrow().get_harmonic(harmonic);
+                ContinuouslyMutateControl::wrap(self, move |delta, steps| {
+                    amplitude = (amplitude + delta / 100.0).clam(-1.0, 1.0);
+                    let final_amplitude = if let Some(steps) = steps {
+                        amplitude.snap(-1.0, 1.0, steps)
+                    } else {
+                        amplitude
+                    };
+                    let update = cref.borrow_mut().set_harmonic(harmonic, final_amplitude);
+                    let tooltip = Tooltip {
+                        text: format!(
+                            "Harmonic {}: {}",
+                            harmonic + 1,
+                            format_decimal(final_amplitude, 3)
+                        ),
+                        interaction: vec![
+                            InteractionHint::SnappingModifier,
+                            InteractionHint::PrecisionModifier,
+                        ],
+                    };
+                    (update, Some(tooltip))
+                })
+            }
+        }
+    }
+
+    fn on_hover_impl(self: &Rc<Self>, _pos: Vec2D) -> Option<()> {
+        let tooltip = Tooltip {
+            text: self.state.borrow().tooltip.clone(),
+            interaction: vec![
+                InteractionHint::LeftClick,
+                InteractionHint::LeftClickAndDrag,
+            ],
+        };
+        self.with_gui_state_mut(|state| {
+            state.set_tooltip(tooltip);
+        });
+        Some(())
+    }
+
+    fn draw_impl(self: &Rc<Self>, g: &mut Renderer) {
+        let state = self.state.borrow();
+        let size = state.size;
+        const HH: f32 = HEADER_HEIGHT;
+        let control = state.control.borrow();
+        let mode = control.get_mode();
+
+        let tab_size = Vec2D::new(size.x / 2.0, HH - GRID_P / 2.0);
+        for (index, (tab_mode, label)) in MODES.iter().enumerate() {
+            let x = index as f32 * tab_size.x;
+            if *tab_mode == mode {
+                g.set_color(&COLOR_FG1);
+                g.draw_rounded_rect((x, 0.0), tab_size, CORNER_SIZE);
+                g.set_color(&COLOR_BG0);
+            } else {
+                g.set_color(&COLOR_FG1);
+            }
+            g.draw_text(FONT_SIZE, (x, 0.0), tab_size, (0, 0), 1, label);
+        }
+
+        let area = size - (0.0, HH);
+        g.set_color(&COLOR_BG0);
+        g.draw_rounded_rect((0.0, HH), area, CORNER_SIZE);
+        let mid = HH + area.y / 2.0;
+        g.set_color(&COLOR_FG1);
+        g.set_alpha(0.3);
+        g.draw_line((0.0, mid), (size.x, mid), 1.0);
+
+        if mode == DrawMode::Harmonics {
+            let bar_width = size.x / NUM_HARMONICS as f32;
+            g.set_color(&COLOR_EDITABLE);
+            g.set_alpha(1.0);
+            for harmonic in 0..NUM_HARMONICS {
+                let top = mid - control.get_harmonic(harmonic) * area.y / 2.0;
+                let x = harmonic as f32 * bar_width;
+                g.draw_rect(
+                    (x + 1.0, mid.min(top)),
+                    (bar_width - 2.0, (mid - top).abs()),
+                );
+            }
+            // The waveform the harmonics make is drawn faintly over them.
+            g.set_color(&COLOR_FG1);
+            g.set_alpha(0.5);
+        } else {
+            g.set_color(&COLOR_EDITABLE);
+            g.set_alpha(1.0);
+        }
+        let table = control.get_table();
+        let point = |index: usize| {
+            let x = index as f32 / DRAWN_WAVEFORM_LENGTH as f32 * size.x;
+            Vec2D::new(x, mid - table[index % DRAWN_WAVEFORM_LENGTH] * area.y / 2.0)
+        };
+        let mut old_point = point(0);
+        for index in 1..=DRAWN_WAVEFORM_LENGTH {
+            let new_point = point(index);
+            g.draw_line(old_point, new_point, 2.0);
+            old_point = new_point;
+        }
+        g.set_alpha(1.0);
+    }
+}
+
+impl ModuleWidgetImpl for WaveformDrawer {
+    fn represented_control(self: &Rc<Self>) -> Option<Rcrc<dyn Control>> {
+        Some(Rc::clone(&self.state.borrow().control) as _)
+    }
+}
@@ -12,7 +12,8 @@ yaml_widget_boilerplate::make_widget_outline! {
         pos: GridPos,
         size: GridSize,
     ),
-    feedback: ManualValue,
+    // 2 for cursor, the rest for the graph. Other voices only send their cursor.
+    feedback: ManualValuePerVoice,
 }
 
 scui::widget! {
@@ -21,6 +22,7 @@ scui::widget! {
         pos: Vec2D,
         size: Vec2D,
         feedback: Vec<f32>,
+        voice_cursors: Vec<(f32, f32)>,
     }
 }
 
@@ -30,6 +32,7 @@ impl WaveformGraph {
             pos,
             size,
             feedback: Vec::new(),
+            voice_cursors: Vec::new(),
         };
         Rc::new(Self::create(parent, state))
     }
@@ -69,6 +72,13 @@ impl WidgetImpl<Renderer, DropTarget> for WaveformGraph {
             g.draw_line(old_point, new_point, 1.0);
             old_point = new_point;
         }
+        // Every other voice that is playing gets a fainter vertical line.
+        g.set_alpha(0.4);
+        for (cursor_phase, _) in &state.voice_cursors {
+            let x = state.size.x * cursor_phase;
+            g.draw_line((x, 0.0), (x, state.size.y), 1.0);
+        }
+        g.set_alpha(1.0);
         let cursor_phase = feedback[0];
         let cursor_value = feedback[1];
         if cursor_phase >= 0.0 {
@@ -85,4 +95,12 @@ impl ModuleWidgetImpl for WaveformGraph {
         assert!(data.len() >= 4);
         self.state.borrow_mut().feedback = data;
     }
+
+    fn take_voice_feedback_data(self: &Rc<Self>, data: Vec<Vec<f32>>) {
+        self.state.borrow_mut().voice_cursors = data
+            .into_iter()
+            .filter(|voice| voice.len() == 2 && voice[0] >= 0.0)
+            .map(|voice| (voice[0], voice[1]))
+            .collect();
+    }
 }
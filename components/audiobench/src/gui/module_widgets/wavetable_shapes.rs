@@ -0,0 +1,141 @@
+use crate::{
+    engine::{
+        controls::{default_option_descriptions_for, WavetableControl, WavetableSource},
+        parts::JackType,
+    },
+    gui::{
+        constants::*, module_widgets::ModuleWidgetImpl, mouse_behaviors::MutateControl,
+        InteractionHint, Tooltip,
+    },
+    scui_config::{DropTarget, MaybeMouseBehavior, Renderer},
+};
+use scui::{GuiInterfaceProvider, MouseMods, Vec2D, WidgetImpl};
+use shared_util::prelude::*;
+
+yaml_widget_boilerplate::make_widget_outline! {
+    widget_struct: WavetableShapes,
+    constructor: new(
+        parent: ParentRef,
+        pos: GridPos,
+        size: GridSize,
+        table: WavetableControlRef,
+        tooltip: String,
+    ),
+}
+
+scui::widget! {
+    pub WavetableShapes
+    State {
+        pos: Vec2D,
+        size: Vec2D,
+        table: Rcrc<WavetableControl>,
+        tooltip: String,
+        /// The icons of the default options for waveform inputs.
+        icons: Vec<usize>,
+    }
+}
+
+impl WavetableShapes {
+    fn new(
+        parent: &impl WavetableShapesParent,
+        pos: Vec2D,
+        size: Vec2D,
+        table: Rcrc<WavetableControl>,
+        tooltip: String,
+    ) -> Rc<Self> {
+        let int = parent.provide_gui_interface();
+        let gui_state = int.state.borrow();
+        let registry = gui_state.registry.borrow();
+        let icons = default_option_descriptions_for(JackType::Waveform)
+            .iter()
+            .map(|option| registry.lookup_icon(option.icon).unwrap_or(0))
+            .collect();
+        let state = WavetableShapesState {
+            pos,
+            size,
+            table,
+            tooltip,
+            icons,
+        };
+        Rc::new(Self::create(parent, state))
+    }
+
+    /// The top row picks the first frame of the table, the bottom row picks the last one.
+    fn icon_size(self: &Rc<Self>) -> f32 {
+        let state = self.state.borrow();
+        (state.size.x / state.icons.len() as f32).min(state.size.y / 2.0)
+    }
+}
+
+impl WidgetImpl<Renderer, DropTarget> for WavetableShapes {
+    fn get_pos_impl(self: &Rc<Self>) -> Vec2D {
+        self.state.borrow().pos
+    }
+
+    fn get_size_impl(self: &Rc<Self>) -> Vec2D {
+        self.state.borrow().size
+    }
+
+    fn get_mouse_behavior_impl(
+        self: &Rc<Self>,
+        pos: Vec2D,
+        _mods: &MouseMods,
+    ) -> MaybeMouseBehavior {
+        let icon_size = self.icon_size();
+        let state = self.state.borrow();
+        let option = (pos.x / icon_size).floor() as usize;
+        let row = (pos.y / icon_size).floor() as usize;
+        if option >= state.icons.len() || row > 1 {
+            return None;
+        }
+        let (from, to) = match state.table.borrow().get_source() {
+            WavetableSource::Generated { from, to } => (from, to),
+            // Switching away from an imported table uses the clicked shape for both ends.
+            WavetableSource::Imported => (option, option),
+        };
+        let (from, to) = if row == 0 {
+            (option, to)
+        } else {
+            (from, option)
+        };
+        let table = Rc::clone(&state.table);
+        MutateControl::wrap(self, move || table.borrow_mut().set_generated(from, to))
+    }
+
+    fn on_hover_impl(self: &Rc<Self>, _pos: Vec2D) -> Option<()> {
+        let tooltip = Tooltip {
+            text: self.state.borrow().tooltip.clone(),
+            interaction: vec![InteractionHint::LeftClick],
+        };
+        self.with_gui_state_mut(|state| {
+            state.set_tooltip(tooltip);
+        });
+        Some(())
+    }
+
+    fn draw_impl(self: &Rc<Self>, g: &mut Renderer) {
+        let icon_size = self.icon_size();
+        let state = self.state.borrow();
+        g.set_color(&COLOR_BG0);
+        g.draw_rounded_rect(0, (state.size.x, icon_size * 2.0), CORNER_SIZE);
+        let selected = match state.table.borrow().get_source() {
+            WavetableSource::Generated { from, to } => [Some(from), Some(to)],
+            WavetableSource::Imported => [None, None],
+        };
+        g.set_color(&COLOR_FG1);
+        for (row, selected) in selected.iter().enumerate() {
+            let y = row as f32 * icon_size;
+            for (option, icon) in state.icons.iter().enumerate() {
+                let x = option as f32 * icon_size;
+                if *selected == Some(option) {
+                    g.draw_rounded_rect((x, y), icon_size, CORNER_SIZE);
+                    g.draw_icon(*icon, (x, y), icon_size);
+                } else {
+                    g.draw_white_icon(*icon, (x, y), icon_size);
+                }
+            }
+        }
+    }
+}
+
+impl ModuleWidgetImpl for WavetableShapes {}
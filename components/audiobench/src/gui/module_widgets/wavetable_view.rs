@@ -0,0 +1,257 @@
+use crate::{
+    engine::{
+        audio_sample::{AudioSample, SUPPORTED_EXTENSIONS},
+        controls::{
+            FloatInRangeControl, WavetableControl, WavetableSource, WAVETABLE_FRAME_LENGTH,
+        },
+        UiThreadEngine,
+    },
+    gui::{
+        constants::*, module_widgets::ModuleWidgetImpl, mouse_behaviors::ManipulateFIRControl,
+        InteractionHint, Tooltip,
+    },
+    scui_config::{DropTarget, GuiState, MaybeMouseBehavior, Renderer},
+};
+use scui::{GuiInterface, GuiInterfaceProvider, MouseBehavior, MouseMods, Vec2D, WidgetImpl};
+use shared_util::prelude::*;
+
+yaml_widget_boilerplate::make_widget_outline! {
+    widget_struct: WavetableView,
+    constructor: new(
+        parent: ParentRef,
+        pos: GridPos,
+        size: GridSize,
+        table: WavetableControlRef,
+        // The control which picks which frame of the table to play.
+        control: FloatInRangeControlRef,
+        tooltip: String,
+    ),
+    feedback: ControlSignal,
+}
+
+scui::widget! {
+    pub WavetableView
+    State {
+        pos: Vec2D,
+        size: Vec2D,
+        table: Rcrc<WavetableControl>,
+        control: Rcrc<FloatInRangeControl>,
+        tooltip: String,
+        /// The most recent value of the position control while a note was playing.
+        value: f32,
+    }
+}
+
+/// Tables with more frames than this only have some of their frames drawn.
+const MAX_DRAWN_FRAMES: usize = 16;
+/// How many points each frame is drawn with.
+const POINTS_PER_FRAME: usize = 48;
+/// How much of the widget is used to offset frames further back in the table.
+const DEPTH_AMOUNT: f32 = 0.35;
+
+impl WavetableView {
+    fn new(
+        parent: &impl WavetableViewParent,
+        pos: Vec2D,
+        size: Vec2D,
+        table: Rcrc<WavetableControl>,
+        control: Rcrc<FloatInRangeControl>,
+        tooltip: String,
+    ) -> Rc<Self> {
+        let state = WavetableViewState {
+            pos,
+            size,
+            table,
+            control,
+            tooltip,
+            value: 0.0,
+        };
+        Rc::new(Self::create(parent, state))
+    }
+}
+
+/// Dragging moves the position like a knob would, double clicking loads a new table.
+struct ManipulateWavetable {
+    position: ManipulateFIRControl,
+    engine: Rcrc<UiThreadEngine>,
+    gui_interface: Rc<GuiInterface<GuiState, DropTarget>>,
+    table: Rcrc<WavetableControl>,
+}
+
+impl MouseBehavior<DropTarget> for ManipulateWavetable {
+    fn on_drag(&mut self, delta: Vec2D, mods: &MouseMods) {
+        self.position.on_drag(delta, mods);
+    }
+
+    fn on_drop(self: Box<Self>, drop_target: Option<DropTarget>) {
+        Box::new(self.position).on_drop(drop_target);
+    }
+
+    fn on_double_click(self: Box<Self>) {
+        let patterns: Vec<String> = SUPPORTED_EXTENSIONS
+            .iter()
+            .map(|extension| format!("*.{}", extension))
+            .collect();
+        let patterns: Vec<&str> = patterns.iter().map(|p| &p[..]).collect();
+        let path = tinyfiledialogs::open_file_dialog(
+            "Load Wavetable",
+            "",
+            Some((&patterns[..], "Audio Files")),
+        );
+        let path = if let Some(path) = path {
+            path
+        } else {
+            return;
+        };
+        let result = std::fs::read(&path)
+            .map_err(|err| format!("ERROR: Failed to read {}, caused by:\nERROR: {}", path, err))
+            .and_then(|buffer| AudioSample::decode(&path, &buffer[..]))
+            .and_then(|sample| self.table.borrow_mut().import(&sample));
+        match result {
+            Ok(..) => {
+                self.engine.borrow_mut().reload_dyn_data();
+                self.engine.borrow().activate_dummy_note_once();
+            }
+            Err(err) => self.gui_interface.state.borrow_mut().add_error_message(err),
+        }
+    }
+}
+
+/// Draws a single cycle of a waveform so that it fills the specified area.
+fn draw_frame(g: &mut Renderer, pos: Vec2D, size: Vec2D, frame: impl Fn(usize) -> f32) {
+    let len = WAVETABLE_FRAME_LENGTH;
+    let point = |index: usize| {
+        let value = frame(index * len / POINTS_PER_FRAME % len);
+        let x = index as f32 / POINTS_PER_FRAME as f32 * size.x;
+        let y = value
+            .clam(-1.0, 1.0)
+            .from_range_to_range(-1.0, 1.0, size.y, 0.0);
+        pos + Vec2D::new(x, y)
+    };
+    let mut old_point = point(0);
+    for index in 1..=POINTS_PER_FRAME {
+        let new_point = point(index);
+        g.draw_line(old_point, new_point, 1.0);
+        old_point = new_point;
+    }
+}
+
+impl WidgetImpl<Renderer, DropTarget> for WavetableView {
+    fn get_pos_impl(self: &Rc<Self>) -> Vec2D {
+        self.state.borrow().pos
+    }
+
+    fn get_size_impl(self: &Rc<Self>) -> Vec2D {
+        self.state.borrow().size
+    }
+
+    fn get_mouse_behavior_impl(
+        self: &Rc<Self>,
+        _pos: Vec2D,
+        _mods: &MouseMods,
+    ) -> MaybeMouseBehavior {
+        let state = self.state.borrow();
+        let int = self.provide_gui_interface();
+        let engine = Rc::clone(&int.state.borrow().engine);
+        Some(Box::new(ManipulateWavetable {
+            position: ManipulateFIRControl::new(self, Rc::clone(&state.control)),
+            engine,
+            gui_interface: int,
+            table: Rc::clone(&state.table),
+        }))
+    }
+
+    fn get_drop_target_impl(self: &Rc<Self>, _pos: Vec2D) -> Option<DropTarget> {
+        Some(DropTarget::Control(
+            Rc::clone(&self.state.borrow().control) as _
+        ))
+    }
+
+    fn on_hover_impl(self: &Rc<Self>, _pos: Vec2D) -> Option<()> {
+        let state = self.state.borrow();
+        let table = state.table.borrow();
+        let source = match table.get_source() {
+            WavetableSource::Generated { .. } => "generated",
+            WavetableSource::Imported => "imported",
+        };
+        let tooltip = Tooltip {
+            text: format!(
+                "{} ({} frames, {})",
+                state.tooltip,
+                table.get_num_frames(),
+                source
+            ),
+            interaction: vec![
+                InteractionHint::LeftClickAndDrag,
+                InteractionHint::PrecisionModifier,
+                InteractionHint::DoubleClick,
+            ],
+        };
+        drop(table);
+        drop(state);
+        self.with_gui_state_mut(|state| {
+            state.set_tooltip(tooltip);
+        });
+        Some(())
+    }
+
+    fn draw_impl(self: &Rc<Self>, g: &mut Renderer) {
+        let state = self.state.borrow();
+        let size = state.size;
+        g.set_color(&COLOR_BG0);
+        g.draw_rounded_rect(0, size, CORNER_SIZE);
+
+        let control = state.control.borrow();
+        // If automated, show the most recent value recorded from when a note was playing.
+        let value = if control.automation.is_empty() {
+            control.value
+        } else {
+            state.value
+        };
+        let position = value
+            .from_range_to_range(control.range.0, control.range.1, 0.0, 1.0)
+            .clam(0.0, 1.0);
+        drop(control);
+
+        let table = state.table.borrow();
+        let num_frames = table.get_num_frames();
+        let depth = size * DEPTH_AMOUNT;
+        let frame_size = size - depth;
+        // The first frame is at the front in the bottom left, later frames are further back.
+        let frame_pos = |fraction: f32| Vec2D::new(depth.x * fraction, depth.y * (1.0 - fraction));
+        let num_drawn = num_frames.min(MAX_DRAWN_FRAMES);
+        g.set_color(&COLOR_FG1);
+        g.set_alpha(0.3);
+        for drawn_index in (0..num_drawn).rev() {
+            let fraction = if num_drawn > 1 {
+                drawn_index as f32 / (num_drawn - 1) as f32
+            } else {
+                0.0
+            };
+            let frame_index = (fraction * (num_frames - 1) as f32).round() as usize;
+            let frame = table.borrow_frame(frame_index);
+            draw_frame(g, frame_pos(fraction), frame_size, |index| frame[index]);
+        }
+        g.set_alpha(1.0);
+
+        // The frame that is currently playing, crossfaded the same way the Julia code does it.
+        let frame_position = position * (num_frames - 1) as f32;
+        let first = (frame_position.floor() as usize).min(num_frames - 1);
+        let second = (first + 1).min(num_frames - 1);
+        let amount = frame_position - first as f32;
+        let (first, second) = (table.borrow_frame(first), table.borrow_frame(second));
+        g.set_color(&COLOR_EDITABLE);
+        draw_frame(g, frame_pos(position), frame_size, |index| {
+            first[index] * (1.0 - amount) + second[index] * amount
+        });
+    }
+}
+
+// The position control is not represented here, otherwise wires from its automation would be
+// drawn to both this and the knob controlling it.
+impl ModuleWidgetImpl for WavetableView {
+    fn take_feedback_data(self: &Rc<Self>, data: Vec<f32>) {
+        assert_eq!(data.len(), 1);
+        self.state.borrow_mut().value = data[0];
+    }
+}
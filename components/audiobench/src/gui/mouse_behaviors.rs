@@ -326,16 +326,29 @@ impl MouseBehavior<DropTarget> for MutateControl {
 pub struct ContinuouslyMutateControl {
     engine: Rcrc<UiThreadEngine>,
     gui_interface: Rc<GuiInterface<GuiState, DropTarget>>,
-    mutator: Box<dyn FnMut(f32, Option<f32>) -> (UpdateRequest, Option<Tooltip>)>,
+    mutator: Box<dyn FnMut(Vec2D, Option<f32>) -> (UpdateRequest, Option<Tooltip>)>,
     #[value(false)]
     code_reload_requested: bool,
 }
 
 impl ContinuouslyMutateControl {
-    pub fn wrap<W, M>(widget: &W, mutator: M) -> MaybeMouseBehavior
+    /// The mutator receives how far the mouse moved right and up since the last time it was called.
+    pub fn wrap<W, M>(widget: &W, mut mutator: M) -> MaybeMouseBehavior
     where
         W: GuiInterfaceProvider<GuiState, DropTarget>,
         M: FnMut(f32, Option<f32>) -> (UpdateRequest, Option<Tooltip>) + 'static,
+    {
+        Self::wrap_2d(widget, move |delta, steps| {
+            mutator(delta.x - delta.y, steps)
+        })
+    }
+
+    /// Like wrap, but the mutator receives how far the mouse moved along both axes, where positive
+    /// y is down.
+    pub fn wrap_2d<W, M>(widget: &W, mutator: M) -> MaybeMouseBehavior
+    where
+        W: GuiInterfaceProvider<GuiState, DropTarget>,
+        M: FnMut(Vec2D, Option<f32>) -> (UpdateRequest, Option<Tooltip>) + 'static,
     {
         let int = widget.provide_gui_interface();
         let engine = Rc::clone(&int.state.borrow().engine);
@@ -345,7 +358,7 @@ impl ContinuouslyMutateControl {
 
 impl MouseBehavior<DropTarget> for ContinuouslyMutateControl {
     fn on_drag(&mut self, delta: Vec2D, mods: &MouseMods) {
-        let mut delta = delta.x - delta.y;
+        let mut delta = delta;
         if mods.precise {
             delta *= PRECISION_MULTIPLIER;
         }
@@ -0,0 +1,192 @@
+use crate::{gui::constants::*, scui_config::Renderer};
+use scui::Vec2D;
+use shared_util::prelude::*;
+use std::{f32::consts::PI, time::Instant};
+
+/// How many bands a spectrum is split into, spaced evenly on a logarithmic frequency axis.
+pub const SPECTRUM_BANDS: usize = 64;
+pub const SPECTRUM_MIN_FREQUENCY: f32 = 20.0;
+pub const SPECTRUM_MAX_FREQUENCY: f32 = 20_000.0;
+/// Levels are clamped to this value, a full scale sine wave is 0dB.
+pub const SPECTRUM_MIN_DB: f32 = -90.0;
+/// How long a peak stays in place before it starts falling.
+const PEAK_HOLD_TIME: f32 = 1.0;
+/// How quickly peaks fall after they have been held, in decibels per second.
+const PEAK_FALL_RATE: f32 = 30.0;
+/// Short inputs are padded with silence so that low frequencies still get a few FFT bins.
+const MIN_FFT_SIZE: usize = 1024;
+
+/// In-place radix-2 FFT. The length of both slices must be the same power of two.
+fn fft(real: &mut [f32], imag: &mut [f32]) {
+    let n = real.len();
+    assert!(n.is_power_of_two());
+    assert_eq!(imag.len(), n);
+    // Put everything in bit-reversed order.
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            real.swap(i, j);
+            imag.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (w_imag, w_real) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_real = real[b] * w_real - imag[b] * w_imag;
+                let t_imag = real[b] * w_imag + imag[b] * w_real;
+                real[b] = real[a] - t_real;
+                imag[b] = imag[a] - t_imag;
+                real[a] += t_real;
+                imag[a] += t_imag;
+            }
+        }
+        len *= 2;
+    }
+}
+
+/// Returns the frequency at the specified position on the logarithmic axis, from 0 to 1.
+pub fn spectrum_frequency_at(position: f32) -> f32 {
+    SPECTRUM_MIN_FREQUENCY * (SPECTRUM_MAX_FREQUENCY / SPECTRUM_MIN_FREQUENCY).powf(position)
+}
+
+/// Turns buffers of audio into levels of frequency bands, keeping track of the highest recent
+/// level of each band.
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    /// In decibels.
+    levels: Vec<f32>,
+    /// In decibels.
+    peaks: Vec<f32>,
+    /// How many seconds ago each peak was set.
+    peak_ages: Vec<f32>,
+    last_update: Option<Instant>,
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Self {
+            levels: vec![SPECTRUM_MIN_DB; SPECTRUM_BANDS],
+            peaks: vec![SPECTRUM_MIN_DB; SPECTRUM_BANDS],
+            peak_ages: vec![0.0; SPECTRUM_BANDS],
+            last_update: None,
+        }
+    }
+}
+
+impl Spectrum {
+    pub fn get_levels(&self) -> &[f32] {
+        &self.levels[..]
+    }
+
+    pub fn get_peaks(&self) -> &[f32] {
+        &self.peaks[..]
+    }
+
+    /// Analyzes a new buffer of mono audio which was produced at the specified sample rate.
+    pub fn update(&mut self, samples: &[f32], sample_rate: f32) {
+        let elapsed = if let Some(last_update) = self.last_update {
+            last_update.elapsed().as_secs_f32()
+        } else {
+            0.0
+        };
+        self.last_update = Some(Instant::now());
+        if samples.is_empty() {
+            return;
+        }
+
+        let size = samples.len().next_power_of_two().max(MIN_FFT_SIZE);
+        let mut real = vec![0.0; size];
+        let mut imag = vec![0.0; size];
+        // A Hann window keeps loud bands from smearing across the whole spectrum.
+        let mut window_sum = 0.0;
+        for (index, sample) in samples.iter().enumerate() {
+            let window = 0.5 - 0.5 * (2.0 * PI * index as f32 / samples.len() as f32).cos();
+            real[index] = sample * window;
+            window_sum += window;
+        }
+        fft(&mut real[..], &mut imag[..]);
+        // Scaled so that a full scale sine wave has an amplitude of 1.
+        let scale = 2.0 / window_sum.max(1e-6);
+        let num_bins = size / 2;
+        let magnitudes: Vec<f32> = (0..=num_bins)
+            .map(|bin| (real[bin] * real[bin] + imag[bin] * imag[bin]).sqrt() * scale)
+            .collect();
+        let bin_width = sample_rate / size as f32;
+        let magnitude_at = |frequency: f32| {
+            let position = (frequency / bin_width).min(num_bins as f32);
+            let low = position.floor() as usize;
+            let high = (low + 1).min(num_bins);
+            let amount = position - low as f32;
+            magnitudes[low] * (1.0 - amount) + magnitudes[high] * amount
+        };
+
+        for band in 0..SPECTRUM_BANDS {
+            let low = spectrum_frequency_at(band as f32 / SPECTRUM_BANDS as f32);
+            let high = spectrum_frequency_at((band + 1) as f32 / SPECTRUM_BANDS as f32);
+            let first_bin = (low / bin_width).ceil() as usize;
+            let last_bin = ((high / bin_width).floor() as usize).min(num_bins);
+            // Low bands can be narrower than a single bin, so they use the interpolated
+            // magnitude at their center instead.
+            let magnitude = if first_bin <= last_bin {
+                magnitudes[first_bin..=last_bin]
+                    .iter()
+                    .fold(0.0f32, |max, value| max.max(*value))
+            } else {
+                magnitude_at((low * high).sqrt())
+            };
+            let level = (20.0 * magnitude.max(1e-9).log10()).max(SPECTRUM_MIN_DB);
+            self.levels[band] = level;
+
+            self.peak_ages[band] += elapsed;
+            let fall = (self.peak_ages[band] - PEAK_HOLD_TIME).clam(0.0, elapsed);
+            self.peaks[band] = (self.peaks[band] - fall * PEAK_FALL_RATE).max(SPECTRUM_MIN_DB);
+            if level >= self.peaks[band] {
+                self.peaks[band] = level;
+                self.peak_ages[band] = 0.0;
+            }
+        }
+    }
+}
+
+impl Renderer {
+    /// Draws the levels of the spectrum as bars, with a line above each bar showing its peak.
+    pub fn draw_spectrum(&mut self, spectrum: &Spectrum, size: Vec2D) {
+        let band_width = size.x / SPECTRUM_BANDS as f32;
+        let height_of = |level: f32| level.from_range_to_range(SPECTRUM_MIN_DB, 0.0, 0.0, size.y);
+        self.set_color(&COLOR_FG1);
+        self.set_alpha(0.3);
+        // Mark 100Hz, 1kHz and 10kHz.
+        for frequency in &[100.0f32, 1_000.0, 10_000.0] {
+            let position = (frequency / SPECTRUM_MIN_FREQUENCY).ln()
+                / (SPECTRUM_MAX_FREQUENCY / SPECTRUM_MIN_FREQUENCY).ln();
+            let x = position * size.x;
+            self.draw_line((x, 0.0), (x, size.y), 1.0);
+        }
+        self.set_alpha(1.0);
+        self.set_color(&COLOR_EDITABLE);
+        for (band, level) in spectrum.get_levels().iter().enumerate() {
+            let height = height_of(*level).clam(0.0, size.y);
+            let x = band as f32 * band_width;
+            self.draw_rect((x, size.y - height), (band_width, height));
+        }
+        self.set_color(&COLOR_FG1);
+        for (band, peak) in spectrum.get_peaks().iter().enumerate() {
+            if *peak <= SPECTRUM_MIN_DB {
+                continue;
+            }
+            let y = size.y - height_of(*peak).clam(0.0, size.y);
+            let x = band as f32 * band_width;
+            self.draw_line((x, y), (x + band_width, y), 1.0);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{
+    gui::{
+        constants::*,
+        master_analyzer::{ChannelLevel, MASTER_HISTORY_LENGTH},
+        spectrum::{SPECTRUM_MAX_FREQUENCY, SPECTRUM_MIN_FREQUENCY},
+        GuiTab, TabArchetype,
+    },
+    scui_config::{DropTarget, Renderer},
+};
+use scui::{Vec2D, WidgetImpl};
+use shared_util::prelude::*;
+
+scui::widget! {
+    pub Analyzer
+    State {}
+}
+
+impl Analyzer {
+    pub fn new(parent: &impl AnalyzerParent) -> Rc<Self> {
+        let state = AnalyzerState {};
+        Rc::new(Self::create(parent, state))
+    }
+}
+
+const LABEL_HEIGHT: f32 = FONT_SIZE + GRID_P;
+const SCOPE_POS: Vec2D = Vec2D::new(GRID_P, GRID_P + LABEL_HEIGHT);
+const SCOPE_SIZE: Vec2D = Vec2D::new(
+    TAB_BODY_WIDTH - GRID_P * 2.0,
+    TAB_BODY_HEIGHT / 2.0 - GRID_P * 2.0 - LABEL_HEIGHT,
+);
+const LOWER_START: f32 = TAB_BODY_HEIGHT / 2.0;
+const METER_WIDTH: f32 = grid(4);
+const METER_POS: Vec2D = Vec2D::new(GRID_P, LOWER_START + LABEL_HEIGHT);
+const METER_SIZE: Vec2D = Vec2D::new(
+    METER_WIDTH,
+    TAB_BODY_HEIGHT / 2.0 - GRID_P - LABEL_HEIGHT * 3.0,
+);
+const SPECTRUM_POS: Vec2D = Vec2D::new(METER_WIDTH + GRID_P * 2.0, LOWER_START + LABEL_HEIGHT);
+const SPECTRUM_SIZE: Vec2D = Vec2D::new(
+    TAB_BODY_WIDTH - METER_WIDTH - GRID_P * 3.0,
+    TAB_BODY_HEIGHT / 2.0 - GRID_P - LABEL_HEIGHT * 2.0,
+);
+
+impl Analyzer {
+    fn draw_panel(&self, r: &mut Renderer, pos: Vec2D, size: Vec2D, label: &str) {
+        r.set_color(&COLOR_FG1);
+        r.draw_text(
+            FONT_SIZE,
+            pos - (0.0, LABEL_HEIGHT),
+            (size.x, LABEL_HEIGHT),
+            (-1, -1),
+            1,
+            label,
+        );
+        r.set_color(&COLOR_BG0);
+        r.draw_rounded_rect(pos - GRID_P / 2.0, size + GRID_P, CORNER_SIZE);
+    }
+
+    fn draw_level_readout(&self, r: &mut Renderer, x: f32, width: f32, level: &ChannelLevel) {
+        let y = METER_POS.y + METER_SIZE.y + GRID_P;
+        r.set_color(if level.held_peak >= 0.0 {
+            &COLOR_ERROR
+        } else {
+            &COLOR_FG1
+        });
+        let text = format!("{:.1}", level.held_peak);
+        r.draw_text(FONT_SIZE, (x, y), (width, LABEL_HEIGHT), (0, -1), 1, &text);
+        r.set_color(&COLOR_FG1);
+        let text = format!("{:.1}", level.rms);
+        let y = y + LABEL_HEIGHT;
+        r.draw_text(FONT_SIZE, (x, y), (width, LABEL_HEIGHT), (0, -1), 1, &text);
+    }
+}
+
+impl WidgetImpl<Renderer, DropTarget> for Analyzer {
+    fn get_pos_impl(self: &Rc<Self>) -> Vec2D {
+        (0.0, HEADER_HEIGHT).into()
+    }
+
+    fn get_size_impl(self: &Rc<Self>) -> Vec2D {
+        TAB_BODY_SIZE
+    }
+
+    fn draw_impl(self: &Rc<Self>, r: &mut Renderer) {
+        let gui_state = self.parents.gui.state.borrow();
+        let analyzer = &gui_state.master_analyzer;
+
+        self.draw_panel(r, SCOPE_POS, SCOPE_SIZE, "Waveform (Left / Right)");
+        r.push_state();
+        r.translate(SCOPE_POS);
+        r.draw_master_scope(analyzer, SCOPE_SIZE, MASTER_HISTORY_LENGTH);
+        r.pop_state();
+
+        self.draw_panel(r, METER_POS, METER_SIZE, "Level (dB)");
+        r.push_state();
+        r.translate(METER_POS);
+        r.draw_master_meter(analyzer, METER_SIZE);
+        r.pop_state();
+        let channel_width = METER_WIDTH / 2.0;
+        for (channel, level) in analyzer.get_levels().iter().enumerate() {
+            let x = METER_POS.x + channel as f32 * channel_width;
+            self.draw_level_readout(r, x, channel_width, level);
+        }
+
+        self.draw_panel(r, SPECTRUM_POS, SPECTRUM_SIZE, "Spectrum");
+        r.push_state();
+        r.translate(SPECTRUM_POS);
+        r.draw_spectrum(analyzer.borrow_spectrum(), SPECTRUM_SIZE);
+        r.set_color(&COLOR_FG1);
+        let label_y = SPECTRUM_SIZE.y + GRID_P;
+        let range = (SPECTRUM_MAX_FREQUENCY / SPECTRUM_MIN_FREQUENCY).ln();
+        for (frequency, label) in &[(100.0f32, "100Hz"), (1_000.0, "1kHz"), (10_000.0, "10kHz")] {
+            let x = (frequency / SPECTRUM_MIN_FREQUENCY).ln() / range * SPECTRUM_SIZE.x;
+            let pos = (x - grid(3) / 2.0, label_y);
+            r.draw_text(FONT_SIZE, pos, (grid(3), LABEL_HEIGHT), (0, -1), 1, label);
+        }
+        r.pop_state();
+    }
+}
+
+impl GuiTab for Rc<Analyzer> {
+    fn get_name(self: &Self) -> String {
+        "Analyzer".to_owned()
+    }
+
+    fn get_archetype(&self) -> TabArchetype {
+        TabArchetype::Analyzer
+    }
+}
@@ -35,30 +35,31 @@ scui::widget! {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum GraphHighlightMode {
     None,
     ReceivesType(ep::JackType),
-    ProducesType(ep::JackType),
+    /// Outputs of any of these types are highlighted.
+    ProducesTypes(Vec<ep::JackType>),
 }
 
 impl GraphHighlightMode {
-    pub fn is_some(self) -> bool {
+    pub fn is_some(&self) -> bool {
         match self {
             Self::None => false,
             _ => true,
         }
     }
 
-    pub fn should_highlight(self, control: &Rcrc<impl Control + ?Sized>) -> bool {
+    pub fn should_highlight(&self, control: &Rcrc<impl Control + ?Sized>) -> bool {
         match self {
             Self::None => false,
             Self::ReceivesType(typ) => control
                 .borrow()
                 .acceptable_automation()
                 .into_iter()
-                .any(|i| i == typ),
-            Self::ProducesType(_) => false,
+                .any(|i| i == *typ),
+            Self::ProducesTypes(_) => false,
         }
     }
 
@@ -199,7 +200,7 @@ impl ModuleGraph {
     }
 
     pub fn get_highlight_mode(self: &Rc<Self>) -> GraphHighlightMode {
-        self.state.borrow().highlight_mode
+        self.state.borrow().highlight_mode.clone()
     }
 
     pub fn get_zoom(self: &Rc<Self>) -> f32 {
@@ -208,7 +209,7 @@ impl ModuleGraph {
 
     pub fn set_hovered_module(self: &Rc<Self>, module: Rc<Module>) {
         self.with_gui_state(|state| {
-            let engine = state.engine.borrow();
+            let mut engine = state.engine.borrow_mut();
             engine.set_module_view(&module.get_real_module());
         });
         self.state.borrow_mut().hovered_module = Some(module);
@@ -267,8 +268,7 @@ impl ModuleGraph {
         state.wire_preview_endpoint = Some(visual_pos);
         let acceptable = control.borrow().acceptable_automation();
         assert!(acceptable.len() > 0);
-        debug_assert_eq!(acceptable.len(), 1);
-        state.highlight_mode = GraphHighlightMode::ProducesType(acceptable[0]);
+        state.highlight_mode = GraphHighlightMode::ProducesTypes(acceptable);
         let graph = Rc::clone(self);
         Box::new(ConnectToControl { graph, control })
     }
@@ -383,6 +383,9 @@ impl WidgetImpl<Renderer, DropTarget> for ModuleGraph {
             }
         }
         for module in children.modules.iter().rev() {
+            if module.pin_in_bounds(pos) {
+                return module.pin_mouse_behavior();
+            }
             ris!(module.get_mouse_behavior(pos, mods))
         }
         Some(Box::new(GraphInteract::new(Rc::clone(self))))
@@ -399,6 +402,9 @@ impl WidgetImpl<Renderer, DropTarget> for ModuleGraph {
             }
         }
         for module in children.modules.iter().rev() {
+            if module.pin_in_bounds(pos) {
+                return module.on_hover_pin();
+            }
             ris!(module.on_hover(pos))
         }
         self.with_gui_state_mut(|state| {
@@ -3,7 +3,9 @@ use crate::{
     gui::{
         constants::*,
         module_widgets::ModuleWidget,
-        top_level::graph::{GraphHighlightMode, ModuleGraph, OutputViewRenderer, WireTracker},
+        top_level::graph::{
+            GraphHighlightMode, ModuleGraph, OutputView, OutputViewRenderer, WireTracker,
+        },
         {InteractionHint, Tooltip},
     },
     scui_config::{DropTarget, MaybeMouseBehavior, Renderer},
@@ -11,6 +13,12 @@ use crate::{
 use scui::{MouseBehavior, MouseMods, OnClickBehavior, Vec2D, Widget, WidgetImpl};
 use shared_util::prelude::*;
 
+/// Modules using at least this fraction of real time have their CPU usage highlighted.
+const HEAVY_MODULE_CPU_USAGE: f32 = 0.1;
+/// Size of the toggle at the right end of the label above the module which keeps its output views
+/// visible.
+const PIN_SIZE: f32 = JACK_SMALL_ICON_SIZE;
+
 struct OutputJack {
     label: String,
     tooltip: Tooltip,
@@ -35,14 +43,19 @@ impl OutputJack {
         } else {
             None
         };
+        let mut interaction = vec![
+            InteractionHint::LeftClickAndDrag,
+            InteractionHint::ProducesOutput(typ),
+        ];
+        // Right clicking switches between viewing the audio as a waveform and as a spectrum.
+        if typ == ep::JackType::Audio {
+            interaction.push(InteractionHint::RightClick);
+        }
         Self {
             label,
             tooltip: Tooltip {
                 text: tooltip,
-                interaction: vec![
-                    InteractionHint::LeftClickAndDrag,
-                    InteractionHint::ProducesOutput(typ),
-                ],
+                interaction,
             },
             icon,
             small_icon,
@@ -99,7 +112,10 @@ scui::widget! {
         size: Vec2D,
         label: String,
         outputs: Vec<OutputJack>,
-        output_view_data: Vec<Vec<f32>>,
+        output_views: Vec<OutputView>,
+        /// Fraction of real time the module uses, if profiling is enabled.
+        cpu_usage: Option<f32>,
+        pin_icon: usize,
         widgets: Vec<Box<dyn ModuleWidget>>
     }
     Parents {
@@ -136,7 +152,9 @@ impl Module {
             fatgrid(grid_size.1),
         );
         let mut outputs = Vec::new();
+        let mut output_views = Vec::new();
         for (index, output) in template_ref.outputs.iter().enumerate() {
+            output_views.push(OutputView::new(output.get_type()));
             outputs.push(OutputJack::create(
                 output.borrow_label().to_owned(),
                 output.borrow_tooltip().to_owned(),
@@ -147,12 +165,22 @@ impl Module {
             ));
         }
 
+        let gui_state = parent.provide_gui_interface().state.borrow();
+        let pin_icon = gui_state
+            .registry
+            .borrow()
+            .lookup_icon("Factory:pin")
+            .unwrap();
+        drop(gui_state);
+
         let state = ModuleState {
             module: Rc::clone(&module),
             size,
             label,
             outputs,
-            output_view_data: Vec::new(),
+            output_views,
+            cpu_usage: None,
+            pin_icon,
             widgets: Vec::new(),
         };
 
@@ -174,19 +202,68 @@ impl Module {
         self.state.borrow().widgets[widget_index].take_feedback_data(data);
     }
 
-    pub fn take_output_view_data(self: &Rc<Self>, data: Vec<Vec<f32>>) {
-        assert_eq!(
-            data.len(),
-            self.state
-                .borrow()
-                .module
-                .borrow()
-                .template
-                .borrow()
-                .outputs
-                .len()
-        );
-        self.state.borrow_mut().output_view_data = data;
+    pub fn take_voice_feedback_data(self: &Rc<Self>, data: Vec<Vec<f32>>, widget_index: usize) {
+        self.state.borrow().widgets[widget_index].take_voice_feedback_data(data);
+    }
+
+    /// `sample_rate` is the rate the note graph was running at when it produced the data.
+    pub fn take_output_view_data(self: &Rc<Self>, data: Vec<Vec<f32>>, sample_rate: f32) {
+        let mut state = self.state.borrow_mut();
+        // Data generated before the graph changed can belong to a module which was at this
+        // module's index at the time.
+        if data.len() != state.output_views.len() {
+            return;
+        }
+        for (view, data) in state.output_views.iter_mut().zip(data.into_iter()) {
+            view.take_data(data, sample_rate);
+        }
+    }
+
+    pub fn set_cpu_usage(self: &Rc<Self>, usage: Option<f32>) {
+        self.state.borrow_mut().cpu_usage = usage;
+    }
+
+    fn is_pinned(self: &Rc<Self>) -> bool {
+        self.state.borrow().module.borrow().pinned
+    }
+
+    fn pin_pos(size: Vec2D) -> Vec2D {
+        (size.x - PIN_SIZE, -PIN_SIZE - 4.0).into()
+    }
+
+    /// The pin toggle is drawn outside the bounds of the module, so the graph has to check for it
+    /// before passing events to the module. `pos` is relative to the graph.
+    pub fn pin_in_bounds(self: &Rc<Self>, pos: Vec2D) -> bool {
+        let pos = pos - self.get_pos() - Self::pin_pos(self.get_size());
+        pos.inside(PIN_SIZE.into())
+    }
+
+    pub fn pin_mouse_behavior(self: &Rc<Self>) -> MaybeMouseBehavior {
+        let this = Rc::clone(self);
+        OnClickBehavior::wrap(move || {
+            let module = this.get_real_module();
+            let pinned = !module.borrow().pinned;
+            this.with_gui_state(|state| {
+                let mut engine = state.engine.borrow_mut();
+                engine.set_module_pinned(&module, pinned);
+            });
+        })
+    }
+
+    pub fn on_hover_pin(self: &Rc<Self>) -> Option<()> {
+        self.parents.graph.set_hovered_module(Rc::clone(self));
+        let text = if self.is_pinned() {
+            "Click to stop showing what this module outputs while it is not hovered"
+        } else {
+            "Click to keep showing what this module outputs while it is not hovered"
+        };
+        self.with_gui_state_mut(|state| {
+            state.set_tooltip(Tooltip {
+                text: text.to_owned(),
+                interaction: vec![InteractionHint::LeftClick],
+            })
+        });
+        Some(())
     }
 
     fn draw_wires(self: &Rc<Self>, g: &mut Renderer, pos: Vec2D) {
@@ -282,6 +359,12 @@ impl WidgetImpl<Renderer, DropTarget> for Module {
         }
         for (index, output) in state.outputs.iter().enumerate() {
             if output.mouse_in_bounds(mouse_pos) {
+                if mods.right_click && state.output_views[index].supports_spectrum() {
+                    let this = Rc::clone(self);
+                    return OnClickBehavior::wrap(move || {
+                        this.state.borrow_mut().output_views[index].toggle_mode()
+                    });
+                }
                 let g = &self.parents.graph;
                 return Some(g.connect_from_source_behavior(Rc::clone(&state.module), index));
             }
@@ -355,6 +438,7 @@ impl WidgetImpl<Renderer, DropTarget> for Module {
         let state = self.state.borrow();
         let layer_index = self.parents.graph.get_current_draw_layer();
         let highlight = self.parents.graph.get_highlight_mode();
+        let pinned = state.module.borrow().pinned;
 
         if layer_index == 0 {
             g.draw_inset_box_shadow(
@@ -386,15 +470,36 @@ impl WidgetImpl<Renderer, DropTarget> for Module {
                 1,
                 &state.label,
             );
+            let hovering = self.is_hovered();
+            if pinned || hovering {
+                if !pinned {
+                    g.set_alpha(0.4);
+                }
+                g.draw_icon(state.pin_icon, Self::pin_pos(size), PIN_SIZE);
+                g.set_alpha(1.0);
+            }
+            if let Some(usage) = state.cpu_usage {
+                if usage >= HEAVY_MODULE_CPU_USAGE {
+                    g.set_color(&COLOR_WARNING);
+                }
+                g.draw_text(
+                    FONT_SIZE,
+                    (JS, -20.0),
+                    (size.x - JS - PIN_SIZE - GRID_P, 20.0),
+                    (1, 1),
+                    1,
+                    &format!("{:.1}% CPU", usage * 100.0),
+                );
+                g.set_color(&COLOR_FG1);
+            }
 
             let module_ref = state.module.borrow();
             let template_ref = module_ref.template.borrow();
-            let hovering = self.is_hovered();
             for output_index in 0..state.outputs.len() {
                 let output = &state.outputs[output_index];
                 let jack = &template_ref.outputs[output_index];
-                let dim = if let GraphHighlightMode::ProducesType(typ) = highlight {
-                    typ != jack.get_type()
+                let dim = if let GraphHighlightMode::ProducesTypes(types) = &highlight {
+                    !types.contains(&jack.get_type())
                 } else {
                     false
                 };
@@ -410,26 +515,12 @@ impl WidgetImpl<Renderer, DropTarget> for Module {
             g.set_alpha(0.2);
             g.translate(pos * -1.0);
             self.draw_wires(g, pos);
-        } else if layer_index == 4 && self.is_hovered() {
-            let module = state.module.borrow();
-            let template = module.template.borrow();
+        } else if layer_index == 4 && (pinned || self.is_hovered()) {
             let x = size.x;
             let mut y = GRID_P + JACK_SIZE / 2.0;
-            let mut index = 0;
-            for output in &template.outputs {
-                let data = if state.output_view_data.len() > index {
-                    &state.output_view_data[index][..]
-                } else {
-                    &[][..]
-                };
-                g.draw_output_view(
-                    data,
-                    output.get_type(),
-                    Vec2D::new(x, y),
-                    Vec2D::new(x + grid(1), y),
-                );
+            for view in &state.output_views {
+                g.draw_output_view(view, Vec2D::new(x, y), Vec2D::new(x + grid(1), y));
                 y += GRID_P + JACK_SIZE;
-                index += 1;
             }
         }
     }
@@ -1,21 +1,74 @@
-use crate::{engine::parts::JackType, gui::constants::*, scui_config::Renderer};
+use crate::{
+    engine::parts::JackType,
+    gui::{constants::*, spectrum::Spectrum},
+    scui_config::Renderer,
+};
 use scui::Vec2D;
+use shared_util::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputViewMode {
+    /// The data is drawn as it is, e.g. audio is drawn as a waveform.
+    Signal,
+    /// The level of each frequency is drawn, only available for audio outputs.
+    Spectrum,
+}
+
+/// The latest data sent back for an output along with how it is displayed. Kept between frames so
+/// that spectra can hold their peaks.
+#[derive(Clone, Debug)]
+pub struct OutputView {
+    typ: JackType,
+    mode: OutputViewMode,
+    data: Vec<f32>,
+    spectrum: Spectrum,
+}
+
+impl OutputView {
+    pub fn new(typ: JackType) -> Self {
+        Self {
+            typ,
+            mode: OutputViewMode::Signal,
+            data: Vec::new(),
+            spectrum: Default::default(),
+        }
+    }
+
+    pub fn supports_spectrum(&self) -> bool {
+        self.typ == JackType::Audio
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            OutputViewMode::Signal if self.supports_spectrum() => {
+                self.spectrum = Default::default();
+                OutputViewMode::Spectrum
+            }
+            _ => OutputViewMode::Signal,
+        };
+    }
+
+    /// `sample_rate` is the rate the data was produced at.
+    pub fn take_data(&mut self, data: Vec<f32>, sample_rate: f32) {
+        if self.mode == OutputViewMode::Spectrum {
+            self.spectrum.update(&data[..], sample_rate);
+        }
+        self.data = data;
+    }
+}
 
 pub trait OutputViewRenderer {
-    fn draw_output_view(
-        &mut self,
-        data: &[f32],
-        view_type: JackType,
-        wire_start: Vec2D,
-        wire_end: Vec2D,
-    );
+    fn draw_output_view(&mut self, view: &OutputView, wire_start: Vec2D, wire_end: Vec2D);
 }
 
 const SIZE: Vec2D = Vec2D::new(grid(5), grid(4));
 const PADDING: f32 = GRID_P;
+/// Audio outputs send back a whole buffer, only the start of it is drawn as a waveform.
+const SIGNAL_VIEW_LENGTH: usize = 64;
 
 impl Renderer {
     fn draw_audio_view(&mut self, data: &[f32]) {
+        let data = &data[..data.len().min(SIGNAL_VIEW_LENGTH)];
         self.set_color(&COLOR_FG1);
         self.translate((0.0, SIZE.y / 2.0));
         if data.len() == 0 {
@@ -37,6 +90,19 @@ impl Renderer {
         }
     }
 
+    /// Shows the value as a bar growing from the center, which is full when the value is -1 or 1.
+    fn draw_control_view(&mut self, data: &[f32]) {
+        let value = data.get(0).cloned().unwrap_or(0.0);
+        let center = SIZE.x / 2.0;
+        let end = center + value.clam(-1.0, 1.0) * center;
+        self.set_color(&COLOR_EDITABLE);
+        self.draw_rect((center.min(end), 0.0), ((end - center).abs(), SIZE.y));
+        self.set_color(&COLOR_FG1);
+        self.draw_line((center, 0.0), (center, SIZE.y), 1.0);
+        let text = format_decimal(value, 3);
+        self.draw_text(FONT_SIZE, 0, SIZE, (0, 0), 1, &text);
+    }
+
     fn draw_pitch_view(&mut self, data: &[f32]) {
         let pitch = data.get(0).cloned().unwrap_or(0.0);
         let text = format!("{:0.2}Hz", pitch);
@@ -44,6 +110,19 @@ impl Renderer {
         self.draw_text(FONT_SIZE, 0, SIZE, (0, 0), 1, &text);
     }
 
+    fn draw_spectrum_view(&mut self, spectrum: &Spectrum) {
+        self.draw_spectrum(spectrum, SIZE);
+        self.set_color(&COLOR_FG1);
+        self.draw_text(
+            FONT_SIZE,
+            GRID_P / 2.0,
+            SIZE - GRID_P,
+            (-1, -1),
+            1,
+            "Spectrum",
+        );
+    }
+
     fn draw_trigger_view(&mut self, data: &[f32]) {
         let triggered = data.get(0).cloned().unwrap_or(0.0) > 0.5;
         if triggered {
@@ -54,13 +133,7 @@ impl Renderer {
 }
 
 impl OutputViewRenderer for Renderer {
-    fn draw_output_view(
-        &mut self,
-        data: &[f32],
-        view_type: JackType,
-        wire_start: Vec2D,
-        wire_end: Vec2D,
-    ) {
+    fn draw_output_view(&mut self, view: &OutputView, wire_start: Vec2D, wire_end: Vec2D) {
         self.push_state();
         self.translate(wire_end - (0.0, SIZE.y / 2.0));
 
@@ -71,8 +144,11 @@ impl OutputViewRenderer for Renderer {
         self.draw_rounded_rect(0, SIZE, CORNER_SIZE);
 
         self.set_color(&COLOR_FG1);
-        match view_type {
+        let data = &view.data[..];
+        match view.typ {
+            _ if view.mode == OutputViewMode::Spectrum => self.draw_spectrum_view(&view.spectrum),
             JackType::Audio | JackType::Waveform => self.draw_audio_view(data),
+            JackType::Control => self.draw_control_view(data),
             JackType::Pitch => self.draw_pitch_view(data),
             JackType::Trigger => self.draw_trigger_view(data),
             // _ => self.draw_text(FONT_SIZE, 0, SIZE, (0, 0), 1, &format!("{:?}", view_type)),
@@ -1,9 +1,9 @@
 use crate::{
-    engine::parts::JackType,
+    engine::{parts::JackType, CompileStage, FeedbackVoice},
     gui::{
         constants::*,
         ui_widgets::{IconButton, LinkButton, TabButton, TextBox},
-        InteractionHint,
+        InteractionHint, TabArchetype, Tooltip,
     },
     scui_config::{DropTarget, MaybeMouseBehavior, Renderer},
 };
@@ -11,13 +11,18 @@ use clipboard::ClipboardProvider;
 use observatory::{derivation_with_ptrs_dyn, DerivationDynPtr};
 use scui::{ChildHolder, MouseMods, OnClickBehavior, Vec2D, Widget, WidgetImpl};
 use shared_util::prelude::*;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 scui::widget! {
     pub Header
     State {
         hint_icons: HashMap<InteractionHint, Vec<usize>>,
         on_patch_change_effect: Option<DerivationDynPtr<()>>,
+        seen_underruns: usize,
+        last_underrun: Option<Instant>,
     }
     Children {
         save_button: ChildHolder<Rc<IconButton>>,
@@ -31,6 +36,35 @@ const TAB_SIZE: Vec2D = Vec2D::new(grid(4), grid(1));
 const TAB_PADDING: f32 = GRID_P * 0.5;
 const TAB_HEIGHT: f32 = grid(1);
 const TOOLTIP_START: f32 = TAB_HEIGHT + GRID_P;
+const AUDIO_STATUS_WIDTH: f32 = grid(5);
+const AUDIO_STATUS_START: f32 = ROOT_WIDTH - AUDIO_STATUS_WIDTH;
+const PROFILE_STATUS_WIDTH: f32 = grid(3);
+const PROFILE_STATUS_START: f32 = AUDIO_STATUS_START - PROFILE_STATUS_WIDTH - TAB_PADDING;
+const SAFETY_STATUS_WIDTH: f32 = grid(3);
+const SAFETY_STATUS_START: f32 = PROFILE_STATUS_START - SAFETY_STATUS_WIDTH - TAB_PADDING;
+const COMPILE_STATUS_WIDTH: f32 = grid(4);
+const COMPILE_STATUS_START: f32 = SAFETY_STATUS_START - COMPILE_STATUS_WIDTH - TAB_PADDING;
+/// The compile status is drawn on top of this while something is compiling.
+const VOICE_STATUS_WIDTH: f32 = COMPILE_STATUS_WIDTH;
+const VOICE_STATUS_START: f32 = COMPILE_STATUS_START;
+const MASTER_VIEW_WIDTH: f32 = grid(8);
+const MASTER_VIEW_START: f32 = ROOT_WIDTH - GRID_P - MASTER_VIEW_WIDTH;
+const MASTER_SCOPE_WIDTH: f32 = grid(3);
+const MASTER_METER_WIDTH: f32 = GRID_P * 2.0;
+/// How many frames of the master output are shown in the oscilloscope in the header.
+const MASTER_SCOPE_LENGTH: usize = 256;
+/// How much gain reduction fills the meter behind the gain reduction readout, in dB.
+const GAIN_REDUCTION_METER_RANGE: f32 = 12.0;
+/// How long the audio status turns orange for after an underrun happens.
+const UNDERRUN_WARNING_DURATION: Duration = Duration::from_secs(2);
+
+fn note_name(key: usize) -> String {
+    const NAMES: [&str; 12] = [
+        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+    ];
+    // MIDI note 60 is C4.
+    format!("{}{}", NAMES[key % 12], key as i32 / 12 - 1)
+}
 
 impl Header {
     pub fn new(parent: &impl HeaderParent) -> Rc<Self> {
@@ -58,6 +92,7 @@ impl Header {
 
         for jt in &[
             JackType::Audio,
+            JackType::Control,
             JackType::Pitch,
             JackType::Trigger,
             JackType::Waveform,
@@ -71,6 +106,8 @@ impl Header {
         let state = HeaderState {
             hint_icons,
             on_patch_change_effect: None,
+            seen_underruns: engine.get_underrun_count(),
+            last_underrun: None,
         };
         let this = Rc::new(Self::create(parent, state));
 
@@ -129,6 +166,258 @@ impl Header {
         this
     }
 
+    fn on_toggle_lookahead(self: &Rc<Self>) -> MaybeMouseBehavior {
+        let this = Rc::clone(self);
+        let engine = self.with_gui_state(|state| Rc::clone(&state.engine));
+        OnClickBehavior::wrap(move || {
+            let engine = engine.borrow();
+            let enabled = !engine.is_lookahead_enabled();
+            engine.set_lookahead_enabled(enabled);
+            let message = if enabled {
+                "Lookahead enabled, audio will be delayed by an extra buffer."
+            } else {
+                "Lookahead disabled."
+            };
+            this.with_gui_state_mut(|state| state.add_success_message(message.to_owned()));
+        })
+    }
+
+    fn on_click_profile_status(self: &Rc<Self>, mods: &MouseMods) -> MaybeMouseBehavior {
+        let this = Rc::clone(self);
+        let engine = self.with_gui_state(|state| Rc::clone(&state.engine));
+        if mods.right_click {
+            return OnClickBehavior::wrap(move || {
+                let report = engine.borrow().make_cpu_usage_report();
+                this.with_gui_state_mut(|state| match report {
+                    Ok(report) => state.add_success_message(report),
+                    Err(err) => state.add_error_message(err),
+                });
+            });
+        }
+        OnClickBehavior::wrap(move || {
+            let mut engine = engine.borrow_mut();
+            let enabled = !engine.is_profiling_enabled();
+            engine.set_profiling_enabled(enabled);
+            let message = if enabled {
+                "Profiling enabled, the CPU usage of each module will be shown in the module graph."
+            } else {
+                "Profiling disabled."
+            };
+            this.with_gui_state_mut(|state| state.add_success_message(message.to_owned()));
+        })
+    }
+
+    fn on_click_safety_status(self: &Rc<Self>) -> MaybeMouseBehavior {
+        let this = Rc::clone(self);
+        let engine = self.with_gui_state(|state| Rc::clone(&state.engine));
+        OnClickBehavior::wrap(move || {
+            let mut engine = engine.borrow_mut();
+            let mut settings = engine.get_master_safety_settings();
+            settings.enabled = !settings.enabled;
+            engine.set_master_safety_settings(settings);
+            let message = if settings.enabled {
+                "Safety stage enabled, it can be configured in the Note Processor tab."
+            } else {
+                "Safety stage disabled, the output of the patch is no longer protected."
+            };
+            this.with_gui_state_mut(|state| state.add_success_message(message.to_owned()));
+        })
+    }
+
+    fn on_click_voice_status(self: &Rc<Self>, mods: &MouseMods) -> MaybeMouseBehavior {
+        let engine = self.with_gui_state(|state| Rc::clone(&state.engine));
+        if mods.right_click {
+            return OnClickBehavior::wrap(move || {
+                engine.borrow_mut().start_picking_feedback_voice()
+            });
+        }
+        OnClickBehavior::wrap(move || {
+            let mut engine = engine.borrow_mut();
+            let next = match engine.get_feedback_voice() {
+                FeedbackVoice::Youngest => FeedbackVoice::Oldest,
+                FeedbackVoice::Oldest => FeedbackVoice::Loudest,
+                FeedbackVoice::Loudest | FeedbackVoice::Note(..) => FeedbackVoice::Youngest,
+            };
+            engine.set_feedback_voice(next);
+        })
+    }
+
+    fn draw_voice_status(self: &Rc<Self>, r: &mut Renderer) {
+        let (voice, displayed, picking) = self.with_gui_state(|state| {
+            let engine = state.engine.borrow();
+            (
+                engine.get_feedback_voice(),
+                engine.get_displayed_feedback_voice(),
+                engine.is_picking_feedback_voice(),
+            )
+        });
+        let pos = (VOICE_STATUS_START, 0.0);
+        let size = (VOICE_STATUS_WIDTH, TAB_HEIGHT);
+        r.set_color(if picking { &COLOR_WARNING } else { &COLOR_BG1 });
+        r.draw_rect(pos, size);
+        let text = if picking {
+            "Play a note".to_owned()
+        } else {
+            let displayed = displayed.map(note_name).unwrap_or_else(|| "--".to_owned());
+            let policy = match voice {
+                FeedbackVoice::Youngest => "New",
+                FeedbackVoice::Oldest => "Old",
+                FeedbackVoice::Loudest => "Loud",
+                FeedbackVoice::Note(..) => "Note",
+            };
+            format!("{}: {}", policy, displayed)
+        };
+        r.set_color(&COLOR_FG1);
+        r.draw_text(FONT_SIZE, pos, size, (0, 0), 1, &text);
+    }
+
+    fn draw_safety_status(self: &Rc<Self>, r: &mut Renderer) {
+        let reduction =
+            self.with_gui_state(|state| state.engine.borrow().get_master_gain_reduction());
+        let pos = (SAFETY_STATUS_START, 0.0);
+        let size = (SAFETY_STATUS_WIDTH, TAB_HEIGHT);
+        r.set_color(&COLOR_BG1);
+        r.draw_rect(pos, size);
+        let text = if let Some(reduction) = reduction {
+            let fill = (reduction / GAIN_REDUCTION_METER_RANGE).min(1.0);
+            r.set_color(&COLOR_BG2);
+            r.draw_rect(pos, (SAFETY_STATUS_WIDTH * fill, TAB_HEIGHT));
+            format!("GR: {:.1}dB", reduction)
+        } else {
+            "GR: Off".to_owned()
+        };
+        r.set_color(&COLOR_FG1);
+        r.draw_text(FONT_SIZE, pos, size, (0, 0), 1, &text);
+    }
+
+    fn draw_profile_status(self: &Rc<Self>, r: &mut Renderer) {
+        let (enabled, usage) = self.with_gui_state(|state| {
+            let engine = state.engine.borrow();
+            (engine.is_profiling_enabled(), engine.get_total_cpu_usage())
+        });
+        let pos = (PROFILE_STATUS_START, 0.0);
+        let size = (PROFILE_STATUS_WIDTH, TAB_HEIGHT);
+        r.set_color(&COLOR_BG1);
+        r.draw_rect(pos, size);
+        let text = match (enabled, usage) {
+            (false, _) => "CPU: Off".to_owned(),
+            (true, None) => "CPU: --".to_owned(),
+            (true, Some(usage)) => format!("CPU: {:.1}%", usage * 100.0),
+        };
+        r.set_color(&COLOR_FG1);
+        r.draw_text(FONT_SIZE, pos, size, (0, 0), 1, &text);
+    }
+
+    fn draw_audio_status(self: &Rc<Self>, r: &mut Renderer) {
+        let (underruns, lookahead) = self.with_gui_state(|state| {
+            let engine = state.engine.borrow();
+            (engine.get_underrun_count(), engine.is_lookahead_enabled())
+        });
+        let mut state = self.state.borrow_mut();
+        if underruns != state.seen_underruns {
+            state.seen_underruns = underruns;
+            state.last_underrun = Some(Instant::now());
+        }
+        let recent_underrun = state
+            .last_underrun
+            .map(|time| time.elapsed() < UNDERRUN_WARNING_DURATION)
+            .unwrap_or(false);
+        drop(state);
+
+        let pos = (AUDIO_STATUS_START, 0.0);
+        let size = (AUDIO_STATUS_WIDTH, TAB_HEIGHT);
+        r.set_color(if recent_underrun {
+            &COLOR_WARNING
+        } else {
+            &COLOR_BG1
+        });
+        r.draw_rect(pos, size);
+        let text = format!(
+            "{}{} underrun{}",
+            if lookahead { "LA | " } else { "" },
+            underruns,
+            if underruns == 1 { "" } else { "s" }
+        );
+        r.set_color(&COLOR_FG1);
+        r.draw_text(FONT_SIZE, pos, size, (0, 0), 1, &text);
+    }
+
+    fn draw_compile_status(self: &Rc<Self>, r: &mut Renderer) {
+        let progress = self.with_gui_state(|state| state.engine.borrow().get_compile_progress());
+        let progress = if let Some(progress) = progress {
+            progress
+        } else {
+            return;
+        };
+        let pos = (COMPILE_STATUS_START, 0.0);
+        let size = (COMPILE_STATUS_WIDTH, TAB_HEIGHT);
+        r.set_color(&COLOR_BG1);
+        r.draw_rect(pos, size);
+        // Julia can't tell how far along it is, so show that something is happening by sweeping a
+        // block back and forth instead of pretending to know how much work is left.
+        const SWEEP_WIDTH: f32 = COMPILE_STATUS_WIDTH / 4.0;
+        const SWEEP_PERIOD: f32 = 2.0;
+        let elapsed = progress.started.elapsed().as_secs_f32();
+        let phase = (elapsed / SWEEP_PERIOD).fract() * 2.0;
+        let phase = if phase > 1.0 { 2.0 - phase } else { phase };
+        let sweep_start = COMPILE_STATUS_START + (COMPILE_STATUS_WIDTH - SWEEP_WIDTH) * phase;
+        r.set_color(&COLOR_BG2);
+        r.draw_rect((sweep_start, 0.0), (SWEEP_WIDTH, TAB_HEIGHT));
+        let verb = match progress.stage {
+            CompileStage::Queued => "Queued",
+            CompileStage::Loading => "Loading",
+            CompileStage::Preheating => "Compiling",
+        };
+        let text = format!("{} {:.1}s", verb, elapsed);
+        r.set_color(&COLOR_FG1);
+        r.draw_text(FONT_SIZE, pos, size, (0, 0), 1, &text);
+    }
+
+    fn on_click_master_view(self: &Rc<Self>) -> MaybeMouseBehavior {
+        let this = Rc::clone(self);
+        OnClickBehavior::wrap(move || {
+            let mut state = this.parents.gui.state.borrow_mut();
+            if !state.switch_to(TabArchetype::Analyzer) {
+                drop(state);
+                let tab = TabArchetype::Analyzer.instantiate(&this);
+                this.parents.gui.state.borrow_mut().add_tab(tab);
+            }
+        })
+    }
+
+    /// Draws a small oscilloscope, level meter and spectrum of the master output next to the
+    /// tooltip.
+    fn draw_master_view(self: &Rc<Self>, r: &mut Renderer) {
+        const GP: f32 = GRID_P;
+        let inner_height = TOOLTIP_HEIGHT - GP;
+        r.set_color(&COLOR_BG0);
+        r.draw_rounded_rect(
+            (MASTER_VIEW_START, TOOLTIP_START),
+            (MASTER_VIEW_WIDTH, TOOLTIP_HEIGHT),
+            CORNER_SIZE,
+        );
+        self.with_gui_state(|state| {
+            let analyzer = &state.master_analyzer;
+            r.push_state();
+            r.translate((MASTER_VIEW_START + GP / 2.0, TOOLTIP_START + GP / 2.0));
+            r.draw_master_scope(
+                analyzer,
+                (MASTER_SCOPE_WIDTH, inner_height).into(),
+                MASTER_SCOPE_LENGTH,
+            );
+            r.translate((MASTER_SCOPE_WIDTH + GP / 2.0, 0.0));
+            r.draw_master_meter(analyzer, (MASTER_METER_WIDTH, inner_height).into());
+            r.translate((MASTER_METER_WIDTH + GP / 2.0, 0.0));
+            let spectrum_width =
+                MASTER_VIEW_WIDTH - MASTER_SCOPE_WIDTH - MASTER_METER_WIDTH - GP * 2.0;
+            r.draw_spectrum(
+                analyzer.borrow_spectrum(),
+                (spectrum_width, inner_height).into(),
+            );
+            r.pop_state();
+        });
+    }
+
     fn on_save_patch(self: &Rc<Self>) -> MaybeMouseBehavior {
         let mut patch_already_existed_on_disk = false;
         let state = self.state.borrow();
@@ -229,8 +518,23 @@ impl WidgetImpl<Renderer, DropTarget> for Header {
     ) -> MaybeMouseBehavior {
         ris!(self.get_mouse_behavior_children(pos, mods));
         if pos.y > TAB_HEIGHT {
+            if pos.x >= MASTER_VIEW_START && pos.y >= TOOLTIP_START {
+                return self.on_click_master_view();
+            }
             return None;
         }
+        if pos.x >= AUDIO_STATUS_START {
+            return self.on_toggle_lookahead();
+        }
+        if pos.x >= PROFILE_STATUS_START && pos.x < PROFILE_STATUS_START + PROFILE_STATUS_WIDTH {
+            return self.on_click_profile_status(mods);
+        }
+        if pos.x >= SAFETY_STATUS_START && pos.x < SAFETY_STATUS_START + SAFETY_STATUS_WIDTH {
+            return self.on_click_safety_status();
+        }
+        if pos.x >= VOICE_STATUS_START && pos.x < VOICE_STATUS_START + VOICE_STATUS_WIDTH {
+            return self.on_click_voice_status(mods);
+        }
 
         let tab_index = (pos.x / (TAB_SIZE.x + TAB_PADDING)) as usize;
         let this = Rc::clone(self);
@@ -243,6 +547,99 @@ impl WidgetImpl<Renderer, DropTarget> for Header {
         })
     }
 
+    fn on_hover_impl(self: &Rc<Self>, pos: Vec2D) -> Option<()> {
+        ris!(self.on_hover_children(pos));
+        if pos.y > TAB_HEIGHT && pos.x >= MASTER_VIEW_START && pos.y >= TOOLTIP_START {
+            self.with_gui_state_mut(|state| {
+                state.set_tooltip(Tooltip {
+                    text: concat!(
+                        "The output of the patch, shown as a waveform, peak and RMS levels and a ",
+                        "spectrum. Click to open the analyzer"
+                    )
+                    .to_owned(),
+                    interaction: vec![InteractionHint::LeftClick],
+                })
+            });
+            return Some(());
+        }
+        if pos.y > TAB_HEIGHT || pos.x < VOICE_STATUS_START {
+            return None;
+        }
+        if pos.x < VOICE_STATUS_START + VOICE_STATUS_WIDTH {
+            let text = concat!(
+                "Which note knobs and output views show when several are playing, click to ",
+                "choose between the newest, oldest and loudest note or right-click and then play ",
+                "a note on the virtual keyboard to follow that note"
+            );
+            self.with_gui_state_mut(|state| {
+                state.set_tooltip(Tooltip {
+                    text: text.to_owned(),
+                    interaction: vec![InteractionHint::LeftClick, InteractionHint::RightClick],
+                })
+            });
+            return Some(());
+        }
+        if pos.x < SAFETY_STATUS_START {
+            return None;
+        }
+        if pos.x < SAFETY_STATUS_START + SAFETY_STATUS_WIDTH {
+            let enabled = self
+                .with_gui_state(|state| state.engine.borrow().get_master_safety_settings().enabled);
+            let text = format!(
+                concat!(
+                    "How much the safety stage's limiter is turning the output down by (gain ",
+                    "reduction), click to {} the safety stage"
+                ),
+                if enabled { "disable" } else { "enable" }
+            );
+            self.with_gui_state_mut(|state| {
+                state.set_tooltip(Tooltip {
+                    text,
+                    interaction: vec![InteractionHint::LeftClick],
+                })
+            });
+            return Some(());
+        }
+        if pos.x < PROFILE_STATUS_START {
+            return None;
+        }
+        if pos.x < PROFILE_STATUS_START + PROFILE_STATUS_WIDTH {
+            let enabled = self.with_gui_state(|state| state.engine.borrow().is_profiling_enabled());
+            let text = format!(
+                concat!(
+                    "Profiling shows how much CPU time each module uses, click to {} it or ",
+                    "right-click to write a report to the message log"
+                ),
+                if enabled { "disable" } else { "enable" }
+            );
+            self.with_gui_state_mut(|state| {
+                state.set_tooltip(Tooltip {
+                    text,
+                    interaction: vec![InteractionHint::LeftClick, InteractionHint::RightClick],
+                })
+            });
+            return Some(());
+        }
+        if pos.x < AUDIO_STATUS_START {
+            return None;
+        }
+        let lookahead = self.with_gui_state(|state| state.engine.borrow().is_lookahead_enabled());
+        let text = format!(
+            concat!(
+                "Underruns happen when the patch takes too long to render, causing dropouts. ",
+                "Lookahead (LA) reduces them at the cost of extra latency, click to {} it"
+            ),
+            if lookahead { "disable" } else { "enable" }
+        );
+        self.with_gui_state_mut(|state| {
+            state.set_tooltip(Tooltip {
+                text,
+                interaction: vec![InteractionHint::LeftClick],
+            })
+        });
+        Some(())
+    }
+
     fn draw_impl(self: &Rc<Self>, r: &mut Renderer) {
         let self_state = self.state.borrow();
         const BFS: f32 = BIG_FONT_SIZE;
@@ -278,7 +675,8 @@ impl WidgetImpl<Renderer, DropTarget> for Header {
                 (&tooltip.text[..], &COLOR_BG0)
             };
             r.set_color(color);
-            let tooltip_size: Vec2D = (ROOT_WIDTH - GP * 2.0 - button_size, TOOLTIP_HEIGHT).into();
+            let tooltip_width = MASTER_VIEW_START - GP * 2.0 - button_size;
+            let tooltip_size: Vec2D = (tooltip_width, TOOLTIP_HEIGHT).into();
             r.draw_rounded_rect((GP + button_size, GP + TAB_HEIGHT), tooltip_size, CS);
 
             let textbox_size = tooltip_size - GP * 2.0;
@@ -337,6 +735,13 @@ impl WidgetImpl<Renderer, DropTarget> for Header {
 
             show_buttons
         });
+        drop(self_state);
+        self.draw_audio_status(r);
+        self.draw_profile_status(r);
+        self.draw_safety_status(r);
+        self.draw_voice_status(r);
+        self.draw_compile_status(r);
+        self.draw_master_view(r);
 
         if show_buttons {
             self.draw_children(r);
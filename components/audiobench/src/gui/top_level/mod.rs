@@ -1,16 +1,20 @@
 //! Contains widgets that make up the top-level structure of the GUI, I.E. tabs and header.
 
+mod analyzer;
 pub mod graph;
 mod header;
 mod library_info;
 mod message_log;
 mod module_browser;
 mod note_graph;
+mod note_processor_editor;
 mod patch_browser;
 
+pub use analyzer::*;
 pub use header::Header;
 pub use library_info::*;
 pub use message_log::*;
 pub use module_browser::*;
 pub use note_graph::*;
+pub use note_processor_editor::*;
 pub use patch_browser::*;
@@ -0,0 +1,370 @@
+use crate::{
+    engine::{
+        master_safety::MasterSafetySettings,
+        note_processor::{ArpMode, NoteProcessorSettings, CHORD_MEMORY_RANGE},
+        oversampling::OVERSAMPLING_FACTORS,
+    },
+    gui::{constants::*, GuiTab, InteractionHint, TabArchetype, Tooltip},
+    scui_config::{DropTarget, MaybeMouseBehavior, Renderer},
+};
+use scui::{MouseMods, OnClickBehavior, Vec2D, WidgetImpl};
+use shared_util::prelude::*;
+
+scui::widget! {
+    pub NoteProcessorEditor
+    State {}
+}
+
+const ROW_HEIGHT: f32 = grid(1) + GRID_P;
+const LABEL_WIDTH: f32 = grid(5);
+const OPTIONS_START: f32 = GRID_P * 2.0 + LABEL_WIDTH;
+const OPTIONS_WIDTH: f32 = TAB_BODY_WIDTH - OPTIONS_START - GRID_P;
+const MAX_OPTION_WIDTH: f32 = grid(3);
+
+/// Step lengths in beats along with how they should be displayed.
+const RATES: [(f32, &str); 8] = [
+    (4.0, "1/1"),
+    (2.0, "1/2"),
+    (1.0, "1/4"),
+    (0.5, "1/8"),
+    (1.0 / 3.0, "1/8T"),
+    (0.25, "1/16"),
+    (1.0 / 6.0, "1/16T"),
+    (0.125, "1/32"),
+];
+const GATES: [f32; 6] = [0.1, 0.25, 0.5, 0.75, 0.9, 1.0];
+const CROSSFADE_TIMES: [(f32, &str); 7] = [
+    (0.0, "Off"),
+    (0.05, "50ms"),
+    (0.1, "100ms"),
+    (0.25, "250ms"),
+    (0.5, "500ms"),
+    (1.0, "1s"),
+    (2.0, "2s"),
+];
+/// Master safety ceilings in dBFS.
+const CEILINGS: [f32; 5] = [0.0, -0.3, -1.0, -3.0, -6.0];
+const INTERVAL_NAMES: [&str; CHORD_MEMORY_RANGE] = [
+    "R", "m2", "M2", "m3", "M3", "P4", "TT", "P5", "m6", "M6", "m7", "M7", "8va", "m9", "M9",
+    "m10", "M10", "P11", "TT", "P12", "m13", "M13", "m14", "M14",
+];
+
+/// Everything that can be edited in this tab.
+struct Values {
+    settings: NoteProcessorSettings,
+    crossfade_time: f32,
+    safety: MasterSafetySettings,
+    oversampling: usize,
+    non_finite_check: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Row {
+    ArpMode,
+    Octaves,
+    Rate,
+    Gate,
+    NoteRepeat,
+    ChordMemory,
+    Chord,
+    Crossfade,
+    Oversampling,
+    NonFiniteCheck,
+    Safety,
+    DcBlocker,
+    Limiter,
+    SoftClip,
+    Ceiling,
+}
+
+impl Row {
+    const ALL: [Row; 15] = [
+        Row::ArpMode,
+        Row::Octaves,
+        Row::Rate,
+        Row::Gate,
+        Row::NoteRepeat,
+        Row::ChordMemory,
+        Row::Chord,
+        Row::Crossfade,
+        Row::Oversampling,
+        Row::NonFiniteCheck,
+        Row::Safety,
+        Row::DcBlocker,
+        Row::Limiter,
+        Row::SoftClip,
+        Row::Ceiling,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::ArpMode => "Arpeggiator",
+            Self::Octaves => "Octaves",
+            Self::Rate => "Rate",
+            Self::Gate => "Gate",
+            Self::NoteRepeat => "Note Repeat",
+            Self::ChordMemory => "Chord Memory",
+            Self::Chord => "Chord",
+            Self::Crossfade => "Edit Crossfade",
+            Self::Oversampling => "Oversampling",
+            Self::NonFiniteCheck => "NaN Check",
+            Self::Safety => "Safety Stage",
+            Self::DcBlocker => "DC Blocker",
+            Self::Limiter => "Limiter",
+            Self::SoftClip => "Soft Clip",
+            Self::Ceiling => "Ceiling",
+        }
+    }
+
+    fn tooltip(self) -> &'static str {
+        match self {
+            Self::ArpMode => "The order the arpeggiator plays held notes in",
+            Self::Octaves => "How many octaves the arpeggio spans",
+            Self::Rate => "How long each step of the arpeggiator or note repeat lasts",
+            Self::Gate => "How much of each step notes are held for",
+            Self::NoteRepeat => "Repeatedly retrigger all held notes at the selected rate",
+            Self::ChordMemory => "Play the chord below for every key that is pressed",
+            Self::Chord => "Which notes (relative to the pressed key) the chord contains",
+            Self::Crossfade => concat!(
+                "How long held notes take to fade into the new sound when the patch has been ",
+                "edited several times while they were playing"
+            ),
+            Self::Oversampling => concat!(
+                "Run the patch at a higher sample rate to reduce aliasing on high notes, at the ",
+                "cost of more CPU usage and a short recompile"
+            ),
+            Self::NonFiniteCheck => concat!(
+                "Find out which module produced NaN or infinite audio when a voice has to be ",
+                "muted, at a small cost to performance and a short recompile"
+            ),
+            Self::Safety => "Protect the final output of the patch from clipping and DC offset",
+            Self::DcBlocker => "Remove DC offset from the output",
+            Self::Limiter => concat!(
+                "Turn the output down just before it would go above the ceiling, the amount is ",
+                "shown in the top right corner"
+            ),
+            Self::SoftClip => "Smoothly saturate anything that gets past the limiter",
+            Self::Ceiling => "The loudest the output is allowed to get",
+        }
+    }
+
+    fn options(self) -> Vec<String> {
+        match self {
+            Self::ArpMode => ArpMode::ALL.iter().map(|m| m.name().to_owned()).collect(),
+            Self::Octaves => (1..=4).map(|o| format!("{}", o)).collect(),
+            Self::Rate => RATES.iter().map(|(_, name)| String::from(*name)).collect(),
+            Self::Gate => GATES
+                .iter()
+                .map(|g| format!("{}%", (g * 100.0) as i32))
+                .collect(),
+            Self::NoteRepeat
+            | Self::ChordMemory
+            | Self::NonFiniteCheck
+            | Self::Safety
+            | Self::DcBlocker
+            | Self::Limiter
+            | Self::SoftClip => vec!["Off".to_owned(), "On".to_owned()],
+            Self::Chord => INTERVAL_NAMES.iter().map(|&n| n.to_owned()).collect(),
+            Self::Crossfade => CROSSFADE_TIMES
+                .iter()
+                .map(|(_, name)| String::from(*name))
+                .collect(),
+            Self::Oversampling => OVERSAMPLING_FACTORS
+                .iter()
+                .map(|&factor| {
+                    if factor == 1 {
+                        "Off".to_owned()
+                    } else {
+                        format!("{}x", factor)
+                    }
+                })
+                .collect(),
+            Self::Ceiling => CEILINGS.iter().map(|c| format!("{}dB", c)).collect(),
+        }
+    }
+
+    fn is_selected(self, values: &Values, option: usize) -> bool {
+        let settings = &values.settings;
+        match self {
+            Self::ArpMode => ArpMode::ALL[option] == settings.arp_mode,
+            Self::Octaves => option + 1 == settings.arp_octaves as usize,
+            Self::Rate => (RATES[option].0 - settings.rate).abs() < 1e-4,
+            Self::Gate => (GATES[option] - settings.gate).abs() < 1e-3,
+            Self::NoteRepeat => (option == 1) == settings.note_repeat,
+            Self::ChordMemory => (option == 1) == settings.chord_memory,
+            Self::Chord => option == 0 || settings.chord & (1 << option) != 0,
+            Self::Crossfade => (CROSSFADE_TIMES[option].0 - values.crossfade_time).abs() < 1e-4,
+            Self::Oversampling => OVERSAMPLING_FACTORS[option] == values.oversampling,
+            Self::NonFiniteCheck => (option == 1) == values.non_finite_check,
+            Self::Safety => (option == 1) == values.safety.enabled,
+            Self::DcBlocker => (option == 1) == values.safety.dc_blocker,
+            Self::Limiter => (option == 1) == values.safety.limiter,
+            Self::SoftClip => (option == 1) == values.safety.soft_clip,
+            Self::Ceiling => (CEILINGS[option] - values.safety.ceiling).abs() < 1e-2,
+        }
+    }
+
+    fn select(self, values: &mut Values, option: usize) {
+        let settings = &mut values.settings;
+        match self {
+            Self::ArpMode => settings.arp_mode = ArpMode::ALL[option],
+            Self::Octaves => settings.arp_octaves = option as u8 + 1,
+            Self::Rate => settings.rate = RATES[option].0,
+            Self::Gate => settings.gate = GATES[option],
+            Self::NoteRepeat => settings.note_repeat = option == 1,
+            Self::ChordMemory => settings.chord_memory = option == 1,
+            // The root note is always part of the chord.
+            Self::Chord if option > 0 => settings.chord ^= 1 << option,
+            Self::Chord => (),
+            Self::Crossfade => values.crossfade_time = CROSSFADE_TIMES[option].0,
+            Self::Oversampling => values.oversampling = OVERSAMPLING_FACTORS[option],
+            Self::NonFiniteCheck => values.non_finite_check = option == 1,
+            Self::Safety => values.safety.enabled = option == 1,
+            Self::DcBlocker => values.safety.dc_blocker = option == 1,
+            Self::Limiter => values.safety.limiter = option == 1,
+            Self::SoftClip => values.safety.soft_clip = option == 1,
+            Self::Ceiling => values.safety.ceiling = CEILINGS[option],
+        }
+    }
+}
+
+fn option_width(num_options: usize) -> f32 {
+    (OPTIONS_WIDTH / num_options as f32).min(MAX_OPTION_WIDTH)
+}
+
+impl NoteProcessorEditor {
+    pub fn new(parent: &impl NoteProcessorEditorParent) -> Rc<Self> {
+        let state = NoteProcessorEditorState {};
+        Rc::new(Self::create(parent, state))
+    }
+
+    fn row_at(pos: Vec2D) -> Option<(Row, usize)> {
+        let row = ((pos.y - GRID_P) / ROW_HEIGHT).floor();
+        if row < 0.0 || row as usize >= Row::ALL.len() || pos.x < OPTIONS_START {
+            return None;
+        }
+        let row = Row::ALL[row as usize];
+        let num_options = row.options().len();
+        let option = ((pos.x - OPTIONS_START) / option_width(num_options)) as usize;
+        if option < num_options {
+            Some((row, option))
+        } else {
+            None
+        }
+    }
+}
+
+impl WidgetImpl<Renderer, DropTarget> for NoteProcessorEditor {
+    fn get_pos_impl(self: &Rc<Self>) -> Vec2D {
+        (0.0, HEADER_HEIGHT).into()
+    }
+
+    fn get_size_impl(self: &Rc<Self>) -> Vec2D {
+        TAB_BODY_SIZE
+    }
+
+    fn get_mouse_behavior_impl(
+        self: &Rc<Self>,
+        pos: Vec2D,
+        _mods: &MouseMods,
+    ) -> MaybeMouseBehavior {
+        let (row, option) = Self::row_at(pos)?;
+        let engine = self.with_gui_state(|state| Rc::clone(&state.engine));
+        OnClickBehavior::wrap(move || {
+            let mut engine = engine.borrow_mut();
+            let mut values = Values {
+                settings: engine.get_note_processor_settings(),
+                crossfade_time: engine.get_crossfade_time(),
+                safety: engine.get_master_safety_settings(),
+                oversampling: engine.get_oversampling(),
+                non_finite_check: engine.is_non_finite_check_enabled(),
+            };
+            row.select(&mut values, option);
+            engine.set_note_processor_settings(values.settings);
+            engine.set_crossfade_time(values.crossfade_time);
+            engine.set_master_safety_settings(values.safety);
+            engine.set_oversampling(values.oversampling);
+            engine.set_non_finite_check_enabled(values.non_finite_check);
+        })
+    }
+
+    fn on_hover_impl(self: &Rc<Self>, pos: Vec2D) -> Option<()> {
+        let (row, _) = Self::row_at(pos)?;
+        let tooltip = Tooltip {
+            text: row.tooltip().to_owned(),
+            interaction: vec![InteractionHint::LeftClick],
+        };
+        self.with_gui_state_mut(|state| state.set_tooltip(tooltip));
+        Some(())
+    }
+
+    fn draw_impl(self: &Rc<Self>, g: &mut Renderer) {
+        const GP: f32 = GRID_P;
+        let values = self.with_gui_state(|state| {
+            let engine = state.engine.borrow();
+            Values {
+                settings: engine.get_note_processor_settings(),
+                crossfade_time: engine.get_crossfade_time(),
+                safety: engine.get_master_safety_settings(),
+                oversampling: engine.get_oversampling(),
+                non_finite_check: engine.is_non_finite_check_enabled(),
+            }
+        });
+        let settings = &values.settings;
+
+        g.set_color(&COLOR_BG2);
+        g.draw_rect(0, TAB_BODY_SIZE);
+
+        for (index, row) in Row::ALL.iter().enumerate() {
+            let y = GP + ROW_HEIGHT * index as f32;
+            // Options that have no effect with the current settings are drawn faded out.
+            let relevant = match row {
+                Row::Octaves => settings.arp_mode != ArpMode::Off,
+                Row::Rate | Row::Gate => settings.is_stepped(),
+                Row::Chord => settings.chord_memory,
+                Row::DcBlocker | Row::Limiter | Row::SoftClip => values.safety.enabled,
+                Row::Ceiling => {
+                    values.safety.enabled && (values.safety.limiter || values.safety.soft_clip)
+                }
+                _ => true,
+            };
+            g.set_color(&COLOR_FG1);
+            g.draw_text(
+                FONT_SIZE,
+                (GP, y),
+                (LABEL_WIDTH, grid(1)),
+                (1, 0),
+                1,
+                row.label(),
+            );
+            let options = row.options();
+            let width = option_width(options.len());
+            for (option_index, option) in options.iter().enumerate() {
+                let x = OPTIONS_START + width * option_index as f32;
+                let selected = row.is_selected(&values, option_index);
+                g.set_color(if selected {
+                    &COLOR_EDITABLE
+                } else {
+                    &COLOR_BG0
+                });
+                if !relevant {
+                    g.set_alpha(0.5);
+                }
+                g.draw_rounded_rect((x, y), (width - 2.0, grid(1)), CORNER_SIZE);
+                g.set_color(if selected { &COLOR_BG0 } else { &COLOR_FG1 });
+                g.draw_text(FONT_SIZE, (x, y), (width - 2.0, grid(1)), (0, 0), 1, option);
+                g.set_alpha(1.0);
+            }
+        }
+    }
+}
+
+impl GuiTab for Rc<NoteProcessorEditor> {
+    fn get_name(self: &Self) -> String {
+        format!("Note Processor")
+    }
+
+    fn get_archetype(&self) -> TabArchetype {
+        TabArchetype::NoteProcessor
+    }
+}
@@ -96,6 +96,15 @@ impl PatchBrowser {
             "View a log of all info/warning/error messages from this session".into(),
         ));
         let x = x + TabButton::SIZE + GRID_P;
+        tab_buttons.push(TabButton::new(
+            &this,
+            (x, 0.0),
+            registry.lookup_icon("Factory:arpeggiator").unwrap(),
+            TabArchetype::NoteProcessor,
+            "Note Processor".into(),
+            "Set up the arpeggiator, chord memory, and note repeat for this patch".into(),
+        ));
+        let x = x + TabButton::SIZE + GRID_P;
         let link_button = LinkButton::new(
             &this,
             (x, 0.0),
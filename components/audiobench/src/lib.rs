@@ -12,6 +12,12 @@ use gui::Gui;
 use registry::{save_data::Patch, Registry};
 use shared_util::prelude::*;
 
+/// Lets render_audio check that it never touches the heap.
+#[cfg(debug_assertions)]
+#[global_allocator]
+static ALLOCATOR: shared_util::alloc_tracker::TrackingAllocator =
+    shared_util::alloc_tracker::TrackingAllocator;
+
 pub struct ErrorDrawer {
     error: String,
     pub graphics_fns: Rc<GraphicsFunctions>,
@@ -202,7 +208,10 @@ impl Instance {
     }
 
     pub fn audio_render_audio(&mut self) -> &[f32] {
-        self.audio = self.audio_engine.borrow_mut().render_audio();
+        let mut audio_engine = self.audio_engine.borrow_mut();
+        // This only allocates when the buffer length changes, which hosts don't do during playback.
+        self.audio.resize(audio_engine.get_output_length(), 0.0);
+        audio_engine.render_audio(&mut self.audio[..]);
         &self.audio[..]
     }
 
@@ -222,6 +231,7 @@ impl Instance {
             debug_assert!(false, "create_gui called when GUI was already created!");
             eprintln!("WARNING: create_gui called when GUI was already created!");
         } else {
+            self.ui_engine.borrow().set_gui_open(true);
             self.gui = Some(gui::new_gui(
                 Rc::clone(&self.registry),
                 Rc::clone(&self.ui_engine),
@@ -257,6 +267,7 @@ impl Instance {
             eprintln!("WARNING: destroy_gui called when GUI was already destroyed!");
         } else {
             self.gui = None;
+            self.ui_engine.borrow().set_gui_open(false);
         }
     }
 
@@ -1,5 +1,6 @@
 use crate::{
     config::*,
+    engine::audio_sample::{self, AudioSample},
     registry::{
         library_preload::{self, PreloadedLibrary, ZippedLibraryContentProvider},
         module_template::ModuleTemplate,
@@ -14,7 +15,7 @@ use shared_util::prelude::*;
 use std::{
     collections::{HashMap, HashSet},
     fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::mpsc::{self, Receiver, TryRecvError},
 };
 
@@ -35,6 +36,12 @@ pub struct Registry {
     patches: Vec<Rcrc<Patch>>,
     patch_paths: HashMap<String, usize>,
 
+    samples: Vec<Rc<AudioSample>>,
+    sample_indexes: HashMap<String, usize>,
+    /// Where samples from libraries stored as folders are on disk, used to find out if a file the
+    /// user picked is part of a library.
+    sample_paths: HashMap<PathBuf, String>,
+
     library_path: PathBuf,
     library_info: HashMap<String, LibraryInfo>,
     checked_updates: HashMap<String, Option<UpdateInfo>>,
@@ -189,6 +196,13 @@ impl Registry {
             self.load_module_script_resource(lib_name, &full_name, buffer)?;
         } else if file_name.ends_with(".abpatch") {
             self.unloaded_patches.push((full_name, full_path, buffer));
+        } else if audio_sample::is_supported_file(file_name) {
+            let sample = AudioSample::decode(&full_name, &buffer[..])?;
+            if let Some(full_path) = full_path {
+                self.sample_paths.insert(full_path, full_name.clone());
+            }
+            self.sample_indexes.insert(full_name, self.samples.len());
+            self.samples.push(Rc::new(sample));
         } else if file_name.ends_with(".md") {
             // Ignore, probably just readme / license type stuff.
         } else if file_name == "library_info.yaml" {
@@ -387,6 +401,10 @@ impl Registry {
             patches: Vec::new(),
             patch_paths: HashMap::new(),
 
+            samples: Vec::new(),
+            sample_indexes: HashMap::new(),
+            sample_paths: HashMap::new(),
+
             library_path,
             library_info: HashMap::new(),
             checked_updates: HashMap::new(),
@@ -447,6 +465,20 @@ impl Registry {
         &self.patches
     }
 
+    /// Samples are named like patches, E.G. `Factory:samples/Piano.wav`.
+    pub fn get_sample_by_name(&self, name: &str) -> Option<&Rc<AudioSample>> {
+        self.sample_indexes.get(name).map(|i| &self.samples[*i])
+    }
+
+    /// Returns the name of the sample stored at the specified path, if it is part of a library.
+    pub fn get_sample_name_by_path(&self, path: &Path) -> Option<&str> {
+        let path = path.canonicalize().ok()?;
+        self.sample_paths
+            .iter()
+            .find(|(sample_path, _)| sample_path.canonicalize().ok().as_ref() == Some(&path))
+            .map(|(_, name)| &name[..])
+    }
+
     pub fn borrow_library_info(&self, name: &str) -> Option<&LibraryInfo> {
         self.library_info.get(name)
     }
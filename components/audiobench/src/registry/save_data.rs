@@ -1,5 +1,11 @@
 use crate::{
-    engine::{controls::AutomationSource, parts as ep},
+    engine::{
+        controls::{AnyControl, AutomationSource},
+        master_safety::MasterSafetySettings,
+        note_processor::NoteProcessorSettings,
+        oversampling::OVERSAMPLING_FACTORS,
+        parts as ep,
+    },
     registry::Registry,
 };
 use shared_util::{
@@ -20,17 +26,24 @@ pub struct Patch {
     pub(crate) source: PatchSource,
     name: String,
     exists_on_disk: bool,
+    note_processor_settings: NoteProcessorSettings,
+    master_safety_settings: MasterSafetySettings,
+    /// How many times faster than the host's sample rate the note graph runs.
+    oversampling: usize,
     data: Vec<u8>,
 }
 
 impl Patch {
-    const FORMAT_VERSION: u8 = 2;
+    const FORMAT_VERSION: u8 = 5;
 
     pub fn new_dummy(name: String) -> Self {
         Self {
             name,
             source: PatchSource::Dummy,
             exists_on_disk: false,
+            note_processor_settings: Default::default(),
+            master_safety_settings: Default::default(),
+            oversampling: 1,
             data: Vec::new(),
         }
     }
@@ -40,6 +53,9 @@ impl Patch {
             name: "Unnamed".to_owned(),
             source: PatchSource::Writable(save_path),
             exists_on_disk: false,
+            note_processor_settings: Default::default(),
+            master_safety_settings: Default::default(),
+            oversampling: 1,
             data: Vec::new(),
         }
     }
@@ -49,6 +65,9 @@ impl Patch {
             name: Default::default(),
             source,
             exists_on_disk: true,
+            note_processor_settings: Default::default(),
+            master_safety_settings: Default::default(),
+            oversampling: 1,
             data: Vec::new(),
         };
         patch.deserialize(data)?;
@@ -85,6 +104,31 @@ impl Patch {
         &self.name
     }
 
+    pub fn get_note_processor_settings(&self) -> NoteProcessorSettings {
+        self.note_processor_settings
+    }
+
+    pub fn set_note_processor_settings(&mut self, settings: NoteProcessorSettings) {
+        self.note_processor_settings = settings;
+    }
+
+    pub fn get_master_safety_settings(&self) -> MasterSafetySettings {
+        self.master_safety_settings
+    }
+
+    pub fn set_master_safety_settings(&mut self, settings: MasterSafetySettings) {
+        self.master_safety_settings = settings;
+    }
+
+    pub fn get_oversampling(&self) -> usize {
+        self.oversampling
+    }
+
+    pub fn set_oversampling(&mut self, factor: usize) {
+        assert!(OVERSAMPLING_FACTORS.contains(&factor));
+        self.oversampling = factor;
+    }
+
     pub fn save_note_graph(&mut self, graph: &ep::ModuleGraph, registry: &Registry) {
         let mut ser = MiniSer::new();
         let mut ordered_lib_names = Vec::new();
@@ -183,8 +227,8 @@ impl Patch {
         }
         for i in 0..num_modules as usize {
             let mut module = modules[i].borrow_mut();
-            for control in &mut module.controls {
-                let control_ptr = control.as_dyn_ptr();
+            for any_control in &mut module.controls {
+                let control_ptr = any_control.as_dyn_ptr();
                 let mut control = control_ptr.borrow_mut();
                 // Connect wires
                 while des.bool()? {
@@ -200,6 +244,19 @@ impl Patch {
                     }
                     let output_type =
                         target_module.borrow().template.borrow().outputs[output_index].get_type();
+                    let acceptable = control.acceptable_automation();
+                    let output_type = if acceptable.contains(&output_type) {
+                        output_type
+                    } else if output_type == ep::JackType::Control
+                        && acceptable.contains(&ep::JackType::Audio)
+                    {
+                        // Modules like LFO and Envelope used to have audio outputs, so older
+                        // patches can connect them to inputs that only take audio. Both carry
+                        // the same data, so the wire is kept as an audio connection.
+                        ep::JackType::Audio
+                    } else {
+                        return Err(());
+                    };
                     let source = AutomationSource {
                         module: target_module,
                         output_index,
@@ -208,6 +265,11 @@ impl Patch {
                     control.connect_automation(source);
                 }
                 control.deserialize(&mut des)?;
+                drop(control);
+                // Samples from libraries can only be found once the registry is available.
+                if let AnyControl::Sample(sample_control) = any_control {
+                    sample_control.borrow_mut().resolve_library_sample(registry);
+                }
             }
         }
         graph.set_modules(modules);
@@ -245,6 +307,21 @@ impl Patch {
         let mut ser = MiniSer::new();
         ser.u8(Self::FORMAT_VERSION);
         ser.str(&self.name);
+        // Settings are stored in their own length-prefixed section so that the graph data after
+        // them stays byte-aligned.
+        let mut settings_ser = MiniSer::new();
+        self.note_processor_settings.serialize(&mut settings_ser);
+        self.master_safety_settings.serialize(&mut settings_ser);
+        settings_ser.note("Oversampling: ");
+        let oversampling_index = OVERSAMPLING_FACTORS
+            .iter()
+            .position(|&factor| factor == self.oversampling)
+            .unwrap();
+        settings_ser.u2(oversampling_index as u8);
+        let settings_data = settings_ser.finish();
+        assert!(settings_data.len() < 0x100);
+        ser.u8(settings_data.len() as u8);
+        ser.blob(&settings_data[..]);
         ser.blob(&self.data[..]);
         let data = ser.finish();
         base64::encode_config(&data, base64::URL_SAFE_NO_PAD)
@@ -269,6 +346,30 @@ impl Patch {
         self.name = des
             .str()
             .map_err(|_| "ERROR: Patch data is corrupt (does not contain patch name.)")?;
+        // Patch-level settings were added in version 3, the master safety stage in version 4 and
+        // oversampling in version 5.
+        self.note_processor_settings = Default::default();
+        self.master_safety_settings = MasterSafetySettings::disabled();
+        self.oversampling = 1;
+        if format_version >= 3 {
+            let corrupt = |_| "ERROR: Patch data is corrupt (invalid patch settings.)";
+            let len = des.u8().map_err(corrupt)?;
+            let mut settings_data = Vec::new();
+            for _ in 0..len {
+                settings_data.push(des.u8().map_err(corrupt)?);
+            }
+            let mut settings_des = MiniDes::start(settings_data);
+            self.note_processor_settings =
+                NoteProcessorSettings::deserialize(&mut settings_des).map_err(corrupt)?;
+            if format_version >= 4 {
+                self.master_safety_settings =
+                    MasterSafetySettings::deserialize(&mut settings_des).map_err(corrupt)?;
+            }
+            if format_version >= 5 {
+                let index = settings_des.u2().map_err(corrupt)?;
+                self.oversampling = OVERSAMPLING_FACTORS[index as usize];
+            }
+        }
         self.data = des.end();
         Ok(())
     }
@@ -0,0 +1,351 @@
+//! Plays a fixed sequence of notes through every patch in the factory library and compares the
+//! result against reference recordings stored in `tests/golden_audio/`. The references are not raw
+//! audio but a fingerprint of it: the loudness and coarse spectrum of consecutive windows. This
+//! keeps them small and lets the comparison tolerate the tiny differences caused by floating point
+//! reordering or patches that use random numbers, while still catching real changes in sound.
+//!
+//! If a change to the sound is intentional, rerecord the references with
+//! `python build.py regenerate_golden_audio`, which sets `AUDIOBENCH_REGENERATE_GOLDEN_AUDIO`.
+
+use audiobench::Instance;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const REGENERATE_VAR: &str = "AUDIOBENCH_REGENERATE_GOLDEN_AUDIO";
+/// How long to wait for a patch to compile before giving up.
+const COMPILE_TIMEOUT: Duration = Duration::from_secs(600);
+
+const BUFFER_LENGTH: usize = 512;
+const SAMPLE_RATE: usize = 44100;
+/// (buffer index, note index, velocity), a velocity of None releases the note.
+const NOTE_SEQUENCE: &[(usize, usize, Option<f32>)] = &[
+    (0, 60, Some(1.0)),
+    (20, 64, Some(0.8)),
+    (20, 67, Some(0.8)),
+    (40, 60, None),
+    (60, 64, None),
+    (60, 67, None),
+    (70, 48, Some(0.5)),
+    (90, 48, None),
+];
+/// How many buffers to render in total, the last ones are there to capture release tails.
+const NUM_BUFFERS: usize = 130;
+
+/// How many samples of mono audio each line of a fingerprint describes.
+const WINDOW_SIZE: usize = 4096;
+const NUM_BANDS: usize = 16;
+const LOWEST_BAND_FREQ: f32 = 40.0;
+const HIGHEST_BAND_FREQ: f32 = 16_000.0;
+/// Anything quieter than this is considered silence.
+const FLOOR_DB: f32 = -100.0;
+/// Windows where both the reference and the output are quieter than this are not compared.
+const SILENCE_DB: f32 = -60.0;
+/// Bands this far below the loudest band in a window are considered silent.
+const BAND_RANGE_DB: f32 = 60.0;
+const RMS_TOLERANCE_DB: f32 = 1.5;
+/// Maximum average difference between the bands of two windows.
+const SPECTRAL_TOLERANCE_DB: f32 = 3.0;
+
+fn to_db(power: f32) -> f32 {
+    (10.0 * power.log10()).max(FLOOR_DB)
+}
+
+/// In-place radix 2 FFT, `re` and `im` must have a length that is a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f32::consts::PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tre = re[b] * cos - im[b] * sin;
+                let tim = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tre;
+                im[b] = im[a] - tim;
+                re[a] += tre;
+                im[a] += tim;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// One line of a fingerprint, all values are in dB.
+#[derive(Clone, Debug)]
+struct Window {
+    rms: f32,
+    bands: Vec<f32>,
+}
+
+impl Window {
+    fn analyze(samples: &[f32], sample_rate: usize) -> Self {
+        let power = samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32;
+        let mut re: Vec<f32> = samples
+            .iter()
+            .enumerate()
+            .map(|(index, sample)| {
+                let phase = index as f32 / samples.len() as f32;
+                let hann = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * phase).cos();
+                sample * hann
+            })
+            .collect();
+        let mut im = vec![0.0; re.len()];
+        fft(&mut re, &mut im);
+        let bin_width = sample_rate as f32 / samples.len() as f32;
+        let ratio = (HIGHEST_BAND_FREQ / LOWEST_BAND_FREQ).powf(1.0 / NUM_BANDS as f32);
+        let mut bands = vec![0.0; NUM_BANDS];
+        for bin in 1..samples.len() / 2 {
+            let freq = bin as f32 * bin_width;
+            if freq < LOWEST_BAND_FREQ || freq >= HIGHEST_BAND_FREQ {
+                continue;
+            }
+            let band = ((freq / LOWEST_BAND_FREQ).ln() / ratio.ln()) as usize;
+            let magnitude = re[bin] * re[bin] + im[bin] * im[bin];
+            bands[band.min(NUM_BANDS - 1)] += magnitude / (samples.len() * samples.len()) as f32;
+        }
+        Self {
+            rms: to_db(power),
+            bands: bands.into_iter().map(to_db).collect(),
+        }
+    }
+
+    fn parse(line: &str) -> Result<Self, String> {
+        let values = line
+            .split_whitespace()
+            .map(|value| value.parse())
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|err| format!("ERROR: Invalid number in \"{}\": {}", line, err))?;
+        if values.len() != NUM_BANDS + 1 {
+            return Err(format!(
+                "ERROR: Expected {} values in \"{}\".",
+                NUM_BANDS + 1,
+                line
+            ));
+        }
+        Ok(Self {
+            rms: values[0],
+            bands: Vec::from(&values[1..]),
+        })
+    }
+
+    fn serialize(&self) -> String {
+        let mut line = format!("{:.1}", self.rms);
+        for band in &self.bands {
+            line.push_str(&format!(" {:.1}", band));
+        }
+        line
+    }
+
+    /// Average difference between the bands of the two windows, ignoring how far below the
+    /// loudest band something is once it is too quiet to matter.
+    fn spectral_difference(&self, other: &Self) -> f32 {
+        let loudest = self
+            .bands
+            .iter()
+            .chain(other.bands.iter())
+            .cloned()
+            .fold(FLOOR_DB, f32::max);
+        let floor = loudest - BAND_RANGE_DB;
+        let total: f32 = self
+            .bands
+            .iter()
+            .zip(other.bands.iter())
+            .map(|(a, b)| (a.max(floor) - b.max(floor)).abs())
+            .sum();
+        total / NUM_BANDS as f32
+    }
+}
+
+fn fingerprint(audio: &[f32], channels: usize, sample_rate: usize) -> Vec<Window> {
+    let mono: Vec<f32> = audio
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    mono.chunks_exact(WINDOW_SIZE)
+        .map(|window| Window::analyze(window, sample_rate))
+        .collect()
+}
+
+fn compare(reference: &[Window], output: &[Window]) -> Result<(), String> {
+    if reference.len() != output.len() {
+        return Err(format!(
+            "expected {} windows of audio but rendered {}",
+            reference.len(),
+            output.len()
+        ));
+    }
+    for (index, (expected, actual)) in reference.iter().zip(output.iter()).enumerate() {
+        if expected.rms < SILENCE_DB && actual.rms < SILENCE_DB {
+            continue;
+        }
+        let rms_difference = (expected.rms - actual.rms).abs();
+        if rms_difference > RMS_TOLERANCE_DB {
+            return Err(format!(
+                "window {} has an RMS level of {:.1}dB, expected {:.1}dB",
+                index, actual.rms, expected.rms
+            ));
+        }
+        let spectral_difference = expected.spectral_difference(actual);
+        if spectral_difference > SPECTRAL_TOLERANCE_DB {
+            return Err(format!(
+                "window {} has a spectrum which is on average {:.1}dB away from the reference",
+                index, spectral_difference
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn wait_for_compile(instance: &mut Instance, patch_name: &str) -> Result<(), String> {
+    let start = Instant::now();
+    loop {
+        let mut engine = instance.ui_engine.borrow_mut();
+        let errors = engine.take_posted_errors();
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        if engine.get_julia_thread_status().is_ready() && engine.is_note_graph_up_to_date() {
+            return Ok(());
+        }
+        if start.elapsed() > COMPILE_TIMEOUT {
+            return Err(format!("ERROR: Timed out while compiling {}.", patch_name));
+        }
+        drop(engine);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn render_patch(instance: &mut Instance, patch_name: &str) -> Result<Vec<Window>, String> {
+    instance
+        .audio_engine
+        .borrow_mut()
+        .set_global_params(BUFFER_LENGTH, SAMPLE_RATE);
+    let patch = instance
+        .registry
+        .borrow()
+        .get_patch_by_name(&format!("Factory:patches/{}", patch_name))
+        .map(Rc::clone)
+        .ok_or_else(|| format!("ERROR: The registry does not contain {}.", patch_name))?;
+    instance
+        .ui_engine
+        .borrow_mut()
+        .load_patch(patch)
+        .map_err(|_| format!("ERROR: Failed to load {}.", patch_name))?;
+    wait_for_compile(instance, patch_name)?;
+
+    let mut engine = instance.audio_engine.borrow_mut();
+    let mut buffer = vec![0.0; engine.get_output_length()];
+    let mut audio = Vec::with_capacity(buffer.len() * NUM_BUFFERS);
+    for buffer_index in 0..NUM_BUFFERS {
+        for &(_, note, velocity) in NOTE_SEQUENCE.iter().filter(|e| e.0 == buffer_index) {
+            if let Some(velocity) = velocity {
+                engine.start_note(note, velocity);
+            } else {
+                engine.release_note(note);
+            }
+        }
+        engine.render_audio_offline(&mut buffer)?;
+        audio.extend_from_slice(&buffer);
+    }
+    // Don't leave anything playing for the next patch.
+    for &(_, note, _) in NOTE_SEQUENCE {
+        engine.release_note(note);
+    }
+    let channels = buffer.len() / BUFFER_LENGTH;
+    Ok(fingerprint(&audio, channels, SAMPLE_RATE))
+}
+
+fn reference_path(patch_name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden_audio")
+        .join(patch_name.replace(".abpatch", ".txt"))
+}
+
+fn read_reference(path: &Path) -> Result<Vec<Window>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|err| {
+        format!(
+            "ERROR: Failed to read {}, rerecord the references with \
+            `python build.py regenerate_golden_audio`: {}",
+            path.display(),
+            err
+        )
+    })?;
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .map(Window::parse)
+        .collect()
+}
+
+fn write_reference(path: &Path, patch_name: &str, windows: &[Window]) -> Result<(), String> {
+    let mut contents = format!(
+        "# Golden audio fingerprint of Factory:patches/{}, see tests/golden_audio.rs.\n\
+        # Each line is the RMS level of a window of audio followed by the level of each band, in dB.\n",
+        patch_name
+    );
+    for window in windows {
+        contents.push_str(&window.serialize());
+        contents.push('\n');
+    }
+    std::fs::create_dir_all(path.parent().unwrap())
+        .and_then(|_| std::fs::write(path, contents))
+        .map_err(|err| format!("ERROR: Failed to write {}: {}", path.display(), err))
+}
+
+fn factory_patch_names() -> Vec<String> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..")
+        .join("factory_library")
+        .join("patches");
+    let mut names: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".abpatch"))
+        .collect();
+    names.sort();
+    names
+}
+
+// Julia can only be started once per process, so every patch is tested by the same instance.
+#[test]
+fn factory_patches_match_golden_audio() {
+    let regenerate = std::env::var_os(REGENERATE_VAR).is_some();
+    let mut instance = Instance::new().unwrap();
+    let mut failures = Vec::new();
+    for patch_name in factory_patch_names() {
+        let path = reference_path(&patch_name);
+        let result = render_patch(&mut instance, &patch_name).and_then(|output| {
+            if regenerate {
+                write_reference(&path, &patch_name, &output)
+            } else {
+                compare(&read_reference(&path)?, &output)
+            }
+        });
+        if let Err(err) = result {
+            failures.push(format!("{}: {}", patch_name, err));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} patch(es) did not match their golden audio:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,161 @@
+//! Plays a note through a patch made of an Oscillator, an Envelope and a NoteOutput using the
+//! native executor, and compares the result against a reference rendered by Julia which is stored
+//! in `tests/native_executor/`. Unlike the golden audio tests this compares the samples themselves,
+//! because the patch is deterministic and the native modules are supposed to behave exactly like
+//! their Julia sources.
+//!
+//! If the Julia code of these modules changes, rerecord the reference with
+//! `python build.py regenerate_golden_audio`, which sets `AUDIOBENCH_REGENERATE_GOLDEN_AUDIO`. The
+//! reference is then rendered with Julia instead of being compared against.
+
+use audiobench::Instance;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const REGENERATE_VAR: &str = "AUDIOBENCH_REGENERATE_GOLDEN_AUDIO";
+/// Same as NATIVE_BACKEND_VAR in the engine.
+const NATIVE_BACKEND_VAR: &str = "AUDIOBENCH_NATIVE_BACKEND";
+const COMPILE_TIMEOUT: Duration = Duration::from_secs(600);
+const PATCH_NAME: &str = "Native Executor Test";
+
+const BUFFER_LENGTH: usize = 512;
+const SAMPLE_RATE: usize = 44100;
+const NOTE: usize = 60;
+const RELEASE_BUFFER: usize = 20;
+/// Long enough for the release of the envelope to finish.
+const NUM_BUFFERS: usize = 50;
+/// Only every this many samples of the first channel are stored in the reference.
+const SAMPLE_STRIDE: usize = 64;
+/// Julia and Rust do not round floating point math in exactly the same way.
+const TOLERANCE: f32 = 1e-3;
+
+fn data_path(extension: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("native_executor")
+        .join(format!("{}.{}", PATCH_NAME, extension))
+}
+
+fn wait_for_compile(instance: &mut Instance) -> Result<(), String> {
+    let start = Instant::now();
+    loop {
+        let mut engine = instance.ui_engine.borrow_mut();
+        let errors = engine.take_posted_errors();
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+        if engine.get_julia_thread_status().is_ready() && engine.is_note_graph_up_to_date() {
+            return Ok(());
+        }
+        if start.elapsed() > COMPILE_TIMEOUT {
+            return Err(format!("ERROR: Timed out while compiling {}.", PATCH_NAME));
+        }
+        drop(engine);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn render(instance: &mut Instance) -> Result<Vec<f32>, String> {
+    instance
+        .audio_engine
+        .borrow_mut()
+        .set_global_params(BUFFER_LENGTH, SAMPLE_RATE);
+    let path = data_path("abpatch");
+    let patch = std::fs::read(&path)
+        .map_err(|err| format!("ERROR: Failed to read {}: {}", path.display(), err))?;
+    instance
+        .ui_deserialize_patch(&patch)
+        .map_err(|_| format!("ERROR: Failed to load {}.", path.display()))?;
+    wait_for_compile(instance)?;
+
+    let mut engine = instance.audio_engine.borrow_mut();
+    let mut buffer = vec![0.0; engine.get_output_length()];
+    let channels = buffer.len() / BUFFER_LENGTH;
+    let mut samples = Vec::new();
+    for buffer_index in 0..NUM_BUFFERS {
+        if buffer_index == 0 {
+            engine.start_note(NOTE, 1.0);
+        } else if buffer_index == RELEASE_BUFFER {
+            engine.release_note(NOTE);
+        }
+        engine.render_audio_offline(&mut buffer)?;
+        samples.extend(buffer.iter().step_by(channels * SAMPLE_STRIDE));
+    }
+    Ok(samples)
+}
+
+fn read_reference(path: &Path) -> Result<Vec<f32>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|err| {
+        format!(
+            "ERROR: Failed to read {}, rerecord it with \
+            `python build.py regenerate_golden_audio`: {}",
+            path.display(),
+            err
+        )
+    })?;
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .map(|line| {
+            line.trim()
+                .parse()
+                .map_err(|err| format!("ERROR: Invalid number \"{}\": {}", line, err))
+        })
+        .collect()
+}
+
+fn write_reference(path: &Path, samples: &[f32]) -> Result<(), String> {
+    let mut contents = format!(
+        "# Rendered by Julia, see tests/native_executor.rs. Every {}th sample of the first channel.\n",
+        SAMPLE_STRIDE
+    );
+    for sample in samples {
+        contents.push_str(&format!("{:.6}\n", sample));
+    }
+    std::fs::write(path, contents)
+        .map_err(|err| format!("ERROR: Failed to write {}: {}", path.display(), err))
+}
+
+fn compare(reference: &[f32], output: &[f32]) -> Result<(), String> {
+    if reference.len() != output.len() {
+        return Err(format!(
+            "expected {} samples but rendered {}",
+            reference.len(),
+            output.len()
+        ));
+    }
+    for (index, (expected, actual)) in reference.iter().zip(output.iter()).enumerate() {
+        if (expected - actual).abs() > TOLERANCE {
+            return Err(format!(
+                "sample {} is {}, expected {}",
+                index * SAMPLE_STRIDE,
+                actual,
+                expected
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Julia can only be started once per process, and whether it is used at all is decided when the
+// first instance is created, so this has to be the only test in this file.
+#[test]
+fn native_executor_matches_julia() {
+    let regenerate = std::env::var_os(REGENERATE_VAR).is_some();
+    if regenerate {
+        std::env::remove_var(NATIVE_BACKEND_VAR);
+    } else {
+        std::env::set_var(NATIVE_BACKEND_VAR, "1");
+    }
+    let mut instance = Instance::new().unwrap();
+    let output = render(&mut instance).unwrap();
+    let path = data_path("txt");
+    if regenerate {
+        write_reference(&path, &output).unwrap();
+    } else {
+        let reference = read_reference(&path).unwrap();
+        if let Err(err) = compare(&reference, &output) {
+            panic!("The native executor does not match Julia: {}", err);
+        }
+    }
+}
@@ -37,10 +37,16 @@ extern "C" {
     void ABAudioStartNote(ABInstanceRef, int, float);
     void ABAudioReleaseNote(ABInstanceRef, int);
     void ABAudioPitchWheel(ABInstanceRef, float);
+    void ABAudioChannelPressure(ABInstanceRef, float);
     void ABAudioBpm(ABInstanceRef, float);
-    void ABAudioSongTime(ABInstanceRef, float);
-    void ABAudioSongBeats(ABInstanceRef, float);
+    void ABAudioElapsedTime(ABInstanceRef, float);
+    void ABAudioElapsedBeats(ABInstanceRef, float);
+    void ABAudioPlaying(ABInstanceRef, bool);
+    void ABAudioTimeSignature(ABInstanceRef, int, int);
+    void ABAudioBarStartBeats(ABInstanceRef, float);
+    void ABAudioLoop(ABInstanceRef, bool, float, float);
     void ABAudioControl(ABInstanceRef, int, float);
+    int ABAudioGetLatency(ABInstanceRef);
     float *ABAudioRenderAudio(ABInstanceRef);
 
     void ABUiSetGraphicsFunctions(ABInstanceRef, ABGraphicsFunctions);
@@ -150,6 +150,16 @@ pub unsafe extern "C" fn ABAudioPitchWheel(cr: *mut CreateResult, value: f32) {
     });
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn ABAudioChannelPressure(cr: *mut CreateResult, value: f32) {
+    with_ok(cr, |instance| {
+        instance
+            .audio_engine
+            .borrow_mut()
+            .set_channel_pressure(value)
+    });
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn ABAudioControl(cr: *mut CreateResult, index: i32, value: f32) {
     with_ok(cr, |instance| {
@@ -181,6 +191,68 @@ pub unsafe extern "C" fn ABAudioElapsedBeats(cr: *mut CreateResult, beats: f32)
     });
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn ABAudioPlaying(cr: *mut CreateResult, playing: bool) {
+    with_ok(cr, |instance| {
+        instance.audio_engine.borrow_mut().set_playing(playing)
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ABAudioTimeSignature(
+    cr: *mut CreateResult,
+    numerator: i32,
+    denominator: i32,
+) {
+    // Some hosts report nonsense while the transport is stopped. Keep the last valid signature
+    // instead of letting the engine's assertion panic across the FFI boundary.
+    let valid = 1..=u8::MAX as i32;
+    if !valid.contains(&numerator) || !valid.contains(&denominator) {
+        return;
+    }
+    with_ok(cr, |instance| {
+        instance
+            .audio_engine
+            .borrow_mut()
+            .set_time_signature(numerator as u8, denominator as u8)
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ABAudioBarStartBeats(cr: *mut CreateResult, beats: f32) {
+    with_ok(cr, |instance| {
+        instance
+            .audio_engine
+            .borrow_mut()
+            .set_bar_start_beats(beats)
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ABAudioLoop(
+    cr: *mut CreateResult,
+    looping: bool,
+    start_beats: f32,
+    end_beats: f32,
+) {
+    with_ok(cr, |instance| {
+        let loop_beats = if looping {
+            Some((start_beats, end_beats))
+        } else {
+            None
+        };
+        instance
+            .audio_engine
+            .borrow_mut()
+            .set_loop_beats(loop_beats)
+    });
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ABAudioGetLatency(cr: *mut CreateResult) -> i32 {
+    with_ok(cr, |instance| instance.audio_engine.borrow().get_latency()).unwrap_or_default() as i32
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn ABAudioRenderAudio(cr: *mut CreateResult) -> *const f32 {
     with_ok(cr, |instance| instance.audio_render_audio().as_ptr()).unwrap_or(std::ptr::null())
@@ -10,7 +10,8 @@ if isinteractive()
     @eval Main Parameters = Main.Registry.Factory.Lib.TestParameters
 end
 
-using Main.Parameters
+# Each set of parameters gets its own copy of the registry, in a module next to Registry.
+using ....Parameters
 
 struct FixedArray{T,D,ND,NI} <: AbstractArray{T,ND}
     data::Array{T,ND}
@@ -192,20 +193,29 @@ const sine_waveform = (phase::Float32, _buffer_pos::Integer) -> sin(phase * pi *
 struct GlobalInput
     midi_controls::Vector{Float32}
     pitch_wheel::Float32
+    channel_pressure::Float32
     bpm::Float32
     elapsed_time::Float32
     elapsed_beats::Float32
+    playing::Bool
+    # True for the first buffer after the host starts playing.
+    transport_started::Bool
+    time_signature_numerator::Int32
+    time_signature_denominator::Int32
+    bar_start_beats::Float32
+    looping::Bool
+    loop_start_beats::Float32
+    loop_end_beats::Float32
 end
 
-struct NoteInput
-    pitch::Float32
-    velocity::Float32
-    elapsed_time::Float32
-    elapsed_beats::Float32
-    start_trigger::Bool
-    release_trigger::Bool
+# How many beats (quarter notes) fit in one bar.
+function beats_per_bar(global_in::GlobalInput)::Float32
+    global_in.time_signature_numerator * 4f0 / global_in.time_signature_denominator
 end
 
+# Defined by the engine so that every copy of the registry uses the same type.
+const NoteInput = Main.EngineTypes.NoteInput
+
 mutable struct NoteOutput
     audio::StereoAudio
 end
@@ -228,6 +238,11 @@ function timing_mode_source_is_note(mode::Integer)::Bool
     mode & 0b1 == 0b0
 end
 
+# Bar timing is measured from the start of the current bar, so it is always a global source too.
+function timing_mode_source_is_bar(mode::Integer)::Bool
+    mode & 0b100 == 0b100
+end
+
 function timing_mode_unit_is_beats(mode::Integer)::Bool
     mode & 0b10 == 0b10
 end
@@ -239,23 +254,40 @@ end
 # Timing modes:
 # Bit 1 controls note (false) vs song (true)
 # Bit 2 controls seconds (false) vs beats (true)
+# Bit 3 makes song time restart at the beginning of every bar
 function get_timing(context::NoteContext, mode::Integer)::ControlSignal
     result = similar(ControlSignal)
     global_source::Bool = timing_mode_source_is_global(mode)
+    bar_source::Bool = global_source && timing_mode_source_is_bar(mode)
     beat_units::Bool = timing_mode_unit_is_beats(mode)
-    value::Float32 = if global_source 
-        if beat_units context.global_in.elapsed_beats else context.global_in.elapsed_time end
+    global_in = context.global_in
+    seconds_per_beat = 60f0 / global_in.bpm
+    value::Float32 = if bar_source
+        beats_into_bar = global_in.elapsed_beats - global_in.bar_start_beats
+        if beat_units beats_into_bar else beats_into_bar * seconds_per_beat end
+    elseif global_source 
+        if beat_units global_in.elapsed_beats else global_in.elapsed_time end
     else 
         if beat_units context.note_in.elapsed_beats else context.note_in.elapsed_time end
     end
-    per_sample::Float32 = if beat_units
-        context.global_in.bpm / 60f0 / sample_rate
+    per_sample::Float32 = if global_source && !global_in.playing
+        0f0
+    elseif beat_units
+        global_in.bpm / 60f0 / sample_rate
     else
         1f0 / sample_rate
     end
+    bar_length::Float32 = if beat_units
+        beats_per_bar(global_in)
+    else
+        beats_per_bar(global_in) * seconds_per_beat
+    end
     for i in 1:buffer_length
         result[1, i] = value
         value += per_sample
+        if bar_source && value >= bar_length
+            value -= bar_length
+        end
     end
     result
 end
@@ -354,17 +386,19 @@ function make_pitch_view_data(pitch)::Vector{Float32}
     assert_is_control_signal_type(typeof(pitch))
     [pitch[1, 1]]
 end
+# The whole buffer is sent so that the GUI can show its spectrum as well as its waveform.
 function make_audio_view_data(audio)::Vector{Float32}
     assert_is_audio_type(typeof(audio))
     result = Vector{Float32}(undef, 0)
     for sample in a2cs(audio)
         push!(result, sample)
-        if length(result) == 64
-            break
-        end
     end
     result
 end
+function make_control_view_data(signal)::Vector{Float32}
+    assert_is_control_signal_type(typeof(signal))
+    [last(signal)]
+end
 function make_waveform_view_data(waveform::Function)::Vector{Float32}
     assert_is_waveform(waveform)
     result = Vector{Float32}(undef, 64)
@@ -390,9 +424,38 @@ end
 # Other stuff
 lerp(from, to, amount) = to * amount + from * (1 - amount)
 
+# Moves current towards target with a one-pole filter, getting ~63% of the way there after
+# smoothing_time seconds. Useful for hiding the steps in 7-bit MIDI data.
+function smooth_towards(current::Float32, target::Float32, smoothing_time::Float32)::Float32
+    if smoothing_time <= 0f0
+        target
+    else
+        lerp(target, current, exp(-1f0 / (smoothing_time * sample_rate)))
+    end
+end
+
+# Bends how far along a segment of a Curve control something is. Same as shape_curve_segment in
+# the Rust code.
+function shape_curve_segment(amount::Float32, curvature::Float32)::Float32
+    amount^exp(-curvature * 3f0)
+end
+
+# Returns the value of a Curve control at the specified position between 0 and 1.
+function curve_value(curve, x::Float32)::Float32
+    x = clamp(x, first(curve.x), last(curve.x))
+    start = searchsortedlast(curve.x, x)
+    if start >= length(curve.x)
+        return last(curve.y)
+    end
+    width = curve.x[start + 1] - curve.x[start]
+    amount = width > 0f0 ? (x - curve.x[start]) / width : 1f0
+    amount = shape_curve_segment(amount, curve.curvature[start])
+    lerp(curve.y[start], curve.y[start + 1], amount)
+end
+
 # export all
 # https://discourse.julialang.org/t/exportall/4970/16
-for m in (@__MODULE__, Main.Parameters)
+for m in (@__MODULE__, Parameters)
     for n in names(m; all=true)
         if Base.isidentifier(n) && n ∉ (Symbol(@__MODULE__), :eval, :include)
             @eval export $n
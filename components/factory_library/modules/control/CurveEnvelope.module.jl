@@ -0,0 +1,24 @@
+mutable struct StaticData
+    start::Float32
+end
+
+function static_init()
+    return StaticData(-1000f0)
+end
+
+function exec()
+    signal = similar(ControlSignal)
+    timing = get_timing(context, timing_mode)
+
+    for s in sample_indices(ControlSignal)
+        if reset_trigger[1, s]
+            static.start = timing[1, s]
+        end
+        progress = (timing[1, s] - static.start) / duration[1, s]
+        signal[1, s] = curve_value(shape, progress) * 2f0 - 1f0
+    end
+
+    if do_feedback
+        push!(cursor_feedback, clamp((timing[1, 1] - static.start) / duration[1, 1], 0f0, 1f0))
+    end
+end
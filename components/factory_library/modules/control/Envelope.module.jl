@@ -9,10 +9,10 @@ function static_init()
 end
 
 function exec()
-    signal = similar(MonoAudio)
+    signal = similar(ControlSignal)
     timing = get_timing(context, timing_mode)
 
-    for s in sample_indices(MonoAudio)
+    for s in sample_indices(ControlSignal)
         if !static.releasing
             if reset_trigger[1, s]
                 static.start = timing[1, s]
@@ -48,25 +48,27 @@ function exec()
         signal[1, s] = value * 2f0 - 1f0
     end
 
-    if do_feedback
-        now_time = timing[1, 1] - static.start
-        if static.releasing
-            now_time = now_time + attack_time[1, 1] + decay_time[1, 1]
-            if now_time > attack_time[1, 1] + decay_time[1, 1] + release_time[1, 1]
-                now_time = attack_time[1, 1] + decay_time[1, 1] + release_time[1, 1]
-            end
-        elseif now_time > attack_time[1, 1] + decay_time[1, 1]
-            now_time = attack_time[1, 1] + decay_time[1, 1]
-        end
-        multiplier = 1f0
-        if timing_mode_unit_is_beats(timing_mode)
-            multiplier = 60.0 / context.global_in.bpm
+    # The position of this voice is shown in the graph even when another voice provides the rest
+    # of the feedback data.
+    now_time = timing[1, 1] - static.start
+    if static.releasing
+        now_time = now_time + attack_time[1, 1] + decay_time[1, 1]
+        if now_time > attack_time[1, 1] + decay_time[1, 1] + release_time[1, 1]
+            now_time = attack_time[1, 1] + decay_time[1, 1] + release_time[1, 1]
         end
+    elseif now_time > attack_time[1, 1] + decay_time[1, 1]
+        now_time = attack_time[1, 1] + decay_time[1, 1]
+    end
+    multiplier = 1f0
+    if timing_mode_unit_is_beats(timing_mode)
+        multiplier = 60.0 / context.global_in.bpm
+    end
+    push!(graph_feedback, now_time * multiplier)
+    push!(graph_feedback, first(signal))
+    if do_feedback
         push!(graph_feedback, first(attack_time) * multiplier)
         push!(graph_feedback, first(decay_time) * multiplier)
         push!(graph_feedback, first(sustain))
         push!(graph_feedback, first(release_time) * multiplier)
-        push!(graph_feedback, now_time * multiplier)
-        push!(graph_feedback, first(signal))
     end
 end
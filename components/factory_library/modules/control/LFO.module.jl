@@ -10,20 +10,19 @@ function apply_strength(value::Float32, strength::Float32, mode::Integer)
 end
 
 function exec()
-    audio = similar(MonoAudio)
+    audio = similar(ControlSignal)
     timing = get_timing(context, timing_mode)
 
-    for s in sample_indices(MonoAudio)
+    for s in sample_indices(ControlSignal)
         phase = (timing[1, s] / cycle_time[1, s] + offset[1, s] + 1f0) % 1f0
         sample = apply_strength(waveform(phase, s), strength[1, s], strength_mode)
         audio[1, s] = sample
     end
 
+    push!(graph_feedback, (last(timing) / last(cycle_time) + 2f0) % 1f0)
+    push!(graph_feedback, last(audio))
     if do_feedback
         offset = last(offset)
-        phase = last(timing) / last(cycle_time)
-        push!(graph_feedback, (phase + 2f0) % 1f0)
-        push!(graph_feedback, last(audio))
         for s in 1:default_graph_resolution
             phase = ((s - 1) / Float32(default_graph_resolution - 1) + offset) % 1f0
             sample = apply_strength(waveform(phase, s), strength[1, s], strength_mode)
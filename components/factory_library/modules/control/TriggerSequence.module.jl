@@ -12,8 +12,14 @@ function exec()
     num_steps = length(sequence)
     timing = get_timing(context, timing_mode)
 
+    # Sequences timed against the song start over whenever the host starts playing.
+    if timing_mode_source_is_global(timing_mode) && context.global_in.transport_started
+        static.base_time = if timing_mode_source_is_bar(timing_mode) 0f0 else first(timing) end
+        static.last_step = -1
+    end
+
     if do_feedback
-        value = (first(timing) - static.base_time) / first(step_time) % Float32(num_steps)
+        value = mod((first(timing) - static.base_time) / first(step_time), Float32(num_steps))
         push!(playhead_feedback, value)
     end
 
@@ -21,8 +27,9 @@ function exec()
         if reset[1, s]
             static.base_time = timing[1, s]
         end
+        # mod instead of % because bar timing jumps backwards at the start of every bar.
         current_step = 
-            floor(Int32, (timing[1, s] - static.base_time) / first(step_time)) % num_steps
+            mod(floor(Int32, (timing[1, s] - static.base_time) / first(step_time)), num_steps)
         if static.last_step != current_step
             static.last_step = current_step
             out_trigger[1, s] = sequence[current_step + 1] # grumble grumble
@@ -7,12 +7,17 @@ function static_init()
 end
 
 function exec()
-    out_value = similar(MonoAudio)
+    out_value = similar(ControlSignal)
     num_steps = Int32(length(sequence))
     timing = get_timing(context, timing_mode)
 
+    # Sequences timed against the song start over whenever the host starts playing.
+    if timing_mode_source_is_global(timing_mode) && context.global_in.transport_started
+        static.base_time = if timing_mode_source_is_bar(timing_mode) 0f0 else first(timing) end
+    end
+
     if do_feedback
-        value = (first(timing) - static.base_time) / first(step_time) % Float32(num_steps)
+        value = mod((first(timing) - static.base_time) / first(step_time), Float32(num_steps))
         push!(playhead_feedback, value)
         push!(playhead_feedback, first(ramping))
     end
@@ -21,7 +26,8 @@ function exec()
         if reset[1, s]
             static.base_time = timing[1, s]
         end
-        sequence_time = (timing[1, s] - static.base_time) / first(step_time) % Float32(num_steps)
+        # mod instead of % because bar timing jumps backwards at the start of every bar.
+        sequence_time = mod((timing[1, s] - static.base_time) / first(step_time), Float32(num_steps))
         step_index = floor(Int32, sequence_time)
         step_progress = sequence_time % 1f0
         ramp_start = 1f0 - ramping[1, s]
@@ -0,0 +1,22 @@
+mutable struct StaticData
+    value::Float32
+    initialized::Bool
+end
+
+function static_init()
+    StaticData(0f0, false)
+end
+
+function exec()
+    signal = similar(ControlSignal)
+    target = context.global_in.channel_pressure
+    if !static.initialized
+        static.value = target
+        static.initialized = true
+    end
+
+    for s in sample_indices(ControlSignal)
+        static.value = smooth_towards(static.value, target, smoothing[1, s])
+        signal[1, s] = static.value
+    end
+end
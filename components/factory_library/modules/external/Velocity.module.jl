@@ -0,0 +1,4 @@
+function exec()
+    signal = similar(ControlSignal)
+    signal .= context.note_in.velocity
+end
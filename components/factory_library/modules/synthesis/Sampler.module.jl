@@ -0,0 +1,50 @@
+mutable struct StaticData
+    # Measured in samples from the start of the audio, starting at zero.
+    position::Float32
+    playing::Bool
+end
+
+function static_init()
+    StaticData(0f0, false)
+end
+
+function exec()
+    audio = similar(MonoAudio)
+    data = sample.audio
+    looping = loop_mode == 1 && sample.loop_end > sample.loop_start
+
+    if do_feedback
+        playhead = if static.playing && length(data) > 0
+            static.position / Float32(length(data))
+        else
+            -1f0
+        end
+        push!(playhead_feedback, playhead)
+    end
+
+    for s in sample_indices(MonoAudio)
+        if trigger[1, s]
+            static.position = Float32(sample.start)
+            static.playing = true
+        end
+        if looping && static.position >= sample.loop_end
+            static.position -= Float32(sample.loop_end - sample.loop_start)
+        end
+        if static.position >= sample.stop
+            static.playing = false
+        end
+
+        value = 0f0
+        if static.playing
+            index = floor(Int32, static.position)
+            next_index = index + Int32(1)
+            if looping && next_index >= sample.loop_end
+                next_index = sample.loop_start
+            end
+            next_value = if next_index < sample.stop data[next_index + 1] else 0f0 end
+            value = lerp(data[index + 1], next_value, static.position - Float32(index))
+            static.position += pitch[1, s] / first(root)
+        end
+        audio[1, s] = value * amplitude[1, s]
+    end
+end
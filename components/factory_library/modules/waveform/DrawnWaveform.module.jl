@@ -0,0 +1,11 @@
+function exec()
+    table_length = length(shape)
+    waveform = function (phase::Float32, _buffer_pos::Integer)
+        position = phase * Float32(table_length)
+        index = floor(Int32, position)
+        amount = position - Float32(index)
+        index = index % table_length
+        next_index = (index + 1) % table_length
+        lerp(shape[index + 1], shape[next_index + 1], amount)
+    end
+end
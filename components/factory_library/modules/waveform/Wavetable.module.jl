@@ -0,0 +1,26 @@
+# Same as WAVETABLE_FRAME_LENGTH in the Rust code.
+const FRAME_LENGTH = 2048
+
+function read_frame(frames::Vector{Float32}, frame::Integer, phase::Float32)::Float32
+    position = phase * Float32(FRAME_LENGTH)
+    index = floor(Int32, position)
+    amount = position - Float32(index)
+    index = index % Int32(FRAME_LENGTH)
+    next_index = (index + Int32(1)) % Int32(FRAME_LENGTH)
+    offset = frame * FRAME_LENGTH + 1
+    lerp(frames[offset + index], frames[offset + next_index], amount)
+end
+
+function exec()
+    last_frame = Int32(table.num_frames - 1)
+    waveform = function (phase::Float32, buffer_pos::Integer)
+        frame_position = clamp(position[1, buffer_pos], 0f0, 1f0) * Float32(last_frame)
+        frame = min(floor(Int32, frame_position), last_frame)
+        next_frame = min(frame + Int32(1), last_frame)
+        lerp(
+            read_frame(table.frames, frame, phase),
+            read_frame(table.frames, next_frame, phase),
+            frame_position - Float32(frame),
+        )
+    end
+end
@@ -85,6 +85,7 @@ void AudiobenchAudioProcessor::prepareToPlay(double sampleRate,
     // Use this method as the place to do any pre-playback
     // initialisation that you need..
     ABAudioSetGlobalParameters(ab, samplesPerBlock, (int) sampleRate);
+    setLatencySamples(ABAudioGetLatency(ab));
 }
 
 void AudiobenchAudioProcessor::releaseResources() {
@@ -122,6 +123,24 @@ void AudiobenchAudioProcessor::processBlock(AudioBuffer<float>& buffer,
     auto totalNumInputChannels = getTotalNumInputChannels();
     auto totalNumOutputChannels = getTotalNumOutputChannels();
 
+    // Standalone builds have no play head, in which case the engine keeps its
+    // own clock running.
+    AudioPlayHead::CurrentPositionInfo position;
+    auto playHead = getPlayHead();
+    if (playHead != nullptr && playHead->getCurrentPosition(position)) {
+        ABAudioBpm(ab, (float) position.bpm);
+        ABAudioElapsedTime(ab, (float) position.timeInSeconds);
+        ABAudioElapsedBeats(ab, (float) position.ppqPosition);
+        if (position.timeSigNumerator > 0 && position.timeSigDenominator > 0) {
+            ABAudioTimeSignature(ab, position.timeSigNumerator,
+                                 position.timeSigDenominator);
+        }
+        ABAudioBarStartBeats(ab, (float) position.ppqPositionOfLastBarStart);
+        ABAudioLoop(ab, position.isLooping, (float) position.ppqLoopStart,
+                    (float) position.ppqLoopEnd);
+        ABAudioPlaying(ab, position.isPlaying);
+    }
+
     // Doing two seperate loops prevents the problem where a note is turned on
     // and off in the same buffer, but the on is processed after the off so the
     // note just stays on forever.
@@ -134,6 +153,9 @@ void AudiobenchAudioProcessor::processBlock(AudioBuffer<float>& buffer,
             float value = (message.getPitchWheelValue() - 0x2000 + 0.5f) /
                           (0x2000 - 0.5f);
             ABAudioPitchWheel(ab, value);
+        } else if (message.isChannelPressure()) {
+            float value = message.getChannelPressureValue() / 127.0f;
+            ABAudioChannelPressure(ab, value);
         } else if (message.isController()) {
             float value =
                 (message.getControllerValue() - 0x40 + 0.5f) / (0x40 - 0.5f);
@@ -182,6 +204,11 @@ void AudiobenchAudioProcessor::processBlock(AudioBuffer<float>& buffer,
         buffer.clear(i, 0, buffer.getNumSamples());
 
     float* audioBuffer = ABAudioRenderAudio(ab);
+    // Lookahead mode can be toggled from the GUI at any time.
+    int latency = ABAudioGetLatency(ab);
+    if (latency != getLatencySamples()) {
+        setLatencySamples(latency);
+    }
 
     // This is the place where you'd normally do the guts of your plugin's
     // audio processing...
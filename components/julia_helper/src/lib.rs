@@ -1,7 +1,11 @@
 pub use jlrs::prelude::*;
+pub use system_image::*;
 
 use scones::make_constructor;
 use shared_util::{Clip, Position};
+use std::path::{Path, PathBuf};
+
+mod system_image;
 
 const JULIA_DELIMS: &'static [(&'static str, &'static str)] = &[
     ("function", "end"),
@@ -123,6 +127,23 @@ impl GeneratedCode {
         self.append(&snippet.data.as_str(), snippet.get_position());
     }
 
+    /// Appends another piece of generated code, keeping track of where its pieces came from.
+    pub fn append_code(&mut self, other: &GeneratedCode) {
+        let start = self.current_end;
+        let offset = |position: Position| {
+            if position.line == 1 {
+                Position::new(start.line, start.column + position.column - 1)
+            } else {
+                Position::new(start.line + position.line - 1, position.column)
+            }
+        };
+        for (position, from) in &other.code_map {
+            self.code_map.push((offset(*position), from.clone()));
+        }
+        self.current_end = offset(other.current_end);
+        self.catted_source.push_str(&other.catted_source);
+    }
+
     pub fn as_str(&self) -> &str {
         &self.catted_source
     }
@@ -164,9 +185,11 @@ function __error_format_helper__(fn_to_run, arguments...)
     end
 end
 
-function __load_code_helper__(code, filename)
+__julia_bin_dir__() = Sys.BINDIR
+
+function __load_code_helper__(code, filename, into = Main)
     try
-        Main.include_string(Main, code, filename)
+        Main.include_string(into, code, filename)
     catch error
         bt = catch_backtrace()
         throw(sprint(showerror, error, bt))
@@ -203,9 +226,57 @@ impl ExecutionEngine {
         this
     }
 
+    /// Starts Julia with an image made by `build_system_image`. `preloaded_code` must be the same
+    /// code the image was built with, it is not executed again but is needed to make errors
+    /// readable.
+    pub fn with_system_image(
+        julia_bin_dir: &Path,
+        image_path: &Path,
+        preloaded_code: Vec<GeneratedCode>,
+    ) -> Result<Self, String> {
+        let julia = unsafe { Julia::init_with_image(julia_bin_dir, image_path, STACK_SIZE) }
+            .map_err(|err| {
+                format!(
+                    "ERROR: Failed to start Julia with system image {}, caused by:\nERROR: {:?}",
+                    image_path.to_string_lossy(),
+                    err
+                )
+            })?;
+        let mut global_code_segments = Vec::with_capacity(preloaded_code.len() + 1);
+        global_code_segments.push(GeneratedCode::from_unique_source(
+            "__execution_engine__",
+            EE_ENV,
+        ));
+        global_code_segments.extend(preloaded_code.into_iter());
+        Ok(Self {
+            julia,
+            global_code_segments,
+        })
+    }
+
+    /// Returns the directory containing the Julia executable, which is needed to build and load
+    /// system images.
+    pub fn julia_bin_dir(&mut self) -> Result<PathBuf, String> {
+        self.call_fn(
+            &["Main", "__julia_bin_dir__"],
+            |_, _| Ok(()),
+            |_, value| Ok(PathBuf::from(value.cast::<JuliaString>()?.as_str()?)),
+        )
+    }
+
     /// Executes the specified code clip in the global scope such that it will affect the
     /// execution of all Julia code executed after this call.
     pub fn add_global_code(&mut self, code: GeneratedCode) -> Result<(), String> {
+        self.load_code(None, code)
+    }
+
+    /// Like add_global_code, but evaluates the code inside a module which was previously defined
+    /// in the global scope.
+    pub fn add_code_to_module(&mut self, module: &str, code: GeneratedCode) -> Result<(), String> {
+        self.load_code(Some(module), code)
+    }
+
+    fn load_code(&mut self, into: Option<&str>, code: GeneratedCode) -> Result<(), String> {
         let tracker_filename = format!("__global_code_{}__.jl", self.global_code_segments.len());
         let res = self
             .julia
@@ -214,11 +285,20 @@ impl ExecutionEngine {
                 let tracker_filename = Value::new(frame, tracker_filename).unwrap();
                 let main_module = Module::main(global);
                 let include_helper = main_module.function("__load_code_helper__").unwrap();
-                Ok(include_helper
-                    .call2(frame, code_str, tracker_filename)
-                    .unwrap())
+                let into = match into {
+                    Some(name) => match main_module.submodule(name) {
+                        Ok(module) => module,
+                        Err(..) => {
+                            return Ok(Err(format!("ERROR: There is no module named {}.", name)))
+                        }
+                    },
+                    None => main_module,
+                };
+                Ok(Ok(include_helper
+                    .call3(frame, code_str, tracker_filename, into.as_value())
+                    .unwrap()))
             })
-            .unwrap();
+            .unwrap()?;
         self.global_code_segments.push(code);
         match res {
             Ok(..) => Ok(()),
@@ -356,6 +436,19 @@ Scope End"#
         assert_eq!(code.as_str(), "\n    Here is a scope where things go\n");
     }
 
+    #[test]
+    fn append_code() {
+        let mut code = GeneratedCode::from_unique_source("first.jl", "one\ntwo");
+        let mut other = GeneratedCode::from_unique_source("second.jl", "three\n");
+        other.append("four", "third.jl");
+        code.append_code(&other);
+        assert_eq!(code.as_str(), "one\ntwothree\nfour");
+        assert_eq!(code.code_map[1].0, Position::new(2, 4));
+        assert_eq!(code.code_map[1].1.filename, "second.jl");
+        assert_eq!(code.code_map[2].0, Position::new(3, 1));
+        assert_eq!(code.current_end, Position::new(3, 5));
+    }
+
     const TEST_CODE: &'static str = r#"
         begin
             increment(x) = x + one(x)
@@ -0,0 +1,135 @@
+use crate::{GeneratedCode, EE_ENV};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+#[cfg(target_os = "windows")]
+pub const SYSTEM_IMAGE_EXTENSION: &'static str = "dll";
+#[cfg(target_os = "macos")]
+pub const SYSTEM_IMAGE_EXTENSION: &'static str = "dylib";
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub const SYSTEM_IMAGE_EXTENSION: &'static str = "so";
+
+fn julia_executable(julia_bin_dir: &Path) -> PathBuf {
+    if cfg!(target_os = "windows") {
+        julia_bin_dir.join("julia.exe")
+    } else {
+        julia_bin_dir.join("julia")
+    }
+}
+
+fn run(command: &mut Command, description: &str) -> Result<(), String> {
+    let output = command.output().map_err(|err| {
+        format!(
+            "ERROR: Failed to start {}, caused by:\nERROR: {}",
+            description, err
+        )
+    })?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "ERROR: {} failed, caused by:\n{}",
+            description,
+            String::from_utf8_lossy(&output.stderr)
+        ))
+    }
+}
+
+/// Julia string literal containing the given path.
+fn julia_path_literal(path: &Path) -> String {
+    format!("{:?}", path.to_string_lossy()).replace('$', "\\$")
+}
+
+/// Builds a system image containing everything `ExecutionEngine::new` sets up followed by the
+/// provided global code, in that order. This runs a separate Julia process and needs a C compiler
+/// (`cc` or whatever the `CC` environment variable specifies) to link the image, so it can take a
+/// few minutes. Load the result with `ExecutionEngine::with_system_image`.
+pub fn build_system_image(
+    julia_bin_dir: &Path,
+    image_path: &Path,
+    global_code: &[GeneratedCode],
+) -> Result<(), String> {
+    let work_dir = image_path.with_extension("build");
+    fs::create_dir_all(&work_dir).map_err(|err| {
+        format!(
+            "ERROR: Failed to create {}, caused by:\nERROR: {}",
+            work_dir.to_string_lossy(),
+            err
+        )
+    })?;
+    let result = build_in_dir(julia_bin_dir, image_path, global_code, &work_dir);
+    let _ = fs::remove_dir_all(&work_dir);
+    result
+}
+
+fn build_in_dir(
+    julia_bin_dir: &Path,
+    image_path: &Path,
+    global_code: &[GeneratedCode],
+    work_dir: &Path,
+) -> Result<(), String> {
+    let env_code = GeneratedCode::from_unique_source("__execution_engine__", EE_ENV);
+    let mut script = String::new();
+    // The segments are loaded under the same names ExecutionEngine would give them so that errors
+    // from code in the image can still be traced back to the original source.
+    for (index, code) in std::iter::once(&env_code).chain(global_code).enumerate() {
+        let segment_path = work_dir.join(format!("__global_code_{}__.jl", index));
+        fs::write(&segment_path, code.as_str()).map_err(|err| {
+            format!(
+                "ERROR: Failed to write {}, caused by:\nERROR: {}",
+                segment_path.to_string_lossy(),
+                err
+            )
+        })?;
+        script.push_str(&format!(
+            "include_string(Main, read({}, String), \"__global_code_{}__.jl\")\n",
+            julia_path_literal(&segment_path),
+            index
+        ));
+    }
+    let script_path = work_dir.join("build_image.jl");
+    fs::write(&script_path, script).map_err(|err| {
+        format!(
+            "ERROR: Failed to write {}, caused by:\nERROR: {}",
+            script_path.to_string_lossy(),
+            err
+        )
+    })?;
+
+    let object_path = work_dir.join("image.o");
+    run(
+        Command::new(julia_executable(julia_bin_dir))
+            .arg("--startup-file=no")
+            .arg("--history-file=no")
+            .arg("--output-o")
+            .arg(&object_path)
+            .arg(&script_path),
+        "Compiling the system image",
+    )?;
+
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    let mut link = Command::new(compiler);
+    link.arg("-shared").arg("-o").arg(image_path);
+    if cfg!(target_os = "macos") {
+        link.arg("-Wl,-all_load").arg(&object_path);
+    } else {
+        if cfg!(target_os = "windows") {
+            link.arg("-Wl,--export-all-symbols");
+        }
+        link.arg("-Wl,--whole-archive")
+            .arg(&object_path)
+            .arg("-Wl,--no-whole-archive");
+    }
+    if cfg!(target_os = "windows") {
+        link.arg("-L")
+            .arg(julia_bin_dir)
+            .args(&["-ljulia", "-lopenlibm"]);
+    } else {
+        link.arg("-L").arg(julia_bin_dir.join("..").join("lib"));
+        link.arg("-ljulia");
+    }
+    run(&mut link, "Linking the system image")
+}
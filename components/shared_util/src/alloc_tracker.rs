@@ -0,0 +1,107 @@
+//! A global allocator that can detect heap allocations on threads which must not make them, such
+//! as the audio thread. It only does anything if it is installed with `#[global_allocator]`, which
+//! should only be done in debug builds.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+thread_local! {
+    static FORBIDDEN: Cell<bool> = Cell::new(false);
+    static VIOLATIONS: Cell<usize> = Cell::new(0);
+}
+
+fn record_violation() {
+    // try_with because the allocator can be called while thread locals are being torn down.
+    let _ = FORBIDDEN.try_with(|forbidden| {
+        if forbidden.get() {
+            let _ = VIOLATIONS.try_with(|violations| violations.set(violations.get() + 1));
+        }
+    });
+}
+
+/// Forwards everything to the system allocator, counting any allocations or deallocations that
+/// happen inside `assert_no_alloc`.
+pub struct TrackingAllocator;
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        record_violation();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        record_violation();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        record_violation();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        record_violation();
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Returns how many times the heap was touched inside `op`. Always returns zero if
+/// `TrackingAllocator` is not the global allocator.
+pub fn count_allocations<T>(op: impl FnOnce() -> T) -> (T, usize) {
+    let was_forbidden = FORBIDDEN.with(|forbidden| forbidden.replace(true));
+    let before = VIOLATIONS.with(|violations| violations.get());
+    let result = op();
+    let after = VIOLATIONS.with(|violations| violations.get());
+    FORBIDDEN.with(|forbidden| forbidden.set(was_forbidden));
+    (result, after - before)
+}
+
+/// Panics if `op` allocates or frees any heap memory. This check is only performed in debug builds
+/// where `TrackingAllocator` is installed, in release builds this just calls `op`.
+pub fn assert_no_alloc<T>(op: impl FnOnce() -> T) -> T {
+    if cfg!(debug_assertions) {
+        let (result, count) = count_allocations(op);
+        // Panicking allocates, so this has to happen after tracking has stopped.
+        assert!(
+            count == 0,
+            "{} heap allocations or deallocations happened in real-time code.",
+            count
+        );
+        result
+    } else {
+        op()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[global_allocator]
+    static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+    #[test]
+    fn counts_allocations() {
+        let (_, count) = count_allocations(|| drop(vec![0u8; 16]));
+        assert_eq!(count, 2);
+        let mut buffer = vec![0.0f32; 16];
+        let (_, count) = count_allocations(|| buffer.iter_mut().for_each(|x| *x = 1.0));
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn nested_tracking() {
+        let (inner, outer) = count_allocations(|| count_allocations(|| Box::new(1)).1);
+        assert_eq!(inner, 1);
+        // The outer count includes the inner allocation as well as freeing the box.
+        assert_eq!(outer, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn assert_panics_on_alloc() {
+        assert_no_alloc(|| Box::new(1));
+    }
+}
@@ -242,6 +242,7 @@ impl Parse for ConstructorDescription {
 enum FeedbackMode {
     ControlSignal,
     ManualValue,
+    ManualValuePerVoice,
 }
 
 impl Parse for FeedbackMode {
@@ -250,6 +251,7 @@ impl Parse for FeedbackMode {
         Ok(match &typ.to_string()[..] {
             "ControlSignal" => Self::ControlSignal,
             "ManualValue" => Self::ManualValue,
+            "ManualValuePerVoice" => Self::ManualValuePerVoice,
             _ => panic!("{} is not a valid feedback mode", typ),
         })
     }
@@ -321,6 +323,17 @@ pub fn make_widget_outline(args: TokenStream) -> TokenStream {
                 }
             }
         }
+        Some(FeedbackMode::ManualValuePerVoice) => {
+            outline_fields.push((
+                format_ident!("feedback_name"),
+                quote! {::std::string::String},
+            ));
+            quote! {
+                crate::gui::module_widgets::FeedbackMode::ManualValuePerVoice {
+                    name: self.feedback_name.clone(),
+                }
+            }
+        }
     };
 
     let outline_field_names: Vec<_> = outline_fields
@@ -332,7 +345,9 @@ pub fn make_widget_outline(args: TokenStream) -> TokenStream {
         .iter()
         .map(|arg| arg.create_from_yaml_code())
         .collect();
-    if let Some(FeedbackMode::ManualValue) = &feedback_mode {
+    if let Some(FeedbackMode::ManualValue) | Some(FeedbackMode::ManualValuePerVoice) =
+        &feedback_mode
+    {
         field_from_yaml_code.push(quote! {
             let feedback_name = yaml.map_entry("feedback_name")?.value()?.to_owned();
         });